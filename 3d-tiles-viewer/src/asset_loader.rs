@@ -5,10 +5,10 @@ use bevy::{
     utils::BoxedFuture,
 };
 use serde::Deserialize;
-use std::ffi::OsStr;
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 use tiles3d::b3dm::B3dm;
-use tiles3d::i3dm::I3dm;
+use tiles3d::content::ContentType;
+use tiles3d::i3dm::{I3dm, I3dmGltf};
 
 #[derive(Debug, Deserialize, TypeUuid)]
 #[uuid = "4c1bd5f9-8131-47ea-ac15-b6cf03b4473a"]
@@ -24,20 +24,15 @@ impl AssetLoader for Tiles3dAssetLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let mut reader = Cursor::new(bytes);
-            match load_context.path().extension().and_then(OsStr::to_str) {
-                Some("b3dm") => {
-                    let _b3dm = B3dm::from_reader(&mut reader).unwrap();
-                }
-                Some("i3dm") => {
-                    let _i3dm = I3dm::from_reader(&mut reader).unwrap();
-                }
-                _ => {
-                    panic!("unexpected extension")
-                }
-            }
-            let mut gltf_buf = Vec::new();
-            reader.read_to_end(&mut gltf_buf)?;
+            let reader = Cursor::new(bytes);
+            let gltf_buf = match ContentType::detect(bytes) {
+                ContentType::B3dm => B3dm::from_reader(reader)?.gltf,
+                ContentType::I3dm => match I3dm::from_reader(reader)?.gltf {
+                    I3dmGltf::Embedded(glb) => glb,
+                    I3dmGltf::Uri(url) => anyhow::bail!("glTF uri {} not supported", url),
+                },
+                content_type => anyhow::bail!("unsupported content type {:?}", content_type),
+            };
             let gltf_loader = GltfLoader::default();
             gltf_loader.load(&gltf_buf, load_context).await?; // calls set_default_asset
             Ok(())
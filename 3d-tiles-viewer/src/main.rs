@@ -2,10 +2,14 @@ mod asset_loader;
 mod viewer;
 
 use argh::FromArgs;
-use std::ffi::OsStr;
-use std::path::Path;
-use tiles3d::{b3dm, i3dm, pnts};
-use viewer::{init_viewer, transform, view_gltf, view_pnts, view_tileset};
+use bevy::prelude::Transform;
+use std::fs;
+use std::process;
+use tiles3d::content::ContentType;
+use tiles3d::loader::{FileSource, TilesetLoader};
+use tiles3d::validate::{validate, ValidateOptions};
+use tiles3d::{b3dm, cmpt, i3dm, pnts};
+use viewer::{init_viewer, view_gltf, view_pnts, view_tileset};
 
 #[derive(FromArgs)]
 /// 3D tiles reader.
@@ -19,6 +23,7 @@ struct App {
 enum Commands {
     View(View),
     Extract(Extract),
+    Validate(Validate),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -39,44 +44,88 @@ struct Extract {
     path: String,
 }
 
+#[derive(FromArgs, PartialEq, Default, Debug)]
+/// Validate tileset and tile contents. Exits with status 1 if errors are found.
+#[argh(subcommand, name = "validate")]
+struct Validate {
+    #[argh(positional)]
+    /// input tileset
+    path: String,
+    #[argh(switch)]
+    /// only validate tileset JSON, without reading tile contents
+    skip_contents: bool,
+    #[argh(switch)]
+    /// pretty-print the JSON report
+    pretty: bool,
+}
+
 fn main() {
     let app: App = argh::from_env();
     match app.command {
         Commands::View(args) => {
-            if Path::new(&args.path).file_name().and_then(OsStr::to_str) == Some("tileset.json") {
+            let content_type = read_content_type(&args.path);
+            if content_type == ContentType::Tileset {
                 view_tileset(&args.path);
             } else {
                 let mut app = bevy::app::App::build();
                 init_viewer(&mut app);
-                match Path::new(&args.path).extension().and_then(OsStr::to_str) {
-                    Some("glb") => {
-                        view_gltf(&mut app, transform(&None), &args.path);
+                match content_type {
+                    ContentType::Glb | ContentType::Gltf => {
+                        view_gltf(&mut app, Transform::identity(), &args.path);
                     }
-                    Some("pnts") => {
-                        view_pnts(&mut app, transform(&None), &args.path);
+                    ContentType::Pnts => {
+                        view_pnts(&mut app, Transform::identity(), &args.path);
                     }
                     _ => {
-                        println!("Unknown file extension");
+                        println!("Unsupported content type {:?}", content_type);
                     }
                 }
                 app.run();
             }
         }
         Commands::Extract(args) => {
-            match Path::new(&args.path).extension().and_then(OsStr::to_str) {
-                Some("b3dm") => {
-                    b3dm::extract_gltf(&args.path).unwrap();
-                }
-                Some("i3dm") => {
-                    i3dm::extract_gltf(&args.path).unwrap();
-                }
-                Some("pnts") => {
-                    pnts::extract(&args.path).unwrap();
-                }
-                _ => {
-                    println!("Unknown file extension");
+            let result = match read_content_type(&args.path) {
+                ContentType::B3dm => b3dm::extract_gltf(&args.path).map(drop),
+                ContentType::I3dm => i3dm::extract_gltf(&args.path).map(drop),
+                ContentType::Pnts => pnts::extract(&args.path).map(drop),
+                ContentType::Cmpt => cmpt::extract(&args.path).map(drop),
+                content_type => {
+                    println!("Unsupported content type {:?}", content_type);
+                    Ok(())
                 }
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                process::exit(1);
             }
         }
+        Commands::Validate(args) => {
+            let loader = TilesetLoader::new(FileSource);
+            let options = ValidateOptions {
+                contents: !args.skip_contents,
+                ..Default::default()
+            };
+            let report = validate(&loader, &args.path, &options);
+            let json = if args.pretty {
+                serde_json::to_string_pretty(&report)
+            } else {
+                serde_json::to_string(&report)
+            };
+            println!("{}", json.expect("serializable report"));
+            if !report.is_valid() {
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Detect the content type of the file at `path`, exiting if it can't be read.
+fn read_content_type(path: &str) -> ContentType {
+    match fs::read(path) {
+        Ok(data) => ContentType::detect(&data),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
     }
 }
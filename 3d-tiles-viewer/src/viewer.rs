@@ -5,20 +5,24 @@ use bevy::render::pipeline::PrimitiveTopology;
 use bevy::{pbr::AmbientLight, prelude::*};
 use bevy_inspector_egui::{Inspectable, InspectableRegistry, WorldInspectorPlugin};
 use bevy_prototype_debug_lines::*;
-use byteorder::{LittleEndian, ReadBytesExt};
 use smooth_bevy_cameras::{
     controllers::orbit::{OrbitCameraBundle, OrbitCameraController, OrbitCameraPlugin},
     LookTransform, LookTransformPlugin,
 };
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{self, BufReader, Read};
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
 use tiles3d::b3dm::B3dm;
-use tiles3d::batch_table::BatchTable;
-use tiles3d::i3dm::I3dm;
+use tiles3d::content::ContentType;
+use tiles3d::i3dm::{I3dm, I3dmGltf};
+use tiles3d::implicit::{ImplicitTree, SubtreeCache};
+use tiles3d::loader::{FileSource, TilesetLoader};
+use tiles3d::matrix::Matrix4;
 use tiles3d::pnts::Pnts;
-use tiles3d::tileset::{BoundingVolume, Tile, Tileset};
+use tiles3d::tileset::Tile;
+
+/// Deepest level of implicit tilesets to show. All tiles are loaded up front, so deeper
+/// levels of large implicit tilesets would exhaust memory.
+const MAX_IMPLICIT_LEVEL: u32 = 5;
 
 pub fn view_tileset(tileset_path: &str) {
     let mut app = App::build();
@@ -27,109 +31,152 @@ pub fn view_tileset(tileset_path: &str) {
     app.run();
 }
 
-fn read_tileset_json(tileset_path: &str) -> Tileset {
-    let file = File::open(tileset_path).expect(&format!("Couldn't open file {}", tileset_path));
-    let tileset = Tileset::from_reader(BufReader::new(file)).expect("Invalid Tileset JSON");
-    debug!("{:?}", &tileset);
-    tileset
-}
-
 fn view_tileset_content(app: &mut AppBuilder, tileset_path: &str) {
-    let tileset = read_tileset_json(tileset_path);
-    let mut tile = &tileset.root;
-    if tile.content.is_some() {
-        view_tile(app, tileset_path, &tile, &tileset.root.bounding_volume);
-    }
-    while tile.content.is_none() {
-        if let Some(ref children) = tile.children {
-            for child in children {
-                tile = child;
-                if tile.content.is_some() {
-                    view_tile(app, tileset_path, &tile, &tileset.root.bounding_volume);
+    let loader = TilesetLoader::new(FileSource);
+    let loaded = loader
+        .load_eager(tileset_path)
+        .expect("Invalid Tileset JSON");
+    debug!("{:?}", &loaded.tileset);
+    let root = &loaded.tileset.root;
+    let origin = tileset_origin(root);
+    view_bounding_volume(app, root, origin);
+    for node in root.depth_first() {
+        if let Ok(tree) = ImplicitTree::new(node.tile) {
+            // Content URIs of implicit tiles are templates
+            let mut subtrees = SubtreeCache::new(&loader, tree);
+            let mut implicit_root = tree
+                .expand(&mut subtrees, Some(MAX_IMPLICIT_LEVEL))
+                .expect("Invalid implicit tiling");
+            // Already contained in the transform of `node`
+            implicit_root.transform = None;
+            for implicit_node in implicit_root.depth_first() {
+                let tile_transform = node.transform * implicit_node.transform;
+                for content in implicit_node.tile.all_contents() {
+                    view_tile(app, &content.uri, &tile_transform, origin);
                 }
             }
+            continue;
+        }
+        for content in node.tile.all_contents() {
+            view_tile(app, &content.uri, &node.transform, origin);
         }
     }
 }
 
-/// File path for tile in tileset
-fn tile_fn(tileset_path: &str, tile_uri: &str) -> String {
-    let mut tile_path = Path::new(&tileset_path).parent().unwrap().to_path_buf();
-    tile_path.push(&tile_uri);
-    let tile_fn = tile_path.into_os_string();
-    let tile_fn = tile_fn.to_str().expect("Invalid file name");
-    tile_fn.to_string()
+/// Center of the root tile in world coordinates.
+///
+/// Tiles are rendered relative to this origin, since `f32` world coordinates of content placed
+/// on the globe are only accurate to about half a meter.
+fn tileset_origin(root: &Tile) -> [f64; 3] {
+    root.bounding_volume
+        .volume
+        .transform(&root.local_transform())
+        .to_sphere()
+        .center
 }
 
-fn view_tile(app: &mut AppBuilder, tileset_path: &str, tile: &Tile, root_volume: &BoundingVolume) {
-    let tile_uri = &tile.content.as_ref().expect("Tile content missing").uri;
-    let tile_fn = tile_fn(tileset_path, &tile_uri);
+/// Add the root bounding volume, which is also used for placing the camera.
+fn view_bounding_volume(app: &mut AppBuilder, root: &Tile, origin: [f64; 3]) {
+    let mut volume = root
+        .bounding_volume
+        .volume
+        .transform(&root.local_transform())
+        .to_box();
+    for (c, o) in volume.center.iter_mut().zip(origin.iter()) {
+        *c -= o;
+    }
+    let elements: [f64; 12] = volume.into();
+    app.world_mut().spawn().insert(BoundingVolumeBox {
+        elements: elements.iter().map(|n| *n as f32).collect(),
+        transform: Transform::identity(),
+    });
+}
+
+fn view_tile(app: &mut AppBuilder, tile_fn: &str, tile_transform: &Matrix4, origin: [f64; 3]) {
     debug!("view_tile {}", &tile_fn);
-    let file = File::open(&tile_fn).expect(&format!("Couldn't open file {}", &tile_fn));
-    let mut reader = BufReader::new(file);
-
-    let transform = transform(&tile.transform);
-    if let Some(ref bounding_volume_box) = root_volume.bounding_volume_box {
-        app.world_mut().spawn().insert(BoundingVolumeBox {
-            elements: bounding_volume_box.iter().map(|n| *n as f32).collect(),
-            transform: transform.clone(),
-        });
+    let data = fs::read(&tile_fn).expect(&format!("Couldn't open file {}", &tile_fn));
+    let content_type = ContentType::detect(&data);
+    if content_type == ContentType::Tileset {
+        // External tilesets are grafted into the tileset
+        return;
     }
-    match Path::new(&tile_uri).extension().and_then(OsStr::to_str) {
-        Some("b3dm") => {
-            let b3dm = B3dm::from_reader(&mut reader).expect("Invalid b3dm");
+
+    match content_type {
+        ContentType::B3dm => {
+            let b3dm = B3dm::from_reader(&data[..]).expect("Invalid b3dm");
             // debug!("{:?}", &b3dm.feature_table.header);
             // debug!("{:?}", &b3dm.batch_table.header);
-            if b3dm.feature_table.header.rtc_center.is_some() {
-                warn!(
-                    "TODO: add transformation for rtc_center {:?}",
-                    b3dm.feature_table.header.rtc_center
-                );
-            }
-            view_gltf_from_reader(app, transform, &mut reader);
+            let rtc_center = match b3dm.feature_table.header.rtc_center {
+                Some(ref rtc_center) => rtc_center
+                    .value("RTC_CENTER", &b3dm.feature_table.body)
+                    .expect("Invalid b3dm RTC_CENTER"),
+                None => [0.0; 3],
+            };
+            let tile_transform = *tile_transform * Matrix4::from_translation(rtc_center);
+            view_gltf_from_bytes(app, transform(&tile_transform, origin), &b3dm.gltf);
         }
-        Some("i3dm") => {
-            let i3dm = I3dm::from_reader(&mut reader).expect("Invalid i3dm");
+        ContentType::I3dm => {
+            let i3dm = I3dm::from_reader(&data[..]).expect("Invalid i3dm");
             // debug!("{:?}", &i3dm.feature_table.header);
             // debug!("{:?}", &i3dm.batch_table.header);
-            if i3dm.feature_table.header.rtc_center.is_some() {
-                warn!(
-                    "TODO: add transformation for rtc_center {:?}",
-                    i3dm.feature_table.header.rtc_center
-                );
-            }
-
-            if i3dm.header.gltf_format == 0 {
-                let mut url = String::new();
-                reader.read_to_string(&mut url).unwrap();
-                debug!("{:?}", &url); // TODO
-            } else if i3dm.header.gltf_format == 1 {
-                view_gltf_from_reader(app, transform, &mut reader);
+            match i3dm.gltf {
+                I3dmGltf::Uri(ref url) => {
+                    debug!("{:?}", url); // TODO
+                }
+                I3dmGltf::Embedded(ref glb) => {
+                    let instances = i3dm.instance_transforms().expect("Invalid i3dm instances");
+                    let gltf_fn = gltf_tempfile(glb);
+                    for instance in &instances {
+                        // Instance positions may be ECEF, so combine in f64 before converting
+                        let cols = (*tile_transform * *instance).to_f32_relative_to(origin);
+                        let instance_transform =
+                            Transform::from_matrix(Mat4::from_cols_array(&cols));
+                        view_gltf(app, instance_transform, &gltf_fn);
+                    }
+                }
             }
         }
-        Some("pnts") => {
-            view_pnts(app, transform, &tile_fn);
+        ContentType::Pnts => {
+            // Positions are relative to RTC_CENTER, which is added to the tile transform
+            let pnts = Pnts::from_reader(&data[..]).expect("Invalid pnts");
+            let rtc_center = match pnts.feature_table.header.rtc_center {
+                Some(ref rtc_center) => rtc_center
+                    .value("RTC_CENTER", &pnts.feature_table.body)
+                    .expect("Invalid pnts RTC_CENTER"),
+                None => [0.0; 3],
+            };
+            let tile_transform = *tile_transform * Matrix4::from_translation(rtc_center);
+            view_pnts(app, transform(&tile_transform, origin), tile_fn);
+        }
+        ContentType::Glb => {
+            // The asset server detects glTF by the file extension
+            view_gltf_from_bytes(app, transform(tile_transform, origin), &data);
         }
-        Some("json") => {
-            view_tileset_content(app, &tile_fn);
+        ContentType::Gltf => {
+            // Keep the location for resolving relative buffer URIs
+            view_gltf(app, transform(tile_transform, origin), tile_fn);
         }
-        _ => {
-            error!("Unknown file extension");
+        content_type => {
+            error!("Unsupported content type {:?}", content_type);
         }
     }
 }
 
-fn view_gltf_from_reader<R: Read>(app: &mut AppBuilder, transform: Transform, mut reader: R) {
-    // Write glTF into file
+fn view_gltf_from_bytes(app: &mut AppBuilder, transform: Transform, gltf: &[u8]) {
+    let gltf_fn = gltf_tempfile(gltf);
+    view_gltf(app, transform, &gltf_fn);
+}
+
+/// Write glTF into a file which can be loaded by the asset server
+fn gltf_tempfile(gltf: &[u8]) -> String {
     let mut file = tempfile::Builder::new()
         .prefix("tile_")
         .suffix(".glb")
         .tempfile()
         .expect("Couldn't create tempfile");
-    io::copy(&mut reader, &mut file).unwrap();
+    file.write_all(gltf).unwrap();
     let (_file, path) = file.keep().expect("tempfile keep failed");
-    let gltf_fn = path.to_str().expect("Invalid file name");
-    view_gltf(app, transform, &gltf_fn);
+    path.to_str().expect("Invalid file name").to_string()
 }
 
 pub fn init_viewer(app: &mut AppBuilder) {
@@ -170,21 +217,16 @@ pub fn init_viewer(app: &mut AppBuilder) {
     app.add_system(camera_debug_system.system());
 }
 
-/// Convert 3D tiles transform matrix to Bevy Transform
-pub fn transform(transform: &Option<Vec<f32>>) -> Transform {
-    if let Some(t) = transform {
-        let mut t = Transform::from_matrix(Mat4::from_cols_array(&[
-            t[0], t[1], t[2], t[3], t[4], t[5], t[6], t[7], t[8], t[9], t[10], t[11], t[12], t[13],
-            t[14], t[15],
-        ]));
-        if t.scale != Vec3::ONE {
-            warn!("Ignoring tile scale");
-            t.scale = Vec3::ONE;
-        }
-        t
-    } else {
-        Transform::identity()
+/// Convert 3D tiles transform matrix to Bevy Transform, with the translation relative to
+/// `origin`.
+pub fn transform(transform: &Matrix4, origin: [f64; 3]) -> Transform {
+    let cols = transform.to_f32_relative_to(origin);
+    let mut t = Transform::from_matrix(Mat4::from_cols_array(&cols));
+    if t.scale != Vec3::ONE {
+        warn!("Ignoring tile scale");
+        t.scale = Vec3::ONE;
     }
+    t
 }
 
 pub fn view_gltf(app: &mut AppBuilder, transform: Transform, tile_path: &str) {
@@ -248,52 +290,29 @@ fn setup_pnts(
     for tile in query.iter() {
         debug!("Adding point tile mesh: {}", tile.path);
         let file = File::open(tile.path.as_str()).unwrap();
-        let mut reader = BufReader::new(file);
-        let pnts = Pnts::from_reader(&mut reader).unwrap();
+        let pnts = Pnts::from_reader(BufReader::new(file)).unwrap();
         // debug!("{:?}", &pnts.feature_table.header);
 
-        if let Some(dataref) = pnts.feature_table.header.position {
-            assert_eq!(dataref.byte_offset, 0);
-        }
         let points_length = pnts.feature_table.header.points_length as usize;
-        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(points_length);
-        for _ in 0..points_length {
-            positions.push([
-                reader.read_f32::<LittleEndian>().unwrap(),
-                reader.read_f32::<LittleEndian>().unwrap(),
-                reader.read_f32::<LittleEndian>().unwrap(),
-            ]);
-        }
-        if let Some(dataref) = pnts.feature_table.header.normal {
-            warn!("TODO: Read normals beginning at {}", dataref.byte_offset)
-        }
+        let positions: Vec<[f32; 3]> = pnts
+            .feature_table
+            .positions_f64(false)
+            .expect("Invalid pnts")
+            .iter()
+            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+            .collect();
+        let normals = pnts
+            .feature_table
+            .normals()
+            .expect("Invalid pnts")
+            .unwrap_or_else(|| vec![[0.0; 3]; points_length]);
 
         let mut mesh = Mesh::new(PrimitiveTopology::PointList);
         mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, vec![0.0; points_length]);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
 
-        // Skip remaining feature data
-        let mut body = vec![
-            0;
-            pnts.header.feature_table_binary_byte_length as usize
-                - (points_length * std::mem::size_of::<f32>() * 3)
-        ];
-        reader.read_exact(&mut body).unwrap();
+        // debug!("{:?}", &pnts.batch_table.header);
 
-        let _batch_table = BatchTable::from_reader(
-            &mut reader,
-            pnts.header.batch_table_json_byte_length,
-            pnts.header.batch_table_binary_byte_length,
-        )
-        .unwrap();
-        // debug!("{:?}", &batch_table.header);
-
-        if pnts.feature_table.header.rtc_center.is_some() {
-            warn!(
-                "TODO: add transformation for rtc_center {:?}",
-                pnts.feature_table.header.rtc_center
-            );
-        }
         debug!("PntsTileComponent transformation: {:?}", &tile.transform);
         commands.spawn_bundle(PbrBundle {
             mesh: meshes.add(mesh),
@@ -419,11 +438,9 @@ fn setup_camera(mut commands: Commands, query: Query<&BoundingVolumeBox>) {
         commands.spawn_bundle(OrbitCameraBundle::new(
             OrbitCameraController::default(),
             PerspectiveCameraBundle::default(),
-            // Vec3::new(-2.0, 5.0, 50.0), // dragon
-            // Vec3::new(0., 0., 0.),
-            // points transformed:
-            Vec3::new(1215031.0, -4736383.5, 4081666.3 + 50.0),
-            Vec3::new(1215031.0, -4736383.5, 4081666.3),
+            // Content is rendered relative to its origin
+            Vec3::new(0.0, 0.0, 50.0),
+            Vec3::ZERO,
         ));
         // rotating light
         commands
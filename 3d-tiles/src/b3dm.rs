@@ -1,26 +1,30 @@
 use crate::batch_table::BatchTable;
-use crate::error::Error;
+use crate::binary::{check_alignment, check_header, collect, json_section, layout, read_section};
+use crate::error::{Error, Section};
 use crate::feature_table::{GlobalPropertyCartesian3, GlobalPropertyScalar, Property};
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::limits::Limits;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
+use std::io::{BufReader, Read, Write};
+use std::mem;
 use std::path::Path;
 
 /// Batched 3D Model tile.
 ///
 /// <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Batched3DModel/README.md>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct B3dm {
     pub header: B3dmHeader,
     pub feature_table: FeatureTable,
     pub batch_table: BatchTable,
-    // Binary GlTF
+    /// Binary glTF
+    pub gltf: Vec<u8>,
 }
 
 /// The header section of a .b3dm file.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[repr(C)]
 pub struct B3dmHeader {
     /// Must be `b"b3dm"`. This can be used to identify the content as a Batched 3D Model tile.
@@ -42,6 +46,8 @@ pub struct B3dmHeader {
 impl B3dmHeader {
     fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
         use self::Error::Io;
+        let buf = read_section(&mut reader, Section::Header, 0, mem::size_of::<Self>())?;
+        let mut reader = &buf[..];
         let mut magic = [0; 4];
         reader.read_exact(&mut magic).map_err(Io)?;
         if &magic == b"b3dm" {
@@ -58,14 +64,90 @@ impl B3dmHeader {
             Err(Error::Magic(magic))
         }
     }
+
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        use self::Error::Io;
+        writer.write_all(&self.magic).map_err(Io)?;
+        writer.write_u32::<LittleEndian>(self.version).map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.feature_table_json_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.feature_table_binary_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.batch_table_json_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.batch_table_binary_byte_length)
+            .map_err(Io)?;
+        Ok(())
+    }
+
+    /// Lengths of the tables following the header.
+    fn table_lengths(&self) -> [(Section, u32); 4] {
+        [
+            (
+                Section::FeatureTableJson,
+                self.feature_table_json_byte_length,
+            ),
+            (
+                Section::FeatureTableBinary,
+                self.feature_table_binary_byte_length,
+            ),
+            (Section::BatchTableJson, self.batch_table_json_byte_length),
+            (
+                Section::BatchTableBinary,
+                self.batch_table_binary_byte_length,
+            ),
+        ]
+    }
+
+    /// Lengths of the tables following the header, for [`layout`].
+    fn declared_lengths(&self) -> Vec<Option<usize>> {
+        self.table_lengths()
+            .iter()
+            .map(|(_, length)| Some(*length as usize))
+            .collect()
+    }
+
+    /// Byte offset of the Batch Table in the tile.
+    fn batch_table_offset(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.feature_table_json_byte_length as usize
+            + self.feature_table_binary_byte_length as usize
+    }
+
+    /// The length of the binary glTF in bytes.
+    fn gltf_byte_length(&self) -> Result<usize, Error> {
+        let sections = mem::size_of::<Self>() as u64
+            + u64::from(self.feature_table_json_byte_length)
+            + u64::from(self.feature_table_binary_byte_length)
+            + u64::from(self.batch_table_json_byte_length)
+            + u64::from(self.batch_table_binary_byte_length);
+        u64::from(self.byte_length)
+            .checked_sub(sections)
+            .map(|len| len as usize)
+            .ok_or(Error::ByteLength {
+                section: Section::Header,
+                offset: 8,
+                declared: u64::from(self.byte_length),
+                actual: sections,
+            })
+    }
 }
 
 /// A Feature Table is a component of a tile's binary body and describes position and appearance properties required to render each feature in a tile.
 // <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/FeatureTable/README.md>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FeatureTable {
     /// JSON header
     pub header: BatchedFeatureTable,
+    /// JSON header as read, written instead of `header` while it has the same content.
+    pub raw_header: Option<Vec<u8>>,
     /// Binary body
     pub body: Vec<u8>,
 }
@@ -76,14 +158,48 @@ impl FeatureTable {
         json_byte_length: u32,
         binary_byte_length: u32,
     ) -> Result<Self, Error> {
-        use self::Error::Io;
-        let mut buf = vec![0; json_byte_length as usize];
-        reader.read_exact(&mut buf).map_err(Io)?;
+        let offset = mem::size_of::<B3dmHeader>();
+        let json_byte_length = json_byte_length as usize;
+        let buf = read_section(
+            &mut reader,
+            Section::FeatureTableJson,
+            offset,
+            json_byte_length,
+        )?;
         // dbg!(&std::str::from_utf8(&buf));
         let header: BatchedFeatureTable = serde_json::from_slice(&buf).map_err(Error::Json)?;
-        let mut body = vec![0; binary_byte_length as usize];
-        reader.read_exact(&mut body).map_err(Io)?;
-        Ok(FeatureTable { header, body })
+        let body = read_section(
+            &mut reader,
+            Section::FeatureTableBinary,
+            offset + json_byte_length,
+            binary_byte_length as usize,
+        )?;
+        Ok(FeatureTable {
+            header,
+            raw_header: Some(buf),
+            body,
+        })
+    }
+
+    /// Serialize the JSON header and the binary body.
+    fn to_bytes(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let json = json_section(&self.header, self.raw_header.as_deref())?;
+        Ok((json, self.body.clone()))
+    }
+
+    /// Number of features (`BATCH_LENGTH`).
+    pub fn batch_length(&self) -> Result<usize, Error> {
+        self.header.batch_length.length("BATCH_LENGTH", &self.body)
+    }
+
+    /// Check that the global properties fit into the binary body.
+    fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        collect(&mut errors, self.batch_length());
+        if let Some(ref rtc_center) = self.header.rtc_center {
+            collect(&mut errors, rtc_center.value("RTC_CENTER", &self.body));
+        }
+        errors
     }
 }
 
@@ -92,7 +208,7 @@ impl FeatureTable {
 ///
 /// A set of semantics containing per-tile and per-feature values defining the position and
 /// appearance properties for features in a tile.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchedFeatureTable {
     /// A `GlobalPropertyScalar` object defining a numeric property for all features. See the
     /// corresponding property semantic in
@@ -102,23 +218,36 @@ pub struct BatchedFeatureTable {
     /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
     /// features. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Batched3DModel/README.md#semantics).
-    #[serde(rename = "RTC_CENTER")]
+    #[serde(rename = "RTC_CENTER", skip_serializing_if = "Option::is_none")]
     pub rtc_center: Option<GlobalPropertyCartesian3>,
 
     #[serde(flatten)]
     pub properties: HashMap<String, Property>,
     /// Dictionary object with extension-specific objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
     /// Application-specific data.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
 }
 
 impl B3dm {
-    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Read tile, checking the header against `limits` before reading any section.
+    pub fn from_reader_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, Error> {
         let header = B3dmHeader::from_reader(&mut reader)?;
         if header.version != 1 {
             return Err(Error::Version(header.version));
         }
+        check_header(
+            header.byte_length,
+            mem::size_of::<B3dmHeader>(),
+            &header.table_lengths(),
+            limits,
+        )?;
         let feature_table = FeatureTable::from_reader(
             &mut reader,
             header.feature_table_json_byte_length,
@@ -126,27 +255,147 @@ impl B3dm {
         )?;
         let batch_table = BatchTable::from_reader(
             &mut reader,
+            header.batch_table_offset(),
             header.batch_table_json_byte_length,
             header.batch_table_binary_byte_length,
+            feature_table.batch_length()?,
+        )?;
+        let gltf_byte_length = header.gltf_byte_length()?;
+        let gltf = read_section(
+            &mut reader,
+            Section::Gltf,
+            header.byte_length as usize - gltf_byte_length,
+            gltf_byte_length,
         )?;
         Ok(B3dm {
             header,
             feature_table,
             batch_table,
+            gltf,
         })
     }
+
+    /// Check the binary layout of the tile.
+    ///
+    /// All sections must end on an 8-byte boundary, properties must fit into the binary
+    /// bodies and the Batch Table must have `BATCH_LENGTH` values per property.
+    pub fn validate(&self) -> Vec<Error> {
+        let mut sections = self.header.table_lengths().to_vec();
+        sections.push((Section::Gltf, self.gltf.len() as u32));
+        let mut errors = check_alignment(mem::size_of::<B3dmHeader>(), &sections);
+        errors.extend(self.feature_table.validate());
+        errors.extend(self.batch_table.validate());
+        errors
+    }
+
+    /// Write tile.
+    ///
+    /// The header lengths are computed from the tables and the glTF. Each section is padded
+    /// to an 8-byte boundary, unless all sections are unchanged since reading the tile, which
+    /// is then written exactly as read.
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let header_length = mem::size_of::<B3dmHeader>();
+        let (feature_table_json, feature_table_binary) = self.feature_table.to_bytes()?;
+        let (batch_table_json, batch_table_binary) = self.batch_table.to_bytes()?;
+        let mut declared = self.header.declared_lengths();
+        declared.push(self.header.gltf_byte_length().ok());
+        let sections = layout(
+            header_length,
+            vec![
+                (feature_table_json, b' '),
+                (feature_table_binary, 0),
+                (batch_table_json, b' '),
+                (batch_table_binary, 0),
+                (self.gltf.clone(), 0),
+            ],
+            &declared,
+        );
+        let header = B3dmHeader {
+            magic: *b"b3dm",
+            version: 1,
+            byte_length: (header_length + sections.iter().map(Vec::len).sum::<usize>()) as u32,
+            feature_table_json_byte_length: sections[0].len() as u32,
+            feature_table_binary_byte_length: sections[1].len() as u32,
+            batch_table_json_byte_length: sections[2].len() as u32,
+            batch_table_binary_byte_length: sections[3].len() as u32,
+        };
+        header.to_writer(&mut writer)?;
+        for section in &sections {
+            writer.write_all(section).map_err(Error::Io)?;
+        }
+        Ok(())
+    }
 }
 
 /// Read b3dm file and extract binary glTF
 pub fn extract_gltf(path: &str) -> Result<B3dm, Error> {
     use self::Error::Io;
-    let file = File::open(path).map_err(Io)?;
-    let mut reader = BufReader::new(file);
-    let b3dm = B3dm::from_reader(&mut reader)?;
+    let file = File::open(path).map_err(|e| Io(e).with_path(path))?;
+    let b3dm = B3dm::from_reader(BufReader::new(file)).map_err(|e| e.with_path(path))?;
 
     let dest = Path::new(path).with_extension("glb");
     println!("Writing {:?}", &dest);
-    let mut file = File::create(dest).map_err(Io)?;
-    io::copy(&mut reader, &mut file).map_err(Io)?;
+    let mut file = File::create(&dest).map_err(|e| Io(e).with_path(&dest))?;
+    file.write_all(&b3dm.gltf)
+        .map_err(|e| Io(e).with_path(&dest))?;
     Ok(b3dm)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample;
+
+    const SAMPLES: &[&str] = &[
+        "TilesetWithDiscreteLOD/dragon_low.b3dm",
+        "TilesetWithDiscreteLOD/dragon_medium.b3dm",
+        "TilesetWithRequestVolume/building.b3dm",
+        "TilesetWithRequestVolume/city/ll.b3dm",
+        "TilesetWithRequestVolume/city/lr.b3dm",
+        "TilesetWithRequestVolume/city/ul.b3dm",
+        "TilesetWithRequestVolume/city/ur.b3dm",
+    ];
+
+    #[test]
+    fn round_trip_samples() {
+        for name in SAMPLES {
+            let data = sample(name);
+            let b3dm = B3dm::from_reader(&data[..]).unwrap();
+            let mut written = Vec::new();
+            b3dm.to_writer(&mut written).unwrap();
+            assert!(written == data, "{} differs after writing", name);
+        }
+    }
+
+    #[test]
+    fn write_edited_feature_table() {
+        let data = sample("TilesetWithRequestVolume/city/ll.b3dm");
+        let mut b3dm = B3dm::from_reader(&data[..]).unwrap();
+        b3dm.feature_table.header.rtc_center = None;
+        let mut written = Vec::new();
+        b3dm.to_writer(&mut written).unwrap();
+
+        let read = B3dm::from_reader(&written[..]).unwrap();
+        assert!(read.validate().is_empty());
+        assert_eq!(read.header.byte_length as usize, written.len());
+        assert_eq!(read.feature_table.batch_length().unwrap(), 10);
+        assert!(read.feature_table.header.rtc_center.is_none());
+        assert_eq!(written.len() % 8, 0);
+        assert_eq!(&read.gltf[..b3dm.gltf.len()], &b3dm.gltf[..]);
+        let json = read.feature_table.raw_header.unwrap();
+        assert!(std::str::from_utf8(&json)
+            .unwrap()
+            .starts_with(r#"{"BATCH_LENGTH":10}"#));
+    }
+
+    #[test]
+    fn embedded_gltf() {
+        let data = sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let b3dm = B3dm::from_reader(&data[..]).unwrap();
+        assert_eq!(&b3dm.gltf[0..4], b"glTF");
+        let glb_length =
+            u32::from_le_bytes([b3dm.gltf[8], b3dm.gltf[9], b3dm.gltf[10], b3dm.gltf[11]]);
+        assert_eq!(glb_length as usize, b3dm.gltf.len());
+        assert!(data.ends_with(&b3dm.gltf));
+    }
+}
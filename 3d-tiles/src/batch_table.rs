@@ -1,64 +1,344 @@
-use crate::error::Error;
+use crate::batch_table_hierarchy::{BatchTableHierarchy, Hierarchy, EXTENSION_NAME};
+use crate::binary::{body_slice, collect, json_section, read_section};
+use crate::error::{Error, Section};
+use byteorder::{ByteOrder, LittleEndian};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::Read;
 
 /// The Batch Table contains per-model application-specific properties.
 // <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/BatchTable/README.md>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BatchTable {
     /// JSON header
     pub header: Option<BatchTableHeader>,
+    /// JSON header as read, written instead of `header` while it has the same content.
+    pub raw_header: Option<Vec<u8>>,
     /// Binary body
     pub body: Vec<u8>,
+    /// Number of features, defined by the Feature Table of the tile.
+    pub batch_length: usize,
 }
 
 impl BatchTable {
+    /// Read the Batch Table, which starts at byte `offset` of the tile.
     pub fn from_reader<R: Read>(
         mut reader: R,
+        offset: usize,
         json_byte_length: u32,
         binary_byte_length: u32,
+        batch_length: usize,
     ) -> Result<Self, Error> {
-        use Error::Io;
-        let header = if json_byte_length > 0 {
-            let mut buf = vec![0; json_byte_length as usize];
-            reader.read_exact(&mut buf).map_err(Io)?;
+        let json_byte_length = json_byte_length as usize;
+        let (header, raw_header) = if json_byte_length > 0 {
+            let buf = read_section(
+                &mut reader,
+                Section::BatchTableJson,
+                offset,
+                json_byte_length,
+            )?;
             // dbg!(&std::str::from_utf8(&buf));
             let header: BatchTableHeader = serde_json::from_slice(&buf).map_err(Error::Json)?;
-            Some(header)
+            (Some(header), Some(buf))
         } else {
-            None
+            (None, None)
         };
-        let mut body = vec![0; binary_byte_length as usize];
-        reader.read_exact(&mut body).map_err(Io)?;
-        Ok(BatchTable { header, body })
+        let body = read_section(
+            &mut reader,
+            Section::BatchTableBinary,
+            offset + json_byte_length,
+            binary_byte_length as usize,
+        )?;
+        Ok(BatchTable {
+            header,
+            raw_header,
+            body,
+            batch_length,
+        })
+    }
+
+    /// Serialize the JSON header and the binary body.
+    pub(crate) fn to_bytes(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let json = match self.header {
+            Some(ref header) => json_section(header, self.raw_header.as_deref())?,
+            None => Vec::new(),
+        };
+        Ok((json, self.body.clone()))
+    }
+
+    /// Values of the property `name`, one per feature.
+    pub fn property(&self, name: &str) -> Result<Option<PropertyValues>, Error> {
+        let property = match self.header {
+            Some(ref header) => header.properties.get(name),
+            None => None,
+        };
+        match property {
+            Some(property) => Ok(Some(property.values(
+                name,
+                &self.body,
+                self.batch_length,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The `3DTILES_batch_table_hierarchy` extension, if present.
+    pub fn hierarchy(&self) -> Result<Option<Hierarchy>, Error> {
+        let extension = match self.header {
+            Some(ref header) => header
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get(EXTENSION_NAME)),
+            None => None,
+        };
+        match extension {
+            Some(extension) => {
+                let value = serde_json::to_value(extension).map_err(Error::Json)?;
+                let hierarchy: BatchTableHierarchy =
+                    serde_json::from_value(value).map_err(Error::Json)?;
+                Ok(Some(Hierarchy::new(&hierarchy, &self.body)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Check that every property has one value per feature and that binary properties and the
+    /// hierarchy extension fit into the binary body.
+    pub fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        if let Some(ref header) = self.header {
+            let mut names: Vec<_> = header.properties.keys().collect();
+            names.sort();
+            for name in names {
+                if let Some(Some(PropertyValues::Json(values))) =
+                    collect(&mut errors, self.property(name))
+                {
+                    if values.len() != self.batch_length {
+                        errors.push(Error::format(
+                            Section::BatchTableJson,
+                            format!(
+                                "property `{}` has {} values, expected {}",
+                                name,
+                                values.len(),
+                                self.batch_length
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        collect(&mut errors, self.hierarchy());
+        errors
+    }
+
+    /// All properties of the feature `batch_id`, `None` if `batch_id` is not smaller than the
+    /// batch length.
+    ///
+    /// Includes properties inherited through `hierarchy`, the `3DTILES_batch_table_hierarchy`
+    /// extension as returned by [`hierarchy`](Self::hierarchy), unless the feature defines
+    /// them itself. Only the values of the feature are decoded.
+    pub fn feature(
+        &self,
+        batch_id: usize,
+        hierarchy: Option<&Hierarchy>,
+    ) -> Result<Option<HashMap<String, serde_json::Value>>, Error> {
+        if batch_id >= self.batch_length {
+            return Ok(None);
+        }
+        let mut feature = HashMap::new();
+        if let Some(ref header) = self.header {
+            for (name, property) in &header.properties {
+                if let Some(value) =
+                    property.value(name, &self.body, self.batch_length, batch_id)?
+                {
+                    feature.insert(name.clone(), value);
+                }
+            }
+        }
+        if let Some(hierarchy) = hierarchy {
+            for (name, value) in hierarchy.properties(batch_id) {
+                feature.entry(name).or_insert(value);
+            }
+        }
+        Ok(Some(feature))
+    }
+}
+
+/// Values of a Batch Table property.
+#[derive(Debug, Clone)]
+pub enum PropertyValues {
+    /// Values defined directly in the JSON header.
+    Json(Vec<serde_json::Value>),
+    /// Values stored in the binary body. Vector components of all features are stored
+    /// consecutively.
+    Binary {
+        property_type: Type,
+        components: Components,
+    },
+}
+
+impl PropertyValues {
+    /// Value of the feature `batch_id`, a JSON array for vector types.
+    pub fn get(&self, batch_id: usize) -> Option<serde_json::Value> {
+        match self {
+            PropertyValues::Json(values) => values.get(batch_id).cloned(),
+            PropertyValues::Binary {
+                property_type,
+                components,
+            } => {
+                let n = property_type.components();
+                let start = batch_id.checked_mul(n)?;
+                let values = (start..start.checked_add(n)?)
+                    .map(|i| components.get(i))
+                    .collect::<Option<Vec<_>>>()?;
+                if let Type::Scalar = property_type {
+                    values.into_iter().next()
+                } else {
+                    Some(serde_json::Value::Array(values))
+                }
+            }
+        }
+    }
+}
+
+/// Components of a binary Batch Table property.
+#[derive(Debug, Clone)]
+pub enum Components {
+    Byte(Vec<i8>),
+    UnsignedByte(Vec<u8>),
+    Short(Vec<i16>),
+    UnsignedShort(Vec<u16>),
+    Int(Vec<i32>),
+    UnsignedInt(Vec<u32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl Components {
+    /// Component at `index` as JSON number.
+    pub fn get(&self, index: usize) -> Option<serde_json::Value> {
+        match self {
+            Components::Byte(values) => values.get(index).map(|v| (*v).into()),
+            Components::UnsignedByte(values) => values.get(index).map(|v| (*v).into()),
+            Components::Short(values) => values.get(index).map(|v| (*v).into()),
+            Components::UnsignedShort(values) => values.get(index).map(|v| (*v).into()),
+            Components::Int(values) => values.get(index).map(|v| (*v).into()),
+            Components::UnsignedInt(values) => values.get(index).map(|v| (*v).into()),
+            Components::Float(values) => values.get(index).map(|v| (*v).into()),
+            Components::Double(values) => values.get(index).map(|v| (*v).into()),
+        }
     }
 }
 
 /// A set of properties defining application-specific metadata for features in a tile.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchTableHeader {
     #[serde(flatten)]
     pub properties: HashMap<String, Property>,
     /// Dictionary object with extension-specific objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
     /// Application-specific data.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
 }
 
 /// A user-defined property which specifies per-feature application-specific metadata in a
 /// tile. Values either can be defined directly in the JSON as an array, or can refer to
 /// sections in the binary body with a `BinaryBodyReference` object.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Property {
     Array(Vec<serde_json::Value>),
     BinaryBodyReference(BinaryBodyReference),
 }
 
+impl Property {
+    /// Value of the feature `batch_id` of the property `name` for `count` features, a JSON
+    /// array for vector types. Binary values are read from `body`.
+    pub fn value(
+        &self,
+        name: &str,
+        body: &[u8],
+        count: usize,
+        batch_id: usize,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        match self {
+            Property::Array(values) => Ok(values.get(batch_id).cloned()),
+            Property::BinaryBodyReference(reference) => {
+                let data = reference.data(name, body, count)?;
+                if batch_id >= count {
+                    return Ok(None);
+                }
+                // The data of all features fits, so the size of one doesn't overflow
+                let size = reference.component_type.size();
+                let n = reference.property_type.components();
+                let mut values = data[batch_id * n * size..(batch_id + 1) * n * size]
+                    .chunks_exact(size)
+                    .map(|component| reference.component_type.value(component));
+                Ok(match reference.property_type {
+                    Type::Scalar => values.next(),
+                    _ => Some(serde_json::Value::Array(values.collect())),
+                })
+            }
+        }
+    }
+
+    /// Values of the property `name` for `count` features, binary values are read from `body`.
+    pub fn values(&self, name: &str, body: &[u8], count: usize) -> Result<PropertyValues, Error> {
+        match self {
+            Property::Array(values) => Ok(PropertyValues::Json(values.clone())),
+            Property::BinaryBodyReference(reference) => {
+                let size = reference.component_type.size();
+                let data = reference.data(name, body, count)?;
+                let components = match reference.component_type {
+                    ComponentType::Byte => {
+                        Components::Byte(data.iter().map(|b| *b as i8).collect())
+                    }
+                    ComponentType::UnsignedByte => Components::UnsignedByte(data.to_vec()),
+                    ComponentType::Short => Components::Short(
+                        data.chunks_exact(size)
+                            .map(LittleEndian::read_i16)
+                            .collect(),
+                    ),
+                    ComponentType::UnsignedShort => Components::UnsignedShort(
+                        data.chunks_exact(size)
+                            .map(LittleEndian::read_u16)
+                            .collect(),
+                    ),
+                    ComponentType::Int => Components::Int(
+                        data.chunks_exact(size)
+                            .map(LittleEndian::read_i32)
+                            .collect(),
+                    ),
+                    ComponentType::UnsignedInt => Components::UnsignedInt(
+                        data.chunks_exact(size)
+                            .map(LittleEndian::read_u32)
+                            .collect(),
+                    ),
+                    ComponentType::Float => Components::Float(
+                        data.chunks_exact(size)
+                            .map(LittleEndian::read_f32)
+                            .collect(),
+                    ),
+                    ComponentType::Double => Components::Double(
+                        data.chunks_exact(size)
+                            .map(LittleEndian::read_f64)
+                            .collect(),
+                    ),
+                };
+                Ok(PropertyValues::Binary {
+                    property_type: reference.property_type,
+                    components,
+                })
+            }
+        }
+    }
+}
+
 /// An object defining the reference to a section of the binary body of the batch table where
 /// the property values are stored if not defined directly in the JSON.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BinaryBodyReference {
     /// The offset into the buffer in bytes.
     #[serde(rename = "byteOffset")]
@@ -71,8 +351,30 @@ pub struct BinaryBodyReference {
     pub property_type: Type,
 }
 
+impl BinaryBodyReference {
+    /// Data of the property `name` for `count` features in `body`.
+    fn data<'a>(&self, name: &str, body: &'a [u8], count: usize) -> Result<&'a [u8], Error> {
+        let count = count
+            .checked_mul(self.property_type.components())
+            .ok_or_else(|| {
+                Error::format(
+                    Section::BatchTableJson,
+                    format!("property `{}`: number of components overflows", name),
+                )
+            })?;
+        body_slice(
+            body,
+            Section::BatchTableBinary,
+            name,
+            self.byte_offset,
+            count,
+            self.component_type.size(),
+        )
+    }
+}
+
 /// The datatype of components in the property.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum ComponentType {
     #[serde(rename = "BYTE")]
     Byte,
@@ -92,8 +394,34 @@ pub enum ComponentType {
     UnsignedShort,
 }
 
+impl ComponentType {
+    /// Size of a component in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            ComponentType::Byte | ComponentType::UnsignedByte => 1,
+            ComponentType::Short | ComponentType::UnsignedShort => 2,
+            ComponentType::Int | ComponentType::UnsignedInt | ComponentType::Float => 4,
+            ComponentType::Double => 8,
+        }
+    }
+
+    /// Component stored in `data` as JSON number.
+    fn value(self, data: &[u8]) -> serde_json::Value {
+        match self {
+            ComponentType::Byte => (data[0] as i8).into(),
+            ComponentType::UnsignedByte => data[0].into(),
+            ComponentType::Short => LittleEndian::read_i16(data).into(),
+            ComponentType::UnsignedShort => LittleEndian::read_u16(data).into(),
+            ComponentType::Int => LittleEndian::read_i32(data).into(),
+            ComponentType::UnsignedInt => LittleEndian::read_u32(data).into(),
+            ComponentType::Float => LittleEndian::read_f32(data).into(),
+            ComponentType::Double => LittleEndian::read_f64(data).into(),
+        }
+    }
+}
+
 /// Specifies if the property is a scalar or vector.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Type {
     #[serde(rename = "SCALAR")]
     Scalar,
@@ -104,3 +432,130 @@ pub enum Type {
     #[serde(rename = "VEC4")]
     Vec4,
 }
+
+impl Type {
+    /// Number of components.
+    pub fn components(self) -> usize {
+        match self {
+            Type::Scalar => 1,
+            Type::Vec2 => 2,
+            Type::Vec3 => 3,
+            Type::Vec4 => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b3dm::B3dm;
+    use crate::sample;
+    use serde_json::json;
+
+    /// Batch Table with the JSON `header` and binary `body` for `batch_length` features.
+    fn batch_table(header: serde_json::Value, body: Vec<u8>, batch_length: usize) -> BatchTable {
+        BatchTable {
+            header: Some(serde_json::from_value(header).unwrap()),
+            raw_header: None,
+            body,
+            batch_length,
+        }
+    }
+
+    #[test]
+    fn sample_features() {
+        let data = sample("TilesetWithRequestVolume/city/ll.b3dm");
+        let batch_table = B3dm::from_reader(&data[..]).unwrap().batch_table;
+        assert_eq!(batch_table.batch_length, 10);
+        assert!(batch_table.validate().is_empty());
+        let feature = batch_table.feature(3, None).unwrap().unwrap();
+        assert_eq!(feature["id"], json!(3));
+        assert_eq!(feature["Longitude"], json!(-1.3197052536661238));
+        assert!(batch_table.feature(10, None).unwrap().is_none());
+        assert!(batch_table.property("unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn binary_properties() {
+        let header = json!({
+            "height": {"byteOffset": 0, "componentType": "UNSIGNED_SHORT", "type": "SCALAR"},
+            "offset": {"byteOffset": 8, "componentType": "FLOAT", "type": "VEC2"},
+            "name": ["a", "b"],
+        });
+        let mut body = vec![0; 24];
+        LittleEndian::write_u16_into(&[7, 9], &mut body[0..4]);
+        LittleEndian::write_f32_into(&[1.0, 2.0, 3.0, 4.0], &mut body[8..24]);
+        let batch_table = batch_table(header, body, 2);
+        assert!(batch_table.validate().is_empty());
+        let height = batch_table.property("height").unwrap().unwrap();
+        assert_eq!(height.get(1), Some(json!(9)));
+        assert_eq!(height.get(2), None);
+        let offset = batch_table.property("offset").unwrap().unwrap();
+        assert_eq!(offset.get(1), Some(json!([3.0, 4.0])));
+        let feature = batch_table.feature(0, None).unwrap().unwrap();
+        assert_eq!(feature.len(), 3);
+        assert_eq!(feature["name"], json!("a"));
+        assert_eq!(feature["offset"], json!([1.0, 2.0]));
+        // Single values match the decoded properties
+        let feature = batch_table.feature(1, None).unwrap().unwrap();
+        assert_eq!(Some(&feature["height"]), height.get(1).as_ref());
+        assert_eq!(Some(&feature["offset"]), offset.get(1).as_ref());
+    }
+
+    #[test]
+    fn binary_property_exceeding_body() {
+        let header = json!({
+            "height": {"byteOffset": 4, "componentType": "DOUBLE", "type": "VEC3"},
+        });
+        let batch_table = batch_table(header, vec![0; 48], 2);
+        assert!(matches!(
+            batch_table.property("height"),
+            Err(Error::PropertyReference {
+                section: Section::BatchTableBinary,
+                offset: 4,
+                length: 48,
+                available: 48,
+                ..
+            })
+        ));
+        assert_eq!(batch_table.validate().len(), 1);
+        assert!(batch_table.feature(0, None).is_err());
+    }
+
+    #[test]
+    fn json_property_length() {
+        let batch_table = batch_table(json!({"name": ["a"]}), Vec::new(), 2);
+        let errors = batch_table.validate();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            Error::Format {
+                section: Section::BatchTableJson,
+                ..
+            }
+        ));
+        assert!(batch_table.feature(1, None).unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn overflowing_feature_count() {
+        let header = json!({
+            "offset": {"byteOffset": 0, "componentType": "FLOAT", "type": "VEC4"},
+        });
+        let batch_table = batch_table(header, vec![0; 32], usize::MAX);
+        assert!(matches!(
+            batch_table.property("offset"),
+            Err(Error::Format {
+                section: Section::BatchTableJson,
+                ..
+            })
+        ));
+        let values = PropertyValues::Binary {
+            property_type: Type::Vec4,
+            components: Components::Float(vec![0.0; 8]),
+        };
+        assert_eq!(values.get(1), Some(json!([0.0, 0.0, 0.0, 0.0])));
+        assert_eq!(values.get(usize::MAX / 4), None);
+        assert_eq!(values.get(usize::MAX), None);
+    }
+}
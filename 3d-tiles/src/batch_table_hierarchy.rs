@@ -0,0 +1,387 @@
+use crate::batch_table::{ComponentType, Property, PropertyValues};
+use crate::binary::body_slice;
+use crate::error::{Error, Section};
+use byteorder::{ByteOrder, LittleEndian};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Name of the extension in `BatchTableHeader.extensions`.
+pub const EXTENSION_NAME: &str = "3DTILES_batch_table_hierarchy";
+
+/// The `3DTILES_batch_table_hierarchy` extension of a Batch Table.
+///
+/// <https://github.com/CesiumGS/3d-tiles/blob/main/extensions/3DTILES_batch_table_hierarchy/README.md>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchTableHierarchy {
+    /// An array of classes, each defining the properties of its instances.
+    pub classes: Vec<Class>,
+    /// The total number of instances of all classes.
+    #[serde(rename = "instancesLength")]
+    pub instances_length: usize,
+    /// The class index of each instance.
+    #[serde(rename = "classIds")]
+    pub class_ids: Indices,
+    /// The number of parents of each instance. When omitted, every instance has one parent.
+    #[serde(rename = "parentCounts", skip_serializing_if = "Option::is_none")]
+    pub parent_counts: Option<Indices>,
+    /// The instance indexes of the parents of each instance. An instance pointing to itself has
+    /// no parent.
+    #[serde(rename = "parentIds", skip_serializing_if = "Option::is_none")]
+    pub parent_ids: Option<Indices>,
+    /// Dictionary object with extension-specific objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    /// Application-specific data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
+}
+
+/// A class of instances in the hierarchy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Class {
+    /// The name of the class.
+    pub name: String,
+    /// The number of instances of the class.
+    pub length: usize,
+    /// Properties of the instances, with one value per instance.
+    pub instances: HashMap<String, Property>,
+}
+
+/// Instance indexes, defined directly in the JSON or in the binary body of the Batch Table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Indices {
+    Array(Vec<u32>),
+    BinaryBodyReference(IndicesReference),
+}
+
+/// Reference to instance indexes in the binary body of the Batch Table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndicesReference {
+    /// The offset into the buffer in bytes.
+    #[serde(rename = "byteOffset")]
+    pub byte_offset: usize,
+    /// The datatype of the indexes. The default is `UNSIGNED_SHORT`.
+    #[serde(rename = "componentType", skip_serializing_if = "Option::is_none")]
+    pub component_type: Option<ComponentType>,
+}
+
+impl Indices {
+    /// Resolve `count` indexes of the property `name`, binary values are read from `body`.
+    fn resolve(&self, name: &str, body: &[u8], count: usize) -> Result<Vec<u32>, Error> {
+        let indices = match self {
+            Indices::Array(values) => values.clone(),
+            Indices::BinaryBodyReference(reference) => {
+                let component_type = reference
+                    .component_type
+                    .unwrap_or(ComponentType::UnsignedShort);
+                let size = component_type.size();
+                let data = body_slice(
+                    body,
+                    Section::BatchTableBinary,
+                    name,
+                    reference.byte_offset,
+                    count,
+                    size,
+                )?;
+                match component_type {
+                    ComponentType::UnsignedByte => data.iter().map(|v| u32::from(*v)).collect(),
+                    ComponentType::UnsignedShort => data
+                        .chunks_exact(size)
+                        .map(|c| u32::from(LittleEndian::read_u16(c)))
+                        .collect(),
+                    ComponentType::UnsignedInt => data
+                        .chunks_exact(size)
+                        .map(LittleEndian::read_u32)
+                        .collect(),
+                    _ => {
+                        return Err(Error::format(
+                            Section::BatchTableJson,
+                            format!("{} must be unsigned integers", name),
+                        ))
+                    }
+                }
+            }
+        };
+        if indices.len() < count {
+            return Err(Error::format(
+                Section::BatchTableJson,
+                format!("{} has {} indexes, expected {}", name, indices.len(), count),
+            ));
+        }
+        Ok(indices)
+    }
+}
+
+/// A resolved batch table hierarchy for property lookup.
+///
+/// The first `BATCH_LENGTH` instances are the features of the tile, so a batch ID is also an
+/// instance index.
+#[derive(Debug, Clone)]
+pub struct Hierarchy {
+    class_names: Vec<String>,
+    class_properties: Vec<HashMap<String, PropertyValues>>,
+    class_ids: Vec<u32>,
+    /// Index of each instance within its class.
+    class_indexes: Vec<usize>,
+    /// Start of the parents of each instance in `parent_ids`.
+    parent_offsets: Vec<usize>,
+    parent_counts: Vec<usize>,
+    parent_ids: Vec<u32>,
+}
+
+impl Hierarchy {
+    /// Resolve the extension, binary values are read from the Batch Table `body`.
+    pub fn new(hierarchy: &BatchTableHierarchy, body: &[u8]) -> Result<Self, Error> {
+        let instances_length = hierarchy.instances_length;
+        let mut class_names = Vec::new();
+        let mut class_properties = Vec::new();
+        for class in &hierarchy.classes {
+            let mut properties = HashMap::new();
+            for (name, property) in &class.instances {
+                properties.insert(name.clone(), property.values(name, body, class.length)?);
+            }
+            class_names.push(class.name.clone());
+            class_properties.push(properties);
+        }
+
+        let class_ids = hierarchy
+            .class_ids
+            .resolve("classIds", body, instances_length)?;
+        let mut class_counts = vec![0; hierarchy.classes.len()];
+        let mut class_indexes = Vec::with_capacity(instances_length);
+        for class_id in &class_ids[..instances_length] {
+            let count = class_counts.get_mut(*class_id as usize).ok_or_else(|| {
+                Error::format(
+                    Section::BatchTableJson,
+                    format!("class id {} exceeds number of classes", class_id),
+                )
+            })?;
+            class_indexes.push(*count);
+            *count += 1;
+        }
+
+        let parent_counts = match (&hierarchy.parent_counts, &hierarchy.parent_ids) {
+            (_, None) => vec![0; instances_length],
+            (None, Some(_)) => vec![1; instances_length],
+            (Some(parent_counts), Some(_)) => parent_counts
+                .resolve("parentCounts", body, instances_length)?
+                .iter()
+                .map(|count| *count as usize)
+                .collect(),
+        };
+        let mut parent_offsets = Vec::with_capacity(instances_length);
+        let mut parent_ids_length = 0;
+        for count in &parent_counts {
+            parent_offsets.push(parent_ids_length);
+            parent_ids_length += count;
+        }
+        let parent_ids = match hierarchy.parent_ids {
+            Some(ref parent_ids) => parent_ids.resolve("parentIds", body, parent_ids_length)?,
+            None => Vec::new(),
+        };
+
+        Ok(Hierarchy {
+            class_names,
+            class_properties,
+            class_ids,
+            class_indexes,
+            parent_offsets,
+            parent_counts,
+            parent_ids,
+        })
+    }
+
+    /// Number of instances.
+    pub fn instances_length(&self) -> usize {
+        self.class_indexes.len()
+    }
+
+    /// Class name of the instance.
+    pub fn class_name(&self, instance: usize) -> Option<&str> {
+        let class_id = *self.class_ids.get(instance)? as usize;
+        self.class_names.get(class_id).map(String::as_str)
+    }
+
+    /// Parents of the instance, excluding the instance itself.
+    pub fn parents(&self, instance: usize) -> Vec<usize> {
+        if instance >= self.instances_length() {
+            return Vec::new();
+        }
+        let offset = self.parent_offsets[instance];
+        self.parent_ids[offset..offset + self.parent_counts[instance]]
+            .iter()
+            .map(|id| *id as usize)
+            .filter(|id| *id != instance)
+            .collect()
+    }
+
+    /// Instance followed by its ancestors, nearest first.
+    ///
+    /// Every instance is visited once, even if the hierarchy contains cycles.
+    pub fn ancestors(&self, instance: usize) -> Vec<usize> {
+        let mut visited = vec![false; self.instances_length()];
+        let mut ancestors = Vec::new();
+        let mut next = vec![instance];
+        while !next.is_empty() {
+            let mut parents = Vec::new();
+            for instance in next {
+                if instance < visited.len() && !visited[instance] {
+                    visited[instance] = true;
+                    ancestors.push(instance);
+                    parents.extend(self.parents(instance));
+                }
+            }
+            next = parents;
+        }
+        ancestors
+    }
+
+    /// Properties defined by the class of the instance itself.
+    fn own_properties(&self, instance: usize) -> Option<(&HashMap<String, PropertyValues>, usize)> {
+        let class_id = *self.class_ids.get(instance)? as usize;
+        let properties = self.class_properties.get(class_id)?;
+        Some((properties, self.class_indexes[instance]))
+    }
+
+    /// Value of the property `name` of the feature `batch_id`, inherited from the nearest
+    /// ancestor defining it.
+    pub fn property(&self, batch_id: usize, name: &str) -> Option<serde_json::Value> {
+        self.ancestors(batch_id).into_iter().find_map(|instance| {
+            let (properties, index) = self.own_properties(instance)?;
+            properties.get(name)?.get(index)
+        })
+    }
+
+    /// All properties of the feature `batch_id`, including the inherited ones.
+    ///
+    /// Properties of nearer ancestors take precedence.
+    pub fn properties(&self, batch_id: usize) -> HashMap<String, serde_json::Value> {
+        let mut feature = HashMap::new();
+        for instance in self.ancestors(batch_id) {
+            if let Some((properties, index)) = self.own_properties(instance) {
+                for (name, values) in properties {
+                    if !feature.contains_key(name) {
+                        if let Some(value) = values.get(index) {
+                            feature.insert(name.clone(), value);
+                        }
+                    }
+                }
+            }
+        }
+        feature
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch_table::BatchTable;
+    use serde_json::json;
+
+    /// Walls of a building with an owner, following the example of the extension.
+    fn walls() -> serde_json::Value {
+        json!({
+            "classes": [
+                {"name": "Wall", "length": 2, "instances": {"color": ["white", "red"]}},
+                {"name": "Building", "length": 1, "instances": {
+                    "name": ["unit29"],
+                    "address": ["100 Main St"],
+                }},
+                {"name": "Owner", "length": 1, "instances": {
+                    "name": ["city"],
+                    "type": ["public"],
+                }},
+            ],
+            "instancesLength": 4,
+            "classIds": [0, 0, 1, 2],
+            "parentIds": [2, 2, 3, 3],
+        })
+    }
+
+    fn walls_with_class_ids(class_ids: serde_json::Value) -> serde_json::Value {
+        let mut json = walls();
+        json["classIds"] = class_ids;
+        json
+    }
+
+    fn hierarchy(json: serde_json::Value, body: &[u8]) -> Result<Hierarchy, Error> {
+        let hierarchy: BatchTableHierarchy = serde_json::from_value(json).unwrap();
+        Hierarchy::new(&hierarchy, body)
+    }
+
+    #[test]
+    fn inherited_properties() {
+        let hierarchy = hierarchy(walls(), &[]).unwrap();
+        assert_eq!(hierarchy.instances_length(), 4);
+        assert_eq!(hierarchy.class_name(1), Some("Wall"));
+        assert_eq!(hierarchy.class_name(3), Some("Owner"));
+        assert_eq!(hierarchy.parents(3), Vec::<usize>::new());
+        assert_eq!(hierarchy.ancestors(1), vec![1, 2, 3]);
+        assert_eq!(hierarchy.property(1, "color"), Some(json!("red")));
+        assert_eq!(hierarchy.property(1, "name"), Some(json!("unit29")));
+        assert_eq!(hierarchy.property(1, "type"), Some(json!("public")));
+        assert_eq!(hierarchy.property(1, "height"), None);
+        let properties = hierarchy.properties(0);
+        assert_eq!(properties.len(), 4);
+        assert_eq!(properties["address"], json!("100 Main St"));
+    }
+
+    #[test]
+    fn multiple_parents_and_cycles() {
+        let mut json = walls();
+        json["parentCounts"] = json!([2, 1, 1, 1]);
+        json["parentIds"] = json!([2, 3, 2, 3, 0]);
+        let hierarchy = hierarchy(json, &[]).unwrap();
+        assert_eq!(hierarchy.parents(0), vec![2, 3]);
+        assert_eq!(hierarchy.ancestors(0), vec![0, 2, 3]);
+        // The owner points back to the first wall
+        assert_eq!(hierarchy.ancestors(3), vec![3, 0, 2]);
+        assert_eq!(hierarchy.property(3, "color"), Some(json!("white")));
+    }
+
+    #[test]
+    fn binary_indexes() {
+        let mut json = walls();
+        json["classIds"] = json!({"byteOffset": 0, "componentType": "UNSIGNED_BYTE"});
+        json["parentIds"] = json!({"byteOffset": 4});
+        let mut body = vec![0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+        LittleEndian::write_u16_into(&[2, 2, 3, 3], &mut body[4..12]);
+        let resolved = hierarchy(json, &body).unwrap();
+        assert_eq!(resolved.ancestors(0), vec![0, 2, 3]);
+
+        assert!(matches!(
+            hierarchy(walls_with_class_ids(json!({"byteOffset": 10})), &body),
+            Err(Error::PropertyReference { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_indexes() {
+        assert!(hierarchy(walls_with_class_ids(json!([0, 0, 1])), &[]).is_err());
+        assert!(hierarchy(walls_with_class_ids(json!([0, 0, 1, 3])), &[]).is_err());
+        let float = json!({"byteOffset": 0, "componentType": "FLOAT"});
+        assert!(hierarchy(walls_with_class_ids(float), &[0; 16]).is_err());
+    }
+
+    #[test]
+    fn batch_table_features() {
+        let header = json!({
+            "height": [10, 20],
+            "extensions": {EXTENSION_NAME: walls()},
+        });
+        let batch_table = BatchTable {
+            header: Some(serde_json::from_value(header).unwrap()),
+            raw_header: None,
+            body: Vec::new(),
+            batch_length: 2,
+        };
+        assert!(batch_table.validate().is_empty());
+        let hierarchy = batch_table.hierarchy().unwrap().unwrap();
+        assert_eq!(hierarchy.instances_length(), 4);
+        let feature = batch_table.feature(1, Some(&hierarchy)).unwrap().unwrap();
+        assert_eq!(feature["height"], json!(20));
+        assert_eq!(feature["color"], json!("red"));
+        assert_eq!(feature["address"], json!("100 Main St"));
+    }
+}
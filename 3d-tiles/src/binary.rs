@@ -0,0 +1,227 @@
+//! Helpers for the binary layout shared by all tile formats.
+
+use crate::error::{Error, Section};
+use crate::limits::Limits;
+use serde::Serialize;
+use std::io::Read;
+
+/// Sections of a tile must start and end on an 8-byte boundary.
+pub(crate) const ALIGNMENT: usize = 8;
+
+/// Pad `section` with `fill` so that it ends on an 8-byte boundary, given that it starts at
+/// byte `offset` of the tile.
+///
+/// JSON sections are padded with spaces (`b' '`), binary sections with zeros.
+pub(crate) fn pad(mut section: Vec<u8>, offset: usize, fill: u8) -> Vec<u8> {
+    let end = offset + section.len();
+    let padding = (ALIGNMENT - end % ALIGNMENT) % ALIGNMENT;
+    section.resize(section.len() + padding, fill);
+    section
+}
+
+/// Lay out the sections following a tile header of `header_length` bytes for writing, each
+/// given with the byte it is padded with.
+///
+/// If every section has the length `declared` in the header the tile was read with, the
+/// sections are kept unchanged. This reproduces unchanged tiles exactly, including tiles of
+/// older tools with 4-byte alignment. Otherwise each section is padded to end on an 8-byte
+/// boundary.
+pub(crate) fn layout(
+    header_length: usize,
+    sections: Vec<(Vec<u8>, u8)>,
+    declared: &[Option<usize>],
+) -> Vec<Vec<u8>> {
+    let unchanged = sections.len() == declared.len()
+        && sections
+            .iter()
+            .zip(declared)
+            .all(|((section, _), declared)| Some(section.len()) == *declared);
+    let mut offset = header_length;
+    sections
+        .into_iter()
+        .map(|(section, fill)| {
+            let section = if unchanged {
+                section
+            } else {
+                pad(section, offset, fill)
+            };
+            offset += section.len();
+            section
+        })
+        .collect()
+}
+
+/// JSON section of a table with `header`.
+///
+/// `raw` is the JSON the table was read from. It is returned as long as it has the content
+/// of `header`, so that unchanged tables keep their formatting and padding.
+pub(crate) fn json_section<T: Serialize>(header: &T, raw: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let value = serde_json::to_value(header).map_err(Error::Json)?;
+    match raw {
+        Some(raw)
+            if serde_json::from_slice::<serde_json::Value>(raw).ok() == Some(value.clone()) =>
+        {
+            Ok(raw.to_vec())
+        }
+        _ => serde_json::to_vec(&value).map_err(Error::Json),
+    }
+}
+
+/// Check the lengths declared in a tile header against `limits`, before reading any section.
+///
+/// `sections` follow the header of `header_length` bytes in order, their total length must
+/// not exceed `byte_length`.
+pub(crate) fn check_header(
+    byte_length: u32,
+    header_length: usize,
+    sections: &[(Section, u32)],
+    limits: &Limits,
+) -> Result<(), Error> {
+    let byte_length = u64::from(byte_length);
+    if byte_length > limits.max_byte_length {
+        return Err(Error::LimitExceeded {
+            section: Section::Header,
+            offset: 0,
+            length: byte_length,
+            limit: limits.max_byte_length,
+        });
+    }
+    let mut offset = header_length as u64;
+    for (section, length) in sections {
+        let length = u64::from(*length);
+        let is_json = *section == Section::FeatureTableJson || *section == Section::BatchTableJson;
+        if is_json && length > limits.max_json_byte_length {
+            return Err(Error::LimitExceeded {
+                section: *section,
+                offset,
+                length,
+                limit: limits.max_json_byte_length,
+            });
+        }
+        offset += length;
+    }
+    if offset > byte_length {
+        return Err(Error::ByteLength {
+            section: Section::Header,
+            offset: 8,
+            declared: byte_length,
+            actual: offset,
+        });
+    }
+    Ok(())
+}
+
+/// Errors for the `sections` following the header which don't end on an 8-byte boundary.
+pub(crate) fn check_alignment(header_length: usize, sections: &[(Section, u32)]) -> Vec<Error> {
+    let mut errors = Vec::new();
+    let mut offset = header_length as u64;
+    for (section, length) in sections {
+        let end = offset + u64::from(*length);
+        if *length > 0 && end % ALIGNMENT as u64 != 0 {
+            errors.push(Error::Padding {
+                section: *section,
+                offset,
+                alignment: ALIGNMENT,
+            });
+        }
+        offset = end;
+    }
+    errors
+}
+
+/// Add the error of `result` to `errors`.
+pub(crate) fn collect<T>(errors: &mut Vec<Error>, result: Result<T, Error>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(error) => {
+            errors.push(error);
+            None
+        }
+    }
+}
+
+/// Read `length` bytes of `section`, which starts at byte `offset` of the tile.
+pub(crate) fn read_section<R: Read>(
+    reader: &mut R,
+    section: Section,
+    offset: usize,
+    length: usize,
+) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    reader
+        .take(length as u64)
+        .read_to_end(&mut buf)
+        .map_err(Error::Io)?;
+    if buf.len() < length {
+        return Err(Error::Truncated {
+            section,
+            offset: offset as u64,
+            expected: length as u64,
+            found: buf.len() as u64,
+        });
+    }
+    Ok(buf)
+}
+
+/// Data of the property `name` with `count` elements of `size` bytes each, starting at
+/// `byte_offset` of the binary `section` `body`.
+pub(crate) fn body_slice<'a>(
+    body: &'a [u8],
+    section: Section,
+    name: &str,
+    byte_offset: usize,
+    count: usize,
+    size: usize,
+) -> Result<&'a [u8], Error> {
+    let length = count.checked_mul(size);
+    length
+        .and_then(|len| len.checked_add(byte_offset))
+        .and_then(|end| body.get(byte_offset..end))
+        .ok_or_else(|| Error::PropertyReference {
+            section,
+            property: name.to_string(),
+            offset: byte_offset as u64,
+            length: length.map_or(u64::MAX, |len| len as u64),
+            available: body.len() as u64,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pad_to_boundary() {
+        assert_eq!(pad(b"{}".to_vec(), 28, b' '), b"{}  ".to_vec());
+        assert_eq!(pad(vec![1; 8], 0, 0), vec![1; 8]);
+        assert_eq!(pad(vec![1], 0, 0), vec![1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn layout_keeps_declared_sections() {
+        let sections = vec![(b"{}  ".to_vec(), b' '), (vec![1, 2], 0)];
+        let unchanged = layout(28, sections.clone(), &[Some(4), Some(2)]);
+        assert_eq!(unchanged, vec![b"{}  ".to_vec(), vec![1, 2]]);
+        let padded = layout(24, sections, &[Some(4), Some(3)]);
+        assert_eq!(
+            padded,
+            vec![b"{}      ".to_vec(), vec![1, 2, 0, 0, 0, 0, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn json_section_keeps_unchanged_json() {
+        let raw = br#"{"b":1.50,"a":2}  "#;
+        let value = serde_json::json!({"a": 2, "b": 1.5});
+        assert_eq!(json_section(&value, Some(raw)).unwrap(), raw.to_vec());
+        let edited = serde_json::json!({"a": 3, "b": 1.5});
+        assert_eq!(
+            json_section(&edited, Some(raw)).unwrap(),
+            br#"{"a":3,"b":1.5}"#.to_vec()
+        );
+        assert_eq!(
+            json_section(&value, None).unwrap(),
+            br#"{"a":2,"b":1.5}"#.to_vec()
+        );
+    }
+}
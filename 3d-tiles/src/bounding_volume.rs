@@ -0,0 +1,954 @@
+//! Typed bounding volumes and geometric operations.
+//!
+//! <https://github.com/CesiumGS/3d-tiles/tree/1.0/specification#bounding-volumes>
+
+use crate::geodesy::{east_north_up_axes, ecef_to_geodetic, geodetic_to_ecef, WGS84_A};
+use crate::implicit::TileCoordinate;
+use crate::math::{add, cross, dot, length, normalize, scale, sub};
+use crate::matrix::Matrix4;
+use serde_derive::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// A box, region or sphere.
+///
+/// Serialized as the `box`, `region` or `sphere` property of a bounding volume.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Volume {
+    #[serde(rename = "box")]
+    Box(OrientedBox),
+    #[serde(rename = "region")]
+    Region(Region),
+    #[serde(rename = "sphere")]
+    Sphere(Sphere),
+}
+
+/// An oriented bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f64; 12]", into = "[f64; 12]")]
+pub struct OrientedBox {
+    /// Center of the box.
+    pub center: [f64; 3],
+    /// Direction and half-length of the x, y and z axis.
+    pub half_axes: [[f64; 3]; 3],
+}
+
+/// A geographic region in EPSG:4979 coordinates.
+///
+/// Longitudes and latitudes are in radians, heights are in meters above the WGS84 ellipsoid.
+/// A region crossing the antimeridian has `east < west`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f64; 6]", into = "[f64; 6]")]
+pub struct Region {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+    pub minimum_height: f64,
+    pub maximum_height: f64,
+}
+
+/// A bounding sphere.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f64; 4]", into = "[f64; 4]")]
+pub struct Sphere {
+    /// Center of the sphere.
+    pub center: [f64; 3],
+    /// Radius in meters.
+    pub radius: f64,
+}
+
+impl From<[f64; 12]> for OrientedBox {
+    fn from(v: [f64; 12]) -> Self {
+        OrientedBox {
+            center: [v[0], v[1], v[2]],
+            half_axes: [[v[3], v[4], v[5]], [v[6], v[7], v[8]], [v[9], v[10], v[11]]],
+        }
+    }
+}
+
+impl From<OrientedBox> for [f64; 12] {
+    fn from(b: OrientedBox) -> Self {
+        let [c, x, y, z] = [b.center, b.half_axes[0], b.half_axes[1], b.half_axes[2]];
+        [
+            c[0], c[1], c[2], x[0], x[1], x[2], y[0], y[1], y[2], z[0], z[1], z[2],
+        ]
+    }
+}
+
+impl From<[f64; 6]> for Region {
+    fn from(v: [f64; 6]) -> Self {
+        Region {
+            west: v[0],
+            south: v[1],
+            east: v[2],
+            north: v[3],
+            minimum_height: v[4],
+            maximum_height: v[5],
+        }
+    }
+}
+
+impl From<Region> for [f64; 6] {
+    fn from(r: Region) -> Self {
+        [
+            r.west,
+            r.south,
+            r.east,
+            r.north,
+            r.minimum_height,
+            r.maximum_height,
+        ]
+    }
+}
+
+impl From<[f64; 4]> for Sphere {
+    fn from(v: [f64; 4]) -> Self {
+        Sphere {
+            center: [v[0], v[1], v[2]],
+            radius: v[3],
+        }
+    }
+}
+
+impl From<Sphere> for [f64; 4] {
+    fn from(s: Sphere) -> Self {
+        [s.center[0], s.center[1], s.center[2], s.radius]
+    }
+}
+
+impl Volume {
+    /// Apply a tile transform.
+    ///
+    /// Regions are not transformed, since they are defined in EPSG:4979 coordinates.
+    pub fn transform(&self, matrix: &Matrix4) -> Volume {
+        match self {
+            Volume::Box(b) => Volume::Box(b.transform(matrix)),
+            Volume::Region(r) => Volume::Region(*r),
+            Volume::Sphere(s) => Volume::Sphere(s.transform(matrix)),
+        }
+    }
+
+    /// Box enclosing the volume.
+    pub fn to_box(&self) -> OrientedBox {
+        match self {
+            Volume::Box(b) => *b,
+            Volume::Region(r) => r.to_box(),
+            Volume::Sphere(s) => s.to_box(),
+        }
+    }
+
+    /// Sphere enclosing the volume.
+    pub fn to_sphere(&self) -> Sphere {
+        match self {
+            Volume::Box(b) => b.to_sphere(),
+            Volume::Region(r) => r.to_sphere(),
+            Volume::Sphere(s) => *s,
+        }
+    }
+
+    /// Whether the point is inside the volume. Points of regions are earth-centered.
+    pub fn contains(&self, point: [f64; 3]) -> bool {
+        match self {
+            Volume::Box(b) => b.contains(point),
+            Volume::Region(r) => r.contains(point),
+            Volume::Sphere(s) => s.contains(point),
+        }
+    }
+
+    /// Distance from the point to the volume, zero if the point is inside.
+    pub fn distance(&self, point: [f64; 3]) -> f64 {
+        match self {
+            Volume::Box(b) => b.distance(point),
+            Volume::Region(r) => r.distance(point),
+            Volume::Sphere(s) => s.distance(point),
+        }
+    }
+
+    /// Whether the volumes overlap.
+    ///
+    /// Regions are compared with other volume types by their enclosing box.
+    pub fn intersects(&self, other: &Volume) -> bool {
+        match (self, other) {
+            (Volume::Region(a), Volume::Region(b)) => a.intersects(b),
+            (Volume::Sphere(a), Volume::Sphere(b)) => a.intersects(b),
+            (Volume::Sphere(s), other) | (other, Volume::Sphere(s)) => {
+                other.distance(s.center) <= s.radius
+            }
+            (a, b) => a.to_box().intersects(&b.to_box()),
+        }
+    }
+
+    /// Whether `other` lies inside the volume, allowing a distance of `tolerance`.
+    ///
+    /// Two regions are compared by their bounds, with `tolerance` converted to radians at the
+    /// equator. Otherwise the corners of boxes, the extreme points of spheres and a grid of
+    /// positions on the boundary of regions are tested.
+    pub fn contains_volume(&self, other: &Volume, tolerance: f64) -> bool {
+        let points = match (self, other) {
+            (Volume::Region(a), Volume::Region(b)) => return a.contains_region(b, tolerance),
+            (Volume::Sphere(a), Volume::Sphere(b)) => {
+                return length(sub(b.center, a.center)) + b.radius <= a.radius + tolerance
+            }
+            (_, Volume::Box(b)) => b.corners().to_vec(),
+            (_, Volume::Region(r)) => r.boundary_points(),
+            (parent, Volume::Sphere(s)) => {
+                let axes = match parent {
+                    Volume::Box(b) => b.unit_axes().0,
+                    _ => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                };
+                let mut points = Vec::with_capacity(6);
+                for axis in &axes {
+                    points.push(add(s.center, scale(*axis, s.radius)));
+                    points.push(sub(s.center, scale(*axis, s.radius)));
+                }
+                points
+            }
+        };
+        points
+            .into_iter()
+            .all(|point| self.distance(point) <= tolerance)
+    }
+
+    /// Volume of the tile at `coordinate` in an implicit subdivision of the volume, `None`
+    /// for spheres, which can't be subdivided.
+    pub fn subdivide(&self, coordinate: &TileCoordinate) -> Option<Volume> {
+        match self {
+            Volume::Box(b) => Some(Volume::Box(b.subdivide(coordinate))),
+            Volume::Region(r) => Some(Volume::Region(r.subdivide(coordinate))),
+            Volume::Sphere(_) => None,
+        }
+    }
+
+    /// Volume enclosing both volumes.
+    ///
+    /// The result has the type of `self`, except for the union of a region with another
+    /// volume type, which is a box.
+    pub fn union(&self, other: &Volume) -> Volume {
+        match (self, other) {
+            (Volume::Region(a), Volume::Region(b)) => Volume::Region(a.union(b)),
+            (a @ Volume::Region(_), b) | (a, b @ Volume::Region(_)) => {
+                Volume::Box(a.to_box().union(&b.to_box()))
+            }
+            (Volume::Sphere(a), b) => Volume::Sphere(a.union(&b.to_sphere())),
+            (Volume::Box(a), Volume::Sphere(b)) => Volume::Box(a.union_sphere(b)),
+            (a, b) => Volume::Box(a.to_box().union(&b.to_box())),
+        }
+    }
+}
+
+impl OrientedBox {
+    /// Apply a transform.
+    pub fn transform(&self, matrix: &Matrix4) -> OrientedBox {
+        OrientedBox {
+            center: matrix.transform_point(self.center),
+            half_axes: [
+                matrix.transform_vector(self.half_axes[0]),
+                matrix.transform_vector(self.half_axes[1]),
+                matrix.transform_vector(self.half_axes[2]),
+            ],
+        }
+    }
+
+    /// Box of the tile at `coordinate` in an implicit subdivision of the box. A quadtree
+    /// divides the x and y axes, an octree all three axes.
+    pub fn subdivide(&self, coordinate: &TileCoordinate) -> OrientedBox {
+        let mut center = self.center;
+        let mut half_axes = self.half_axes;
+        for (axis, (min, max)) in half_axes.iter_mut().zip(&subdivision_bounds(coordinate)) {
+            // Bounds in [0, 1] along the axis, mapped to [-1, 1]
+            center = add(center, scale(*axis, min + max - 1.0));
+            *axis = scale(*axis, max - min);
+        }
+        OrientedBox { center, half_axes }
+    }
+
+    /// The eight corners of the box.
+    pub fn corners(&self) -> [[f64; 3]; 8] {
+        let [x, y, z] = self.half_axes;
+        let mut corners = [[0.0; 3]; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let sign = |bit| if i & bit == 0 { -1.0 } else { 1.0 };
+            *corner = add(
+                self.center,
+                add(scale(x, sign(1)), add(scale(y, sign(2)), scale(z, sign(4)))),
+            );
+        }
+        corners
+    }
+
+    /// Unit axes and half-lengths.
+    ///
+    /// Axes of a flat box with zero length are completed to an orthonormal basis.
+    fn unit_axes(&self) -> ([[f64; 3]; 3], [f64; 3]) {
+        let lengths = [
+            length(self.half_axes[0]),
+            length(self.half_axes[1]),
+            length(self.half_axes[2]),
+        ];
+        let mut axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let mut defined = [false; 3];
+        for i in 0..3 {
+            if lengths[i] > 0.0 {
+                axes[i] = scale(self.half_axes[i], 1.0 / lengths[i]);
+                defined[i] = true;
+            }
+        }
+        match defined {
+            [true, true, false] => axes[2] = normalize(cross(axes[0], axes[1])),
+            [true, false, true] => axes[1] = normalize(cross(axes[2], axes[0])),
+            [false, true, true] => axes[0] = normalize(cross(axes[1], axes[2])),
+            [true, true, true] | [false, false, false] => {}
+            _ => {
+                // A line segment, complete the basis with any perpendicular axes
+                let i = defined.iter().position(|d| *d).unwrap_or(0);
+                let axis = axes[i];
+                let helper = if axis[0].abs() < 0.9 {
+                    [1.0, 0.0, 0.0]
+                } else {
+                    [0.0, 1.0, 0.0]
+                };
+                let u = normalize(cross(axis, helper));
+                let v = cross(axis, u);
+                axes[(i + 1) % 3] = u;
+                axes[(i + 2) % 3] = v;
+            }
+        }
+        (axes, lengths)
+    }
+
+    /// Sphere enclosing the box.
+    pub fn to_sphere(&self) -> Sphere {
+        let radius = self
+            .corners()
+            .iter()
+            .map(|corner| length(sub(*corner, self.center)))
+            .fold(0.0, f64::max);
+        Sphere {
+            center: self.center,
+            radius,
+        }
+    }
+
+    /// Whether the point is inside the box.
+    pub fn contains(&self, point: [f64; 3]) -> bool {
+        self.distance(point) == 0.0
+    }
+
+    /// Distance from the point to the box, zero if the point is inside.
+    pub fn distance(&self, point: [f64; 3]) -> f64 {
+        let (axes, lengths) = self.unit_axes();
+        let offset = sub(point, self.center);
+        let mut distance2 = 0.0;
+        for i in 0..3 {
+            let excess = (dot(offset, axes[i]).abs() - lengths[i]).max(0.0);
+            distance2 += excess * excess;
+        }
+        distance2.sqrt()
+    }
+
+    /// Whether the boxes overlap, using the separating axis test.
+    pub fn intersects(&self, other: &OrientedBox) -> bool {
+        let (a, _) = self.unit_axes();
+        let (b, _) = other.unit_axes();
+        let offset = sub(other.center, self.center);
+        let mut axes = Vec::with_capacity(15);
+        axes.extend_from_slice(&a);
+        axes.extend_from_slice(&b);
+        for u in &a {
+            for v in &b {
+                let axis = cross(*u, *v);
+                if length(axis) > 1e-9 {
+                    axes.push(normalize(axis));
+                }
+            }
+        }
+        let radius = |half_axes: &[[f64; 3]; 3], axis: [f64; 3]| {
+            half_axes.iter().map(|h| dot(*h, axis).abs()).sum::<f64>()
+        };
+        axes.iter().all(|axis| {
+            dot(offset, *axis).abs()
+                <= radius(&self.half_axes, *axis) + radius(&other.half_axes, *axis)
+        })
+    }
+
+    /// Box with the axes of `self` enclosing the box and the given extents along its unit
+    /// axes.
+    fn extended(&self, extents: impl Iterator<Item = [f64; 2]>) -> OrientedBox {
+        let (axes, lengths) = self.unit_axes();
+        let mut range = [[0.0; 2]; 3];
+        for i in 0..3 {
+            let center = dot(self.center, axes[i]);
+            range[i] = [center - lengths[i], center + lengths[i]];
+        }
+        for (i, [min, max]) in extents.enumerate() {
+            let axis = i % 3;
+            range[axis][0] = range[axis][0].min(min);
+            range[axis][1] = range[axis][1].max(max);
+        }
+        let mut center = [0.0; 3];
+        let mut half_axes = [[0.0; 3]; 3];
+        for i in 0..3 {
+            let [min, max] = range[i];
+            center = add(center, scale(axes[i], (min + max) / 2.0));
+            half_axes[i] = scale(axes[i], (max - min) / 2.0);
+        }
+        OrientedBox { center, half_axes }
+    }
+
+    /// Box with the axes of `self` enclosing both boxes.
+    pub fn union(&self, other: &OrientedBox) -> OrientedBox {
+        let (axes, _) = self.unit_axes();
+        let corners = other.corners();
+        self.extended(corners.iter().flat_map(|corner| {
+            axes.iter().map(move |axis| {
+                let d = dot(*corner, *axis);
+                [d, d]
+            })
+        }))
+    }
+
+    /// Box with the axes of `self` enclosing the box and the sphere.
+    pub fn union_sphere(&self, sphere: &Sphere) -> OrientedBox {
+        let (axes, _) = self.unit_axes();
+        self.extended(axes.iter().map(|axis| {
+            let d = dot(sphere.center, *axis);
+            [d - sphere.radius, d + sphere.radius]
+        }))
+    }
+}
+
+impl Region {
+    /// Width in radians, taking the antimeridian into account.
+    fn width(&self) -> f64 {
+        if self.east < self.west {
+            self.east - self.west + 2.0 * PI
+        } else {
+            self.east - self.west
+        }
+    }
+
+    /// Region of the tile at `coordinate` in an implicit subdivision of the region. A quadtree
+    /// divides longitude and latitude, an octree also the height.
+    pub fn subdivide(&self, coordinate: &TileCoordinate) -> Region {
+        let [(x0, x1), (y0, y1), (z0, z1)] = subdivision_bounds(coordinate);
+        let width = self.width();
+        let longitude = |fraction: f64| {
+            let longitude = self.west + width * fraction;
+            if longitude > PI {
+                longitude - 2.0 * PI
+            } else {
+                longitude
+            }
+        };
+        let latitude = |fraction| self.south + (self.north - self.south) * fraction;
+        let height =
+            |fraction| self.minimum_height + (self.maximum_height - self.minimum_height) * fraction;
+        Region {
+            west: longitude(x0),
+            south: latitude(y0),
+            east: longitude(x1),
+            north: latitude(y1),
+            minimum_height: height(z0),
+            maximum_height: height(z1),
+        }
+    }
+
+    /// Whether the longitude is within the region.
+    fn contains_longitude(&self, longitude: f64) -> bool {
+        if self.east < self.west {
+            longitude >= self.west || longitude <= self.east
+        } else {
+            longitude >= self.west && longitude <= self.east
+        }
+    }
+
+    /// Earth-centered positions on a grid over the region, at its minimum and maximum
+    /// height.
+    fn boundary_points(&self) -> Vec<[f64; 3]> {
+        const SAMPLES: usize = 8;
+        let width = self.width();
+        let mut latitudes: Vec<f64> = (0..=SAMPLES)
+            .map(|i| self.south + (self.north - self.south) * i as f64 / SAMPLES as f64)
+            .collect();
+        if self.south < 0.0 && self.north > 0.0 {
+            latitudes.push(0.0);
+        }
+        let mut points = Vec::new();
+        for i in 0..=SAMPLES {
+            let longitude = self.west + width * i as f64 / SAMPLES as f64;
+            for latitude in &latitudes {
+                for height in &[self.minimum_height, self.maximum_height] {
+                    points.push(geodetic_to_ecef(longitude, *latitude, *height));
+                }
+            }
+        }
+        points
+    }
+
+    /// Box enclosing the region, oriented along the east-north-up frame at its center.
+    ///
+    /// The extent is computed from a grid of sampled positions on the boundary of the
+    /// region, which is exact for regions spanning up to a hemisphere.
+    pub fn to_box(&self) -> OrientedBox {
+        let center_longitude = self.west + self.width() / 2.0;
+        let center_latitude = (self.south + self.north) / 2.0;
+        let origin = geodetic_to_ecef(center_longitude, center_latitude, 0.0);
+        let axes = east_north_up_axes(origin);
+
+        let mut range = [[f64::INFINITY, f64::NEG_INFINITY]; 3];
+        for point in self.boundary_points() {
+            let position = sub(point, origin);
+            for (axis, [min, max]) in axes.iter().zip(range.iter_mut()) {
+                let d = dot(position, *axis);
+                *min = min.min(d);
+                *max = max.max(d);
+            }
+        }
+
+        let mut center = origin;
+        let mut half_axes = [[0.0; 3]; 3];
+        for i in 0..3 {
+            let [min, max] = range[i];
+            center = add(center, scale(axes[i], (min + max) / 2.0));
+            half_axes[i] = scale(axes[i], (max - min) / 2.0);
+        }
+        OrientedBox { center, half_axes }
+    }
+
+    /// Sphere enclosing the region.
+    pub fn to_sphere(&self) -> Sphere {
+        self.to_box().to_sphere()
+    }
+
+    /// Whether the earth-centered point is inside the region.
+    pub fn contains(&self, point: [f64; 3]) -> bool {
+        let [longitude, latitude, height] = ecef_to_geodetic(point);
+        self.contains_longitude(longitude)
+            && latitude >= self.south
+            && latitude <= self.north
+            && height >= self.minimum_height
+            && height <= self.maximum_height
+    }
+
+    /// Distance from the earth-centered point to the nearest position within the geographic
+    /// bounds of the region, zero if the point is inside.
+    pub fn distance(&self, point: [f64; 3]) -> f64 {
+        if self.contains(point) {
+            return 0.0;
+        }
+        let [longitude, latitude, height] = ecef_to_geodetic(point);
+        let longitude = if self.contains_longitude(longitude) {
+            longitude
+        } else {
+            let angle = |a: f64, b: f64| {
+                let d = (a - b).abs() % (2.0 * PI);
+                d.min(2.0 * PI - d)
+            };
+            if angle(longitude, self.west) <= angle(longitude, self.east) {
+                self.west
+            } else {
+                self.east
+            }
+        };
+        let latitude = latitude.max(self.south).min(self.north);
+        let height = height.max(self.minimum_height).min(self.maximum_height);
+        length(sub(point, geodetic_to_ecef(longitude, latitude, height)))
+    }
+
+    /// Whether the regions overlap.
+    pub fn intersects(&self, other: &Region) -> bool {
+        let longitudes = self.contains_longitude(other.west)
+            || self.contains_longitude(other.east)
+            || other.contains_longitude(self.west)
+            || other.contains_longitude(self.east);
+        longitudes
+            && self.south <= other.north
+            && other.south <= self.north
+            && self.minimum_height <= other.maximum_height
+            && other.minimum_height <= self.maximum_height
+    }
+
+    /// Whether `other` lies inside the region, allowing `tolerance` meters.
+    fn contains_region(&self, other: &Region, tolerance: f64) -> bool {
+        let angle = tolerance / WGS84_A;
+        let longitudes = if self.width() + angle >= 2.0 * PI {
+            true
+        } else {
+            let offset = (other.west - self.west + angle).rem_euclid(2.0 * PI);
+            offset + other.width() <= self.width() + 2.0 * angle
+        };
+        longitudes
+            && other.south >= self.south - angle
+            && other.north <= self.north + angle
+            && other.minimum_height >= self.minimum_height - tolerance
+            && other.maximum_height <= self.maximum_height + tolerance
+    }
+
+    /// Smallest region enclosing both regions.
+    pub fn union(&self, other: &Region) -> Region {
+        let offset = |from: f64, to: f64| (to - from).rem_euclid(2.0 * PI);
+        let (a_width, b_width) = (self.width(), other.width());
+        // Longitude range starting at the west of either region
+        let from_a = a_width.max(offset(self.west, other.west) + b_width);
+        let from_b = b_width.max(offset(other.west, self.west) + a_width);
+        let (west, width) = if from_a <= from_b {
+            (self.west, from_a)
+        } else {
+            (other.west, from_b)
+        };
+        let (west, east) = if width >= 2.0 * PI {
+            (-PI, PI)
+        } else {
+            let east = west + width;
+            (west, if east > PI { east - 2.0 * PI } else { east })
+        };
+        Region {
+            west,
+            south: self.south.min(other.south),
+            east,
+            north: self.north.max(other.north),
+            minimum_height: self.minimum_height.min(other.minimum_height),
+            maximum_height: self.maximum_height.max(other.maximum_height),
+        }
+    }
+}
+
+impl Sphere {
+    /// Apply a transform. The radius is scaled by the largest scale factor.
+    pub fn transform(&self, matrix: &Matrix4) -> Sphere {
+        Sphere {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * matrix.max_scale(),
+        }
+    }
+
+    /// Axis-aligned box enclosing the sphere.
+    pub fn to_box(&self) -> OrientedBox {
+        let r = self.radius;
+        OrientedBox {
+            center: self.center,
+            half_axes: [[r, 0.0, 0.0], [0.0, r, 0.0], [0.0, 0.0, r]],
+        }
+    }
+
+    /// Whether the point is inside the sphere.
+    pub fn contains(&self, point: [f64; 3]) -> bool {
+        length(sub(point, self.center)) <= self.radius
+    }
+
+    /// Distance from the point to the sphere, zero if the point is inside.
+    pub fn distance(&self, point: [f64; 3]) -> f64 {
+        (length(sub(point, self.center)) - self.radius).max(0.0)
+    }
+
+    /// Whether the spheres overlap.
+    pub fn intersects(&self, other: &Sphere) -> bool {
+        length(sub(other.center, self.center)) <= self.radius + other.radius
+    }
+
+    /// Smallest sphere enclosing both spheres.
+    pub fn union(&self, other: &Sphere) -> Sphere {
+        let offset = sub(other.center, self.center);
+        let distance = length(offset);
+        if distance + other.radius <= self.radius {
+            return *self;
+        }
+        if distance + self.radius <= other.radius {
+            return *other;
+        }
+        let radius = (distance + self.radius + other.radius) / 2.0;
+        let center = add(
+            self.center,
+            scale(offset, (radius - self.radius) / distance),
+        );
+        Sphere { center, radius }
+    }
+}
+
+/// Bounds of the tile at `coordinate` along the x, y and z axis, as fractions of the root
+/// tile. The z axis isn't divided by a quadtree.
+fn subdivision_bounds(coordinate: &TileCoordinate) -> [(f64, f64); 3] {
+    let size = 0.5f64.powi(coordinate.level as i32);
+    let bounds = |index: u32| (index as f64 * size, (index as f64 + 1.0) * size);
+    [
+        bounds(coordinate.x),
+        bounds(coordinate.y),
+        coordinate.z.map_or((0.0, 1.0), bounds),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT_BOX: OrientedBox = OrientedBox {
+        center: [0.0; 3],
+        half_axes: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    fn region(west: f64, south: f64, east: f64, north: f64) -> Region {
+        Region {
+            west,
+            south,
+            east,
+            north,
+            minimum_height: 0.0,
+            maximum_height: 100.0,
+        }
+    }
+
+    #[test]
+    fn serialize_volumes() {
+        let json = r#"{"box":[0.0,0.0,0.0,1.0,0.0,0.0,0.0,1.0,0.0,0.0,0.0,1.0]}"#;
+        let volume: Volume = serde_json::from_str(json).unwrap();
+        assert_eq!(volume, Volume::Box(UNIT_BOX));
+        assert_eq!(serde_json::to_string(&volume).unwrap(), json);
+        let volume: Volume = serde_json::from_str(r#"{"sphere":[1,2,3,4]}"#).unwrap();
+        assert_eq!(
+            volume,
+            Volume::Sphere(Sphere {
+                center: [1.0, 2.0, 3.0],
+                radius: 4.0
+            })
+        );
+        let volume: Volume = serde_json::from_str(r#"{"region":[-1,-0.5,1,0.5,0,10]}"#).unwrap();
+        assert!(matches!(volume, Volume::Region(r) if r.west == -1.0 && r.maximum_height == 10.0));
+        assert!(serde_json::from_str::<Volume>(r#"{"sphere":[1,2,3]}"#).is_err());
+    }
+
+    #[test]
+    fn box_distance() {
+        let volume = Volume::Box(OrientedBox {
+            center: [10.0, 0.0, 0.0],
+            half_axes: [[0.0, 2.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        });
+        assert!(volume.contains([10.5, 1.5, 0.0]));
+        assert!(!volume.contains([11.5, 0.0, 0.0]));
+        assert_eq!(volume.distance([13.0, 0.0, 0.0]), 2.0);
+        assert_eq!(volume.distance([11.0, 5.0, 1.0]), 3.0);
+        assert!((volume.to_sphere().radius - 6.0f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn box_intersection() {
+        let other = |center| OrientedBox {
+            center,
+            half_axes: [[0.5, 0.5, 0.0], [-0.5, 0.5, 0.0], [0.0, 0.0, 1.0]],
+        };
+        assert!(UNIT_BOX.intersects(&other([1.5, 1.5, 0.0])));
+        assert!(!UNIT_BOX.intersects(&other([2.5, 2.5, 0.0])));
+        assert!(!UNIT_BOX.intersects(&other([0.0, 0.0, 2.5])));
+    }
+
+    #[test]
+    fn box_intersection_by_edge_axis() {
+        // Separated only along the cross product of two edges
+        let u = normalize([3.0, -2.0, -2.0]);
+        let v = normalize(cross(u, [1.0, 1.0, 1.0]));
+        let w = cross(u, v);
+        let rod = |center| OrientedBox {
+            center,
+            half_axes: [scale(u, 2.0), scale(v, 0.5), scale(w, 0.5)],
+        };
+        assert!(!UNIT_BOX.intersects(&rod([1.5, 0.0, 2.0])));
+        assert!(UNIT_BOX.intersects(&rod([1.2, 0.0, 1.6])));
+    }
+
+    #[test]
+    fn sphere_operations() {
+        let a = Sphere {
+            center: [0.0; 3],
+            radius: 1.0,
+        };
+        let b = Sphere {
+            center: [4.0, 0.0, 0.0],
+            radius: 1.0,
+        };
+        assert!(!a.intersects(&b));
+        assert_eq!(a.distance([3.0, 0.0, 0.0]), 2.0);
+        let union = a.union(&b);
+        assert_eq!(union.center, [2.0, 0.0, 0.0]);
+        assert_eq!(union.radius, 3.0);
+        assert_eq!(union.union(&a), union);
+        let (sphere, unit_box) = (Volume::Sphere(b), Volume::Box(UNIT_BOX));
+        assert!(!sphere.intersects(&unit_box));
+        assert!(Volume::Sphere(union).contains_volume(&Volume::Sphere(a), 0.0));
+        assert!(!unit_box.contains_volume(&sphere, 0.0));
+        let inner = Volume::Sphere(Sphere {
+            center: [0.5, 0.0, 0.0],
+            radius: 0.5,
+        });
+        assert!(unit_box.contains_volume(&inner, 0.0));
+    }
+
+    #[test]
+    fn transform_volumes() {
+        let mut matrix = Matrix4::from_translation([10.0, 0.0, 0.0]);
+        matrix.0[0] = 2.0;
+        let transformed = Volume::Box(UNIT_BOX).transform(&matrix);
+        assert_eq!(
+            transformed,
+            Volume::Box(OrientedBox {
+                center: [10.0, 0.0, 0.0],
+                half_axes: [[2.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            })
+        );
+        let sphere = Volume::Sphere(UNIT_BOX.to_sphere()).transform(&matrix);
+        assert!(matches!(sphere, Volume::Sphere(s) if s.radius == 2.0 * 3.0f64.sqrt()));
+        let region = Volume::Region(region(0.0, 0.0, 0.1, 0.1));
+        assert_eq!(region.transform(&matrix), region);
+    }
+
+    #[test]
+    fn region_contains() {
+        let region = region(-0.1, 0.5, 0.1, 0.6);
+        assert!(region.contains(geodetic_to_ecef(0.0, 0.55, 50.0)));
+        assert!(!region.contains(geodetic_to_ecef(0.0, 0.55, 150.0)));
+        assert!(!region.contains(geodetic_to_ecef(0.2, 0.55, 50.0)));
+        assert!((region.distance(geodetic_to_ecef(0.0, 0.55, 150.0)) - 50.0).abs() < 1e-6);
+        let volume = Volume::Region(region);
+        let inner = Volume::Region(Region {
+            maximum_height: 50.0,
+            ..region
+        });
+        assert!(volume.contains_volume(&inner, 0.0));
+        assert!(!inner.contains_volume(&volume, 0.0));
+        assert!(inner.contains_volume(&volume, 50.0));
+        // The enclosing box contains the region
+        let enclosing = Volume::Box(region.to_box());
+        assert!(enclosing.contains_volume(&volume, 1e-6));
+    }
+
+    #[test]
+    fn region_across_antimeridian() {
+        let across = region(3.0, 0.0, -3.0, 0.1);
+        assert!(across.contains(geodetic_to_ecef(PI, 0.05, 10.0)));
+        assert!(!across.contains(geodetic_to_ecef(0.0, 0.05, 10.0)));
+        assert!(across.intersects(&region(3.1, 0.05, 3.12, 0.2)));
+        assert!(!across.intersects(&region(-2.0, 0.0, 2.0, 0.1)));
+        let union = region(3.0, 0.0, 3.1, 0.1).union(&region(-3.1, 0.0, -3.0, 0.1));
+        assert_eq!(union.west, 3.0);
+        assert!((union.east + 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn volume_union() {
+        let unit_box = Volume::Box(UNIT_BOX);
+        let shifted = Volume::Box(OrientedBox {
+            center: [2.0, 0.0, 0.0],
+            ..UNIT_BOX
+        });
+        assert_eq!(
+            unit_box.union(&shifted),
+            Volume::Box(OrientedBox {
+                center: [1.0, 0.0, 0.0],
+                half_axes: [[2.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            })
+        );
+        let sphere = Volume::Sphere(Sphere {
+            center: [0.0, 0.0, 3.0],
+            radius: 1.0,
+        });
+        let union = unit_box.union(&sphere);
+        assert!(union.contains_volume(&unit_box, 1e-9));
+        assert!(union.contains_volume(&sphere, 1e-9));
+
+        // A region with another volume type gives a box, independent of the order
+        let region = Volume::Region(region(-1.3, 0.69, -1.29, 0.7));
+        for union in &[sphere.union(&region), region.union(&sphere)] {
+            assert!(matches!(union, Volume::Box(_)));
+            assert!(union.contains_volume(&sphere, 1e-6));
+        }
+    }
+
+    fn coordinate(level: u32, x: u32, y: u32, z: Option<u32>) -> TileCoordinate {
+        TileCoordinate { level, x, y, z }
+    }
+
+    #[test]
+    fn subdivide_box() {
+        let quadrant = UNIT_BOX.subdivide(&coordinate(1, 1, 0, None));
+        assert_eq!(
+            quadrant,
+            OrientedBox {
+                center: [0.5, -0.5, 0.0],
+                half_axes: [[0.5, 0.0, 0.0], [0.0, 0.5, 0.0], [0.0, 0.0, 1.0]],
+            }
+        );
+        let octant = UNIT_BOX.subdivide(&coordinate(2, 0, 3, Some(1)));
+        assert_eq!(octant.center, [-0.75, 0.75, -0.25]);
+        assert_eq!(
+            octant.half_axes,
+            [[0.25, 0.0, 0.0], [0.0, 0.25, 0.0], [0.0, 0.0, 0.25]]
+        );
+        // Rotated boxes are divided along their own axes
+        let rotated = OrientedBox {
+            center: [10.0, 0.0, 0.0],
+            half_axes: [[0.0, 2.0, 0.0], [-2.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        };
+        assert_eq!(
+            rotated.subdivide(&coordinate(1, 1, 1, None)).center,
+            [9.0, 1.0, 0.0]
+        );
+        assert_eq!(UNIT_BOX.subdivide(&coordinate(0, 0, 0, Some(0))), UNIT_BOX);
+    }
+
+    #[test]
+    fn subdivided_children_cover_parent() {
+        let parent = coordinate(2, 1, 2, Some(3));
+        let volume = UNIT_BOX.subdivide(&parent);
+        let union = parent
+            .children()
+            .map(|child| UNIT_BOX.subdivide(&child))
+            .fold(None, |union: Option<OrientedBox>, child| {
+                assert!(volume.contains(child.center));
+                Some(union.map_or(child, |union| union.union(&child)))
+            })
+            .unwrap();
+        for (a, b) in union.center.iter().zip(&volume.center) {
+            assert!((a - b).abs() < 1e-12);
+        }
+        for (a, b) in union
+            .half_axes
+            .iter()
+            .flatten()
+            .zip(volume.half_axes.iter().flatten())
+        {
+            assert!((a - b).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn subdivide_region() {
+        let parent = region(-1.0, 0.2, 1.0, 0.6);
+        let quadrant = parent.subdivide(&coordinate(1, 1, 0, None));
+        assert_eq!(
+            quadrant,
+            Region {
+                west: 0.0,
+                south: 0.2,
+                east: 1.0,
+                north: 0.4,
+                minimum_height: 0.0,
+                maximum_height: 100.0,
+            }
+        );
+        let octant = parent.subdivide(&coordinate(2, 3, 0, Some(2)));
+        assert_eq!((octant.west, octant.east), (0.5, 1.0));
+        assert_eq!(octant.south, 0.2);
+        assert!((octant.north - 0.3).abs() < 1e-12);
+        assert_eq!((octant.minimum_height, octant.maximum_height), (50.0, 75.0));
+
+        // Longitudes beyond the antimeridian wrap around
+        let across = region(3.1, 0.0, -2.9, 0.1);
+        let east = across.subdivide(&coordinate(1, 1, 0, None));
+        assert!((east.west - (0.1 - PI)).abs() < 1e-12);
+        assert!((east.east + 2.9).abs() < 1e-12);
+
+        let sphere = Volume::Sphere(Sphere {
+            center: [0.0; 3],
+            radius: 1.0,
+        });
+        assert_eq!(sphere.subdivide(&coordinate(1, 0, 0, None)), None);
+    }
+}
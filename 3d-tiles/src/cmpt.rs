@@ -0,0 +1,337 @@
+use crate::b3dm::B3dm;
+use crate::binary::{read_section, ALIGNMENT};
+use crate::error::{Error, Section};
+use crate::i3dm::I3dm;
+use crate::limits::Limits;
+use crate::pnts::Pnts;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::mem;
+
+/// Composite tile.
+///
+/// <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Composite/README.md>
+#[derive(Debug, Clone)]
+pub struct Cmpt {
+    pub header: CmptHeader,
+    /// Inner tiles in the order they are stored in the composite.
+    pub tiles: Vec<InnerTile>,
+}
+
+/// The header section of a .cmpt file.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct CmptHeader {
+    /// Must be `b"cmpt"`. This can be used to identify the content as a Composite tile.
+    pub magic: [u8; 4],
+    /// The version of the Composite format. It is currently `1`.
+    pub version: u32,
+    /// The length of the entire Composite tile, including this header and each inner tile, in bytes.
+    pub byte_length: u32,
+    /// The number of tiles in the Composite.
+    pub tiles_length: u32,
+}
+
+impl CmptHeader {
+    fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
+        use self::Error::Io;
+        let buf = read_section(&mut reader, Section::Header, 0, mem::size_of::<Self>())?;
+        let mut reader = &buf[..];
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(Io)?;
+        if &magic == b"cmpt" {
+            Ok(Self {
+                magic,
+                version: reader.read_u32::<LittleEndian>().map_err(Io)?,
+                byte_length: reader.read_u32::<LittleEndian>().map_err(Io)?,
+                tiles_length: reader.read_u32::<LittleEndian>().map_err(Io)?,
+            })
+        } else {
+            Err(Error::Magic(magic))
+        }
+    }
+}
+
+/// A tile contained in a Composite tile.
+#[derive(Debug, Clone)]
+pub enum InnerTile {
+    B3dm(B3dm),
+    I3dm(I3dm),
+    Pnts(Pnts),
+    Cmpt(Cmpt),
+}
+
+impl InnerTile {
+    /// Read the inner tile `index`, starting at byte `offset` of the Composite tile,
+    /// dispatching on its magic.
+    ///
+    /// Every tile format starts with `magic`, `version` and `byteLength`, so exactly
+    /// `byteLength` bytes are consumed from the reader, which must not exceed the `available`
+    /// bytes of the Composite. Returns the tile and its length.
+    fn from_reader<R: Read>(
+        mut reader: R,
+        index: usize,
+        offset: usize,
+        available: usize,
+        limits: &Limits,
+        depth: usize,
+    ) -> Result<(Self, usize), Error> {
+        use self::Error::Io;
+        let section = Section::InnerTile(index);
+        let mut buf = read_section(&mut reader, section, offset, 12)?;
+        let mut magic = [0; 4];
+        magic.copy_from_slice(&buf[0..4]);
+        let byte_length = (&buf[8..12]).read_u32::<LittleEndian>().map_err(Io)? as usize;
+        if byte_length < buf.len() || byte_length > available {
+            return Err(Error::ByteLength {
+                section,
+                offset: offset as u64,
+                declared: byte_length as u64,
+                actual: available as u64,
+            });
+        }
+        let mut rest = read_section(&mut reader, section, offset + 12, byte_length - 12)?;
+        buf.append(&mut rest);
+        let cursor = Cursor::new(buf);
+        let tile = match &magic {
+            b"b3dm" => B3dm::from_reader_with_limits(cursor, limits).map(InnerTile::B3dm),
+            b"i3dm" => I3dm::from_reader_with_limits(cursor, limits).map(InnerTile::I3dm),
+            b"pnts" => Pnts::from_reader_with_limits(cursor, limits).map(InnerTile::Pnts),
+            b"cmpt" => Cmpt::read(cursor, limits, depth + 1).map(InnerTile::Cmpt),
+            _ => Err(Error::Magic(magic)),
+        };
+        let tile = tile.map_err(|e| Error::InnerTile {
+            index,
+            offset: offset as u64,
+            source: Box::new(e),
+        })?;
+        Ok((tile, byte_length))
+    }
+
+    /// The `byteLength` of the inner tile.
+    fn byte_length(&self) -> u32 {
+        match self {
+            InnerTile::B3dm(tile) => tile.header.byte_length,
+            InnerTile::I3dm(tile) => tile.header.byte_length,
+            InnerTile::Pnts(tile) => tile.header.byte_length,
+            InnerTile::Cmpt(tile) => tile.header.byte_length,
+        }
+    }
+
+    fn validate(&self) -> Vec<Error> {
+        match self {
+            InnerTile::B3dm(tile) => tile.validate(),
+            InnerTile::I3dm(tile) => tile.validate(),
+            InnerTile::Pnts(tile) => tile.validate(),
+            InnerTile::Cmpt(tile) => tile.validate(),
+        }
+    }
+}
+
+impl Cmpt {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Read tile, checking the header of the Composite and of every inner tile against
+    /// `limits` before reading its sections.
+    pub fn from_reader_with_limits<R: Read>(reader: R, limits: &Limits) -> Result<Self, Error> {
+        Self::read(reader, limits, 1)
+    }
+
+    /// Read a Composite nested at `depth`.
+    fn read<R: Read>(mut reader: R, limits: &Limits, depth: usize) -> Result<Self, Error> {
+        let header = CmptHeader::from_reader(&mut reader)?;
+        if header.version != 1 {
+            return Err(Error::Version(header.version));
+        }
+        if depth > limits.max_composite_depth {
+            return Err(Error::format(
+                Section::Header,
+                format!(
+                    "Composite tiles nested deeper than {} levels",
+                    limits.max_composite_depth
+                ),
+            ));
+        }
+        let byte_length = u64::from(header.byte_length);
+        if byte_length > limits.max_byte_length {
+            return Err(Error::LimitExceeded {
+                section: Section::Header,
+                offset: 0,
+                length: byte_length,
+                limit: limits.max_byte_length,
+            });
+        }
+        let mut tiles = Vec::new();
+        let mut offset = mem::size_of::<CmptHeader>();
+        for index in 0..header.tiles_length as usize {
+            let available = (header.byte_length as usize).saturating_sub(offset);
+            let (tile, length) =
+                InnerTile::from_reader(&mut reader, index, offset, available, limits, depth)?;
+            tiles.push(tile);
+            offset += length;
+        }
+        Ok(Cmpt { header, tiles })
+    }
+
+    /// Check the binary layout of the tile.
+    ///
+    /// `byteLength` must equal the length of the header and the inner tiles, which must be
+    /// valid and end on an 8-byte boundary.
+    pub fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let mut offset = mem::size_of::<CmptHeader>() as u64;
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let byte_length = u64::from(tile.byte_length());
+            if byte_length % ALIGNMENT as u64 != 0 {
+                errors.push(Error::Padding {
+                    section: Section::InnerTile(index),
+                    offset,
+                    alignment: ALIGNMENT,
+                });
+            }
+            errors.extend(tile.validate().into_iter().map(|e| Error::InnerTile {
+                index,
+                offset,
+                source: Box::new(e),
+            }));
+            offset += byte_length;
+        }
+        if u64::from(self.header.byte_length) != offset {
+            errors.push(Error::ByteLength {
+                section: Section::Header,
+                offset: 8,
+                declared: u64::from(self.header.byte_length),
+                actual: offset,
+            });
+        }
+        errors
+    }
+}
+
+/// Read cmpt file
+pub fn extract(path: &str) -> Result<Cmpt, Error> {
+    let file = File::open(path).map_err(|e| Error::Io(e).with_path(path))?;
+    let mut reader = BufReader::new(file);
+    Cmpt::from_reader(&mut reader).map_err(|e| e.with_path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample;
+    use byteorder::{ByteOrder, WriteBytesExt};
+
+    /// Composite tile with `tiles`.
+    fn composite(tiles: &[&[u8]]) -> Vec<u8> {
+        let byte_length = 16 + tiles.iter().map(|tile| tile.len()).sum::<usize>();
+        let mut data = b"cmpt".to_vec();
+        for value in &[1, byte_length as u32, tiles.len() as u32] {
+            data.write_u32::<LittleEndian>(*value).unwrap();
+        }
+        for tile in tiles {
+            data.extend_from_slice(tile);
+        }
+        data
+    }
+
+    #[test]
+    fn read_inner_tiles() {
+        let b3dm = sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let i3dm = sample("TilesetWithTreeBillboards/tree.i3dm");
+        let nested = composite(&[&i3dm]);
+        let data = composite(&[&b3dm, &nested]);
+        let cmpt = Cmpt::from_reader(&data[..]).unwrap();
+        assert_eq!(cmpt.header.byte_length as usize, data.len());
+        assert_eq!(cmpt.tiles.len(), 2);
+        assert!(matches!(cmpt.tiles[0], InnerTile::B3dm(_)));
+        match cmpt.tiles[1] {
+            InnerTile::Cmpt(ref inner) => {
+                assert!(matches!(inner.tiles[..], [InnerTile::I3dm(_)]))
+            }
+            ref tile => panic!("unexpected inner tile {:?}", tile),
+        }
+        assert!(cmpt.validate().is_empty());
+    }
+
+    #[test]
+    fn unknown_inner_magic() {
+        let b3dm = sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let mut unknown = b3dm.clone();
+        unknown[0..4].copy_from_slice(b"abcd");
+        let data = composite(&[&b3dm, &unknown]);
+        match Cmpt::from_reader(&data[..]) {
+            Err(Error::InnerTile { index, source, .. }) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*source, Error::Magic(magic) if &magic == b"abcd"));
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn inner_tile_exceeding_composite() {
+        let b3dm = sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let mut data = composite(&[&b3dm]);
+        LittleEndian::write_u32(&mut data[8..12], 16 + b3dm.len() as u32 - 8);
+        assert!(matches!(
+            Cmpt::from_reader(&data[..]),
+            Err(Error::ByteLength {
+                section: Section::InnerTile(0),
+                offset: 16,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let b3dm = sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let data = composite(&[&b3dm]);
+        let truncated = &data[..data.len() - 100];
+        assert!(matches!(
+            Cmpt::from_reader(truncated),
+            Err(Error::Truncated {
+                section: Section::InnerTile(0),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let b3dm = sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let mut data = composite(&[&b3dm]);
+        for _ in 1..4 {
+            data = composite(&[&data]);
+        }
+        let limits = Limits::untrusted();
+        assert!(Cmpt::from_reader_with_limits(&data[..], &limits).is_ok());
+        let nested = composite(&[&data]);
+        match Cmpt::from_reader_with_limits(&nested[..], &limits) {
+            Err(Error::InnerTile { index: 0, .. }) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(Cmpt::from_reader(&nested[..]).is_ok());
+    }
+
+    #[test]
+    fn byte_length_limit() {
+        let b3dm = sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let data = composite(&[&b3dm]);
+        let limits = Limits {
+            max_byte_length: 1024,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Cmpt::from_reader_with_limits(&data[..], &limits),
+            Err(Error::LimitExceeded {
+                section: Section::Header,
+                limit: 1024,
+                ..
+            })
+        ));
+    }
+}
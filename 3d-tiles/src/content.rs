@@ -0,0 +1,113 @@
+//! Detection of tile content types.
+//!
+//! Content URIs don't need a file extension, so the type is detected from the data: binary
+//! formats by their magic, JSON by its top-level properties.
+
+use serde::de::IgnoredAny;
+use std::collections::HashMap;
+
+/// Type of tile content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// Batched 3D Model (`b3dm`).
+    B3dm,
+    /// Instanced 3D Model (`i3dm`).
+    I3dm,
+    /// Point Cloud (`pnts`).
+    Pnts,
+    /// Composite (`cmpt`).
+    Cmpt,
+    /// Binary glTF (`glb`).
+    Glb,
+    /// glTF JSON.
+    Gltf,
+    /// External tileset JSON.
+    Tileset,
+    /// Binary subtree of an implicit tileset.
+    Subtree,
+    Unknown,
+}
+
+impl ContentType {
+    /// Detect the type of `data`, which must be the complete content for JSON.
+    pub fn detect(data: &[u8]) -> ContentType {
+        match data.get(0..4) {
+            Some(b"b3dm") => ContentType::B3dm,
+            Some(b"i3dm") => ContentType::I3dm,
+            Some(b"pnts") => ContentType::Pnts,
+            Some(b"cmpt") => ContentType::Cmpt,
+            Some(b"glTF") => ContentType::Glb,
+            Some(b"subt") => ContentType::Subtree,
+            _ => detect_json(data),
+        }
+    }
+
+    /// Whether the content is glTF, either binary or JSON.
+    pub fn is_gltf(&self) -> bool {
+        *self == ContentType::Glb || *self == ContentType::Gltf
+    }
+}
+
+/// A JSON object with `root` is a tileset, one with `asset` but without `root` is glTF.
+fn detect_json(data: &[u8]) -> ContentType {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    if data.iter().find(|b| !b.is_ascii_whitespace()) != Some(&b'{') {
+        return ContentType::Unknown;
+    }
+    match serde_json::from_slice::<HashMap<String, IgnoredAny>>(data) {
+        Ok(properties) if properties.contains_key("root") => ContentType::Tileset,
+        Ok(properties) if properties.contains_key("asset") => ContentType::Gltf,
+        _ => ContentType::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_binary_formats() {
+        for (name, content_type) in &[
+            ("TilesetWithDiscreteLOD/dragon_low.b3dm", ContentType::B3dm),
+            ("TilesetWithTreeBillboards/tree.i3dm", ContentType::I3dm),
+            ("TilesetWithRequestVolume/points.pnts", ContentType::Pnts),
+        ] {
+            assert_eq!(ContentType::detect(&crate::sample(name)), *content_type);
+        }
+        assert_eq!(ContentType::detect(b"cmpt\x01\0\0\0"), ContentType::Cmpt);
+        assert_eq!(ContentType::detect(b"glTF\x02\0\0\0"), ContentType::Glb);
+        assert_eq!(ContentType::detect(b"subt\x01\0\0\0"), ContentType::Subtree);
+        assert_eq!(ContentType::detect(b"b3d"), ContentType::Unknown);
+        assert_eq!(ContentType::detect(b""), ContentType::Unknown);
+    }
+
+    #[test]
+    fn detect_json() {
+        let tileset = crate::sample("TilesetWithDiscreteLOD/tileset.json");
+        assert_eq!(ContentType::detect(&tileset), ContentType::Tileset);
+        let with_bom = [&b"\xEF\xBB\xBF \n"[..], &tileset].concat();
+        assert_eq!(ContentType::detect(&with_bom), ContentType::Tileset);
+        assert_eq!(
+            ContentType::detect(br#"{"asset": {"version": "2.0"}, "meshes": []}"#),
+            ContentType::Gltf
+        );
+        // Neither tileset nor glTF, or not a complete JSON object
+        assert_eq!(ContentType::detect(br#"{"root": 1"#), ContentType::Unknown);
+        assert_eq!(
+            ContentType::detect(br#"{"buffers": []}"#),
+            ContentType::Unknown
+        );
+        assert_eq!(
+            ContentType::detect(br#"[{"root": {}}]"#),
+            ContentType::Unknown
+        );
+    }
+
+    #[test]
+    fn gltf_types() {
+        assert!(ContentType::Glb.is_gltf());
+        assert!(ContentType::Gltf.is_gltf());
+        assert!(!ContentType::B3dm.is_gltf());
+        assert!(!ContentType::Tileset.is_gltf());
+    }
+}
@@ -0,0 +1,153 @@
+//! Decoding and encoding of compressed attributes.
+//!
+//! Oct-encoding maps unit vectors to two components, see
+//! <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#oct-encoded-normal-vectors>
+
+fn sign_not_zero(value: f32) -> f32 {
+    if value < 0.0 {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
+
+/// Decode an oct-encoded unit vector with components in the range `[0, range_max]`.
+pub fn oct_decode_in_range(x: u16, y: u16, range_max: u16) -> [f32; 3] {
+    let range_max = f32::from(range_max);
+    let mut x = f32::from(x) / range_max * 2.0 - 1.0;
+    let mut y = f32::from(y) / range_max * 2.0 - 1.0;
+    let z = 1.0 - x.abs() - y.abs();
+    if z < 0.0 {
+        let old_x = x;
+        x = (1.0 - y.abs()) * sign_not_zero(old_x);
+        y = (1.0 - old_x.abs()) * sign_not_zero(y);
+    }
+    normalize([x, y, z])
+}
+
+/// Oct-encode a unit vector into components in the range `[0, range_max]`.
+pub fn oct_encode_in_range(vector: [f32; 3], range_max: u16) -> [u16; 2] {
+    let [vx, vy, vz] = normalize(vector);
+    let sum = vx.abs() + vy.abs() + vz.abs();
+    let mut x = vx / sum;
+    let mut y = vy / sum;
+    if vz < 0.0 {
+        let old_x = x;
+        x = (1.0 - y.abs()) * sign_not_zero(old_x);
+        y = (1.0 - old_x.abs()) * sign_not_zero(y);
+    }
+    let to_snorm =
+        |v: f32| ((v.clamp(-1.0, 1.0) * 0.5 + 0.5) * f32::from(range_max)).round() as u16;
+    [to_snorm(x), to_snorm(y)]
+}
+
+/// Decode a `NORMAL_OCT16P` value.
+pub fn oct16p_decode(encoded: [u8; 2]) -> [f32; 3] {
+    oct_decode_in_range(u16::from(encoded[0]), u16::from(encoded[1]), 255)
+}
+
+/// Encode a unit vector as `NORMAL_OCT16P` value.
+pub fn oct16p_encode(vector: [f32; 3]) -> [u8; 2] {
+    let [x, y] = oct_encode_in_range(vector, 255);
+    [x as u8, y as u8]
+}
+
+/// Decode a `NORMAL_UP_OCT32P` or `NORMAL_RIGHT_OCT32P` value.
+pub fn oct32p_decode(encoded: [u16; 2]) -> [f32; 3] {
+    oct_decode_in_range(encoded[0], encoded[1], 65535)
+}
+
+/// Encode a unit vector as `NORMAL_UP_OCT32P` or `NORMAL_RIGHT_OCT32P` value.
+pub fn oct32p_encode(vector: [f32; 3]) -> [u16; 2] {
+    oct_encode_in_range(vector, 65535)
+}
+
+/// Decode a `RGB565` color into 8-bit components.
+pub fn rgb565_decode(color: u16) -> [u8; 3] {
+    let red = (color >> 11) & 0x1f;
+    let green = (color >> 5) & 0x3f;
+    let blue = color & 0x1f;
+    [
+        ((red * 255 + 15) / 31) as u8,
+        ((green * 255 + 31) / 63) as u8,
+        ((blue * 255 + 15) / 31) as u8,
+    ]
+}
+
+/// Encode a color with 8-bit components as `RGB565`.
+pub fn rgb565_encode(color: [u8; 3]) -> u16 {
+    let red = (u16::from(color[0]) * 31 + 127) / 255;
+    let green = (u16::from(color[1]) * 63 + 127) / 255;
+    let blue = (u16::from(color[2]) * 31 + 127) / 255;
+    (red << 11) | (green << 5) | blue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for i in 0..3 {
+            assert!(
+                (actual[i] - expected[i]).abs() <= tolerance,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    const VECTORS: &[[f32; 3]] = &[
+        [1.0, 0.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+        [0.6, -0.8, 0.0],
+        [-0.48, 0.6, -0.64],
+    ];
+
+    #[test]
+    fn oct_axes() {
+        assert_close(oct32p_decode([32768, 32768]), [0.0, 0.0, 1.0], 1e-4);
+        assert_close(oct32p_decode([65535, 32768]), [1.0, 0.0, 0.0], 1e-4);
+        assert_close(oct32p_decode([0, 0]), [0.0, 0.0, -1.0], 1e-6);
+        assert_eq!(oct16p_encode([0.0, 0.0, 1.0]), [128, 128]);
+    }
+
+    #[test]
+    fn oct_round_trip() {
+        for vector in VECTORS {
+            assert_close(oct32p_decode(oct32p_encode(*vector)), *vector, 1e-4);
+            assert_close(oct16p_decode(oct16p_encode(*vector)), *vector, 0.02);
+        }
+    }
+
+    #[test]
+    fn decoded_vectors_are_normalized() {
+        for x in (0..=255).step_by(15) {
+            for y in (0..=255).step_by(15) {
+                let [vx, vy, vz] = oct16p_decode([x, y]);
+                let length = (vx * vx + vy * vy + vz * vz).sqrt();
+                assert!((length - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn rgb565() {
+        assert_eq!(rgb565_decode(0xffff), [255, 255, 255]);
+        assert_eq!(rgb565_decode(0), [0, 0, 0]);
+        assert_eq!(rgb565_decode(0xf800), [255, 0, 0]);
+        assert_eq!(rgb565_decode(0x07e0), [0, 255, 0]);
+        assert_eq!(rgb565_decode(0x001f), [0, 0, 255]);
+        assert_eq!(rgb565_encode([255, 0, 0]), 0xf800);
+        for color in 0..=u16::MAX {
+            assert_eq!(rgb565_encode(rgb565_decode(color)), color);
+        }
+    }
+}
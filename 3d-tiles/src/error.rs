@@ -1,12 +1,364 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Section of a tile or tileset an error refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// The tile header.
+    Header,
+    FeatureTableJson,
+    FeatureTableBinary,
+    BatchTableJson,
+    BatchTableBinary,
+    /// The glTF field of a b3dm or i3dm tile.
+    Gltf,
+    /// An inner tile of a Composite tile, by index.
+    InnerTile(usize),
+    /// A tileset JSON.
+    Tileset,
+    /// The JSON of a subtree of an implicit tileset.
+    SubtreeJson,
+    /// The binary chunk of a subtree.
+    SubtreeBinary,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Header => write!(f, "header"),
+            Section::FeatureTableJson => write!(f, "Feature Table JSON"),
+            Section::FeatureTableBinary => write!(f, "Feature Table binary"),
+            Section::BatchTableJson => write!(f, "Batch Table JSON"),
+            Section::BatchTableBinary => write!(f, "Batch Table binary"),
+            Section::Gltf => write!(f, "glTF"),
+            Section::InnerTile(index) => write!(f, "inner tile {}", index),
+            Section::Tileset => write!(f, "tileset"),
+            Section::SubtreeJson => write!(f, "subtree JSON"),
+            Section::SubtreeBinary => write!(f, "subtree binary"),
+        }
+    }
+}
+
 /// Represents a tile loader error.
+///
+/// Byte offsets are relative to the start of the tile, except for property references, which
+/// are relative to the start of the binary section.
 #[derive(Debug)]
 pub enum Error {
     /// Io error occured.
-    Io(::std::io::Error),
+    Io(io::Error),
     /// Unsupported version.
     Version(u32),
     /// Wrong magic.
     Magic([u8; 4]),
     /// JSON decoding occured.
     Json(serde_json::error::Error),
+    /// The data ends before the end of a section.
+    Truncated {
+        section: Section,
+        offset: u64,
+        /// Declared length of the section.
+        expected: u64,
+        /// Number of bytes available.
+        found: u64,
+    },
+    /// A declared byte length is inconsistent with the lengths of the sections it contains.
+    ByteLength {
+        section: Section,
+        offset: u64,
+        declared: u64,
+        /// Length implied by the other sections.
+        actual: u64,
+    },
+    /// A section doesn't end on the required boundary.
+    Padding {
+        section: Section,
+        offset: u64,
+        alignment: usize,
+    },
+    /// A property references data outside of its binary section.
+    PropertyReference {
+        section: Section,
+        property: String,
+        offset: u64,
+        /// Length of the referenced data.
+        length: u64,
+        /// Length of the binary section.
+        available: u64,
+    },
+    /// An extension required for reading the content isn't supported. The offset of a tileset
+    /// is always 0.
+    UnsupportedExtension {
+        section: Section,
+        offset: u64,
+        name: String,
+    },
+    /// A length exceeds the configured [`Limits`](crate::limits::Limits).
+    LimitExceeded {
+        section: Section,
+        offset: u64,
+        length: u64,
+        limit: u64,
+    },
+    /// Error in an inner tile of a Composite tile, starting at byte `offset`.
+    InnerTile {
+        index: usize,
+        offset: u64,
+        source: Box<Error>,
+    },
+    /// A path of child indexes doesn't lead to a tile of the tileset.
+    TileNotFound { path: Vec<usize> },
+    /// Invalid or missing values in a section.
+    Format { section: Section, message: String },
+    /// Error while reading or writing a file.
+    File { path: PathBuf, source: Box<Error> },
+}
+
+impl Error {
+    /// Attach the path of the file the error occured in.
+    ///
+    /// An error which already has a path is returned unchanged.
+    pub fn with_path<P: Into<PathBuf>>(self, path: P) -> Self {
+        match self {
+            Error::File { .. } => self,
+            error => Error::File {
+                path: path.into(),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The path of the file the error occured in, if known.
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Error::File { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn format<S: Into<String>>(section: Section, message: S) -> Self {
+        Error::Format {
+            section,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Version(version) => write!(f, "unsupported version {}", version),
+            Error::Magic(magic) => write!(f, "unknown magic {:?}", String::from_utf8_lossy(magic)),
+            Error::Json(error) => write!(f, "invalid JSON: {}", error),
+            Error::Truncated {
+                section,
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} at byte {} is truncated: expected {} bytes, found {}",
+                section, offset, expected, found
+            ),
+            Error::ByteLength {
+                section,
+                offset,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "{} at byte {}: declared byte length {} is inconsistent with {}",
+                section, offset, declared, actual
+            ),
+            Error::Padding {
+                section,
+                offset,
+                alignment,
+            } => write!(
+                f,
+                "{} at byte {} is not padded to a multiple of {} bytes",
+                section, offset, alignment
+            ),
+            Error::PropertyReference {
+                section,
+                property,
+                offset,
+                length,
+                available,
+            } => write!(
+                f,
+                "{}: property `{}` at byte {} with {} bytes exceeds the section length {}",
+                section, property, offset, length, available
+            ),
+            Error::UnsupportedExtension {
+                section: Section::Tileset,
+                name,
+                ..
+            } => write!(f, "tileset: unsupported extension `{}`", name),
+            Error::UnsupportedExtension {
+                section,
+                offset,
+                name,
+            } => write!(
+                f,
+                "{} at byte {}: unsupported extension `{}`",
+                section, offset, name
+            ),
+            Error::LimitExceeded {
+                section,
+                offset,
+                length,
+                limit,
+            } => write!(
+                f,
+                "{} at byte {}: length {} exceeds the limit of {}",
+                section, offset, length, limit
+            ),
+            Error::InnerTile {
+                index,
+                offset,
+                source,
+            } => write!(f, "inner tile {} at byte {}: {}", index, offset, source),
+            Error::TileNotFound { path } => write!(f, "tile path {:?} not found", path),
+            Error::Format { section, message } => write!(f, "{}: {}", section, message),
+            Error::File { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Json(error) => Some(error),
+            Error::InnerTile { source, .. } | Error::File { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<serde_json::error::Error> for Error {
+    fn from(error: serde_json::error::Error) -> Self {
+        Error::Json(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b3dm::{self, B3dm};
+    use crate::loader::{FileSource, Source};
+    use std::error::Error as _;
+
+    #[test]
+    fn display() {
+        let truncated = Error::Truncated {
+            section: Section::FeatureTableBinary,
+            offset: 28,
+            expected: 12,
+            found: 4,
+        };
+        assert_eq!(
+            truncated.to_string(),
+            "Feature Table binary at byte 28 is truncated: expected 12 bytes, found 4"
+        );
+        let inner = Error::InnerTile {
+            index: 1,
+            offset: 96,
+            source: Box::new(Error::Magic(*b"abcd")),
+        };
+        assert_eq!(
+            inner.to_string(),
+            "inner tile 1 at byte 96: unknown magic \"abcd\""
+        );
+        let extension = Error::UnsupportedExtension {
+            section: Section::Tileset,
+            offset: 0,
+            name: "EXT_unknown".to_string(),
+        };
+        assert_eq!(
+            extension.to_string(),
+            "tileset: unsupported extension `EXT_unknown`"
+        );
+        assert_eq!(
+            Error::format(Section::InnerTile(2), "invalid").to_string(),
+            "inner tile 2: invalid"
+        );
+        assert_eq!(
+            Error::TileNotFound { path: vec![0, 3] }.to_string(),
+            "tile path [0, 3] not found"
+        );
+    }
+
+    #[test]
+    fn path() {
+        let error = Error::Version(2);
+        assert_eq!(error.path(), None);
+        let error = error.with_path("a.b3dm").with_path("tileset.json");
+        assert_eq!(error.path(), Some(&PathBuf::from("a.b3dm")));
+        assert_eq!(error.to_string(), "a.b3dm: unsupported version 2");
+        match error.source().and_then(|e| e.downcast_ref::<Error>()) {
+            Some(Error::Version(2)) => {}
+            source => panic!("unexpected source {:?}", source),
+        }
+    }
+
+    #[test]
+    fn io_errors_with_path() {
+        let error = FileSource.read("missing/tileset.json").unwrap_err();
+        assert_eq!(error.path(), Some(&PathBuf::from("missing/tileset.json")));
+        let io = error
+            .source()
+            .and_then(|e| e.source())
+            .and_then(|e| e.downcast_ref::<io::Error>())
+            .unwrap();
+        assert_eq!(io.kind(), io::ErrorKind::NotFound);
+
+        let error = b3dm::extract_gltf("missing.b3dm").unwrap_err();
+        assert!(
+            matches!(error, Error::File { ref source, .. } if matches!(**source, Error::Io(_)))
+        );
+    }
+
+    #[test]
+    fn truncated_input() {
+        let data = crate::sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let error = B3dm::from_reader(&data[..20]).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::Truncated {
+                    section: Section::Header,
+                    offset: 0,
+                    expected: 28,
+                    found: 20
+                }
+            ),
+            "{:?}",
+            error
+        );
+        let error = B3dm::from_reader(&data[..100]).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::Truncated {
+                    section: Section::Gltf,
+                    offset: 48,
+                    found: 52,
+                    ..
+                }
+            ),
+            "{:?}",
+            error
+        );
+    }
 }
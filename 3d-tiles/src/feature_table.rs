@@ -1,9 +1,12 @@
+use crate::binary::body_slice;
+use crate::error::{Error, Section};
+use byteorder::{ByteOrder, LittleEndian};
 use serde_derive::{Deserialize, Serialize};
 
 /// A user-defined property which specifies per-feature application-specific metadata in a
 /// tile. Values either can be defined directly in the JSON as an array, or can refer to
 /// sections in the binary body with a `BinaryBodyReference` object.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Property {
     BinaryBodyReference(BinaryBodyReference),
@@ -13,25 +16,25 @@ pub enum Property {
 
 /// An object defining the reference to a section of the binary body of the features table
 /// where the property values are stored if not defined directly in the JSON.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BinaryBodyReference {
     /// The offset into the buffer in bytes.
     #[serde(rename = "byteOffset")]
     pub byte_offset: usize,
     /// The datatype of components in the property. This is defined only if the semantic allows
     /// for overriding the implicit component type. These cases are specified in each tile format.
-    #[serde(rename = "componentType")]
+    #[serde(rename = "componentType", skip_serializing_if = "Option::is_none")]
     pub component_type: Option<ComponentType>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalPropertyScalarClass {
     /// The offset into the buffer in bytes.
     #[serde(rename = "byteOffset")]
     pub byte_offset: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalPropertyCartesian3Class {
     /// The offset into the buffer in bytes.
     #[serde(rename = "byteOffset")]
@@ -43,28 +46,115 @@ pub struct GlobalPropertyCartesian3Class {
 /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Batched3DModel/README.md#semantics).
 ///
 /// An object defining a global numeric property value for all features.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum GlobalPropertyScalar {
+    Integer(u32),
     Double(f64),
     DoubleArray(Vec<f64>),
     GlobalPropertyScalarClass(GlobalPropertyScalarClass),
 }
 
+impl GlobalPropertyScalar {
+    /// Resolve the value of the semantic `name`, which is stored as `UNSIGNED_INT` when it
+    /// refers to the binary body.
+    pub fn value(&self, name: &str, body: &[u8]) -> Result<f64, Error> {
+        match self {
+            GlobalPropertyScalar::Integer(value) => Ok(f64::from(*value)),
+            GlobalPropertyScalar::Double(value) => Ok(*value),
+            GlobalPropertyScalar::DoubleArray(values) if values.len() == 1 => Ok(values[0]),
+            GlobalPropertyScalar::DoubleArray(_) => Err(Error::format(
+                Section::FeatureTableJson,
+                format!("{} must have one component", name),
+            )),
+            GlobalPropertyScalar::GlobalPropertyScalarClass(reference) => {
+                let data = body_slice(
+                    body,
+                    Section::FeatureTableBinary,
+                    name,
+                    reference.byte_offset,
+                    1,
+                    4,
+                )?;
+                Ok(f64::from(LittleEndian::read_u32(data)))
+            }
+        }
+    }
+
+    /// Resolve the count `name`, e.g. `BATCH_LENGTH`, which must be a non-negative integer.
+    pub fn length(&self, name: &str, body: &[u8]) -> Result<usize, Error> {
+        to_length(name, self.value(name, body)?)
+    }
+}
+
+/// Convert a count read as `f64` to `usize`, rejecting negative and fractional values and
+/// values exceeding the `UNSIGNED_INT` range.
+fn to_length(name: &str, value: f64) -> Result<usize, Error> {
+    if value >= 0.0 && value.fract() == 0.0 && value <= f64::from(u32::MAX) {
+        Ok(value as usize)
+    } else {
+        Err(Error::format(
+            Section::FeatureTableJson,
+            format!("{} must be a non-negative integer, found {}", name, value),
+        ))
+    }
+}
+
 /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
 /// features. See the corresponding property semantic in
 /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Batched3DModel/README.md#semantics).
 ///
 /// An object defining a global 3-component numeric property values for all features.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum GlobalPropertyCartesian3 {
     DoubleArray(Vec<f64>),
     GlobalPropertyCartesian3Class(GlobalPropertyCartesian3Class),
 }
 
+impl GlobalPropertyCartesian3 {
+    /// Resolve the value of the semantic `name`, which is stored as `FLOAT` components when it
+    /// refers to the binary body.
+    pub fn value(&self, name: &str, body: &[u8]) -> Result<[f64; 3], Error> {
+        match self {
+            GlobalPropertyCartesian3::DoubleArray(values) if values.len() == 3 => {
+                Ok([values[0], values[1], values[2]])
+            }
+            GlobalPropertyCartesian3::DoubleArray(_) => Err(Error::format(
+                Section::FeatureTableJson,
+                format!("{} must have three components", name),
+            )),
+            GlobalPropertyCartesian3::GlobalPropertyCartesian3Class(reference) => {
+                let data = body_slice(
+                    body,
+                    Section::FeatureTableBinary,
+                    name,
+                    reference.byte_offset,
+                    3,
+                    4,
+                )?;
+                Ok([
+                    f64::from(LittleEndian::read_f32(&data[0..4])),
+                    f64::from(LittleEndian::read_f32(&data[4..8])),
+                    f64::from(LittleEndian::read_f32(&data[8..12])),
+                ])
+            }
+        }
+    }
+}
+
+/// Dequantize a `POSITION_QUANTIZED` value with `QUANTIZED_VOLUME_OFFSET` and
+/// `QUANTIZED_VOLUME_SCALE`.
+pub fn dequantize(quantized: [u16; 3], offset: [f64; 3], scale: [f64; 3]) -> [f64; 3] {
+    let mut position = [0.0; 3];
+    for i in 0..3 {
+        position[i] = offset[i] + f64::from(quantized[i]) / 65535.0 * scale[i];
+    }
+    position
+}
+
 /// Specifies if the property is a scalar or vector.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Type {
     #[serde(rename = "SCALAR")]
     Scalar,
@@ -77,7 +167,7 @@ pub enum Type {
 }
 
 /// The datatype of components in the property.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ComponentType {
     #[serde(rename = "BYTE")]
     Byte,
@@ -97,7 +187,7 @@ pub enum ComponentType {
     UnsignedShort,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GlobalPropertyCartesian4Class {
     /// The offset into the buffer in bytes.
     #[serde(rename = "byteOffset")]
@@ -109,20 +199,43 @@ pub struct GlobalPropertyCartesian4Class {
 /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
 ///
 /// An object defining a global numeric property value for all features.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum PurpleGlobalPropertyScalar {
+    Integer(u32),
     Double(f64),
     DoubleArray(Vec<f64>),
     GlobalPropertyScalar(GlobalPropertyScalar),
 }
 
+impl PurpleGlobalPropertyScalar {
+    /// Resolve the value of the semantic `name`, which is stored as `UNSIGNED_INT` when it
+    /// refers to the binary body.
+    pub fn value(&self, name: &str, body: &[u8]) -> Result<f64, Error> {
+        match self {
+            PurpleGlobalPropertyScalar::Integer(value) => Ok(f64::from(*value)),
+            PurpleGlobalPropertyScalar::Double(value) => Ok(*value),
+            PurpleGlobalPropertyScalar::DoubleArray(values) => {
+                GlobalPropertyScalar::DoubleArray(values.clone()).value(name, body)
+            }
+            PurpleGlobalPropertyScalar::GlobalPropertyScalar(property) => {
+                property.value(name, body)
+            }
+        }
+    }
+
+    /// Resolve the count `name`, which must be a non-negative integer.
+    pub fn length(&self, name: &str, body: &[u8]) -> Result<usize, Error> {
+        to_length(name, self.value(name, body)?)
+    }
+}
+
 /// A `GlobalPropertyCartesian4` object defining a 4-component numeric property for all
 /// points. See the corresponding property semantic in
 /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
 ///
 /// An object defining a global 4-component numeric property values for all features.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum GlobalPropertyCartesian4 {
     DoubleArray(Vec<f64>),
@@ -134,10 +247,101 @@ pub enum GlobalPropertyCartesian4 {
 /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
 ///
 /// An object defining a global numeric property value for all features.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum FluffyGlobalPropertyScalar {
     Double(f64),
     DoubleArray(Vec<f64>),
     GlobalPropertyScalar(GlobalPropertyScalar),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dequantize_position() {
+        let offset = [10.0, -20.0, 0.5];
+        let scale = [100.0, 50.0, 2.0];
+        assert_eq!(dequantize([0, 0, 0], offset, scale), offset);
+        assert_eq!(dequantize([65535; 3], offset, scale), [110.0, 30.0, 2.5]);
+        let [x, _, _] = dequantize([32768, 0, 0], offset, scale);
+        assert!((x - 60.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn scalar_values() {
+        let mut body = vec![0; 4];
+        LittleEndian::write_u32(&mut body, 42);
+        let value = |json| {
+            serde_json::from_value::<GlobalPropertyScalar>(json)
+                .unwrap()
+                .value("BATCH_LENGTH", &body)
+        };
+        assert_eq!(value(serde_json::json!(10)).unwrap(), 10.0);
+        assert_eq!(value(serde_json::json!(1.5)).unwrap(), 1.5);
+        assert_eq!(value(serde_json::json!([7])).unwrap(), 7.0);
+        assert_eq!(value(serde_json::json!({"byteOffset": 0})).unwrap(), 42.0);
+        assert!(value(serde_json::json!([1, 2])).is_err());
+        assert!(matches!(
+            value(serde_json::json!({"byteOffset": 4})),
+            Err(Error::PropertyReference { .. })
+        ));
+    }
+
+    #[test]
+    fn cartesian3_values() {
+        let mut body = vec![0; 12];
+        LittleEndian::write_f32_into(&[1.0, 2.0, 3.0], &mut body);
+        let value = |json| {
+            serde_json::from_value::<GlobalPropertyCartesian3>(json)
+                .unwrap()
+                .value("RTC_CENTER", &body)
+        };
+        assert_eq!(
+            value(serde_json::json!([4, 5, 6])).unwrap(),
+            [4.0, 5.0, 6.0]
+        );
+        assert_eq!(
+            value(serde_json::json!({"byteOffset": 0})).unwrap(),
+            [1.0, 2.0, 3.0]
+        );
+        assert!(value(serde_json::json!([4, 5])).is_err());
+        assert!(value(serde_json::json!({"byteOffset": 4})).is_err());
+    }
+
+    #[test]
+    fn scalar_lengths() {
+        let mut body = vec![0; 4];
+        LittleEndian::write_u32(&mut body, 42);
+        let length = |json| {
+            serde_json::from_value::<GlobalPropertyScalar>(json)
+                .unwrap()
+                .length("BATCH_LENGTH", &body)
+        };
+        assert_eq!(length(serde_json::json!(0)).unwrap(), 0);
+        assert_eq!(length(serde_json::json!(10.0)).unwrap(), 10);
+        assert_eq!(length(serde_json::json!({"byteOffset": 0})).unwrap(), 42);
+        for invalid in &[
+            serde_json::json!(-1),
+            serde_json::json!(1.5),
+            serde_json::json!([-0.5]),
+            serde_json::json!(1e20),
+        ] {
+            assert!(
+                matches!(
+                    length(invalid.clone()),
+                    Err(Error::Format {
+                        section: Section::FeatureTableJson,
+                        ..
+                    })
+                ),
+                "{}",
+                invalid
+            );
+        }
+        let purple: PurpleGlobalPropertyScalar =
+            serde_json::from_value(serde_json::json!(-3)).unwrap();
+        assert!(purple.length("BATCH_LENGTH", &body).is_err());
+    }
+}
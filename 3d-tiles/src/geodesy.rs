@@ -0,0 +1,212 @@
+//! WGS84 ellipsoid and conversions between earth-centered, earth-fixed (ECEF) and geodetic
+//! coordinates.
+//!
+//! Geodetic coordinates are `[longitude, latitude, height]` with angles in radians and the
+//! height in meters above the ellipsoid, as used by region bounding volumes (EPSG:4979).
+
+use crate::math::{cross, normalize};
+use crate::matrix::Matrix4;
+
+/// Semi-major axis of the WGS84 ellipsoid in meters.
+pub const WGS84_A: f64 = 6378137.0;
+/// Semi-minor axis of the WGS84 ellipsoid in meters.
+pub const WGS84_B: f64 = 6356752.314245179;
+/// Flattening of the WGS84 ellipsoid.
+pub const WGS84_F: f64 = 1.0 / 298.257223563;
+/// First eccentricity squared of the WGS84 ellipsoid.
+pub const WGS84_E2: f64 = WGS84_F * (2.0 - WGS84_F);
+
+/// ECEF position of geodetic coordinates.
+pub fn geodetic_to_ecef(longitude: f64, latitude: f64, height: f64) -> [f64; 3] {
+    let n = WGS84_A / (1.0 - WGS84_E2 * latitude.sin().powi(2)).sqrt();
+    [
+        (n + height) * latitude.cos() * longitude.cos(),
+        (n + height) * latitude.cos() * longitude.sin(),
+        (n * (1.0 - WGS84_E2) + height) * latitude.sin(),
+    ]
+}
+
+/// Geodetic coordinates `[longitude, latitude, height]` of an ECEF position.
+pub fn ecef_to_geodetic(position: [f64; 3]) -> [f64; 3] {
+    let [x, y, z] = position;
+    let p = x.hypot(y);
+    let longitude = y.atan2(x);
+    let mut latitude = z.atan2(p * (1.0 - WGS84_E2));
+    let mut height = 0.0;
+    for _ in 0..10 {
+        let n = WGS84_A / (1.0 - WGS84_E2 * latitude.sin().powi(2)).sqrt();
+        height = p * latitude.cos() + (z + WGS84_E2 * n * latitude.sin()) * latitude.sin() - n;
+        latitude = z.atan2(p * (1.0 - WGS84_E2 * n / (n + height)));
+    }
+    [longitude, latitude, height]
+}
+
+/// Unit normal of the ellipsoid surface at a longitude and latitude.
+pub fn geodetic_surface_normal(longitude: f64, latitude: f64) -> [f64; 3] {
+    [
+        latitude.cos() * longitude.cos(),
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+    ]
+}
+
+/// Unit normal of the ellipsoid surface at an ECEF position.
+///
+/// Exact for positions on the surface, a close approximation of the geodetic up direction
+/// for positions above or below it.
+pub fn surface_normal(position: [f64; 3]) -> [f64; 3] {
+    let a2 = WGS84_A * WGS84_A;
+    let b2 = WGS84_B * WGS84_B;
+    let [x, y, z] = position;
+    normalize([x / a2, y / a2, z / b2])
+}
+
+/// East, north and up unit vectors at an ECEF position.
+///
+/// At the poles, where east is not defined, east is the y axis.
+pub fn east_north_up_axes(position: [f64; 3]) -> [[f64; 3]; 3] {
+    let [x, y, z] = position;
+    if x.abs() < f64::EPSILON && y.abs() < f64::EPSILON {
+        let sign = if z < 0.0 { -1.0 } else { 1.0 };
+        return [[0.0, 1.0, 0.0], [-sign, 0.0, 0.0], [0.0, 0.0, sign]];
+    }
+    let up = surface_normal(position);
+    let east = normalize([-y, x, 0.0]);
+    let north = cross(up, east);
+    [east, north, up]
+}
+
+/// Transform from the local east-north-up frame at an ECEF position to ECEF.
+///
+/// Used as root tile transform, it places a tileset with local z-up coordinates on the
+/// globe.
+pub fn east_north_up_to_ecef(position: [f64; 3]) -> Matrix4 {
+    let [east, north, up] = east_north_up_axes(position);
+    Matrix4([
+        east[0],
+        east[1],
+        east[2],
+        0.0,
+        north[0],
+        north[1],
+        north[2],
+        0.0,
+        up[0],
+        up[1],
+        up[2],
+        0.0,
+        position[0],
+        position[1],
+        position[2],
+        1.0,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{dot, length, sub};
+    use std::f64::consts::FRAC_PI_2;
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3], tolerance: f64) {
+        assert!(
+            length(sub(actual, expected)) <= tolerance,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn ecef_of_reference_points() {
+        assert_close(geodetic_to_ecef(0.0, 0.0, 0.0), [WGS84_A, 0.0, 0.0], 1e-9);
+        assert_close(
+            geodetic_to_ecef(FRAC_PI_2, 0.0, 10.0),
+            [0.0, WGS84_A + 10.0, 0.0],
+            1e-9,
+        );
+        assert_close(
+            geodetic_to_ecef(0.0, FRAC_PI_2, 0.0),
+            [0.0, 0.0, WGS84_B],
+            1e-6,
+        );
+        assert_close(
+            geodetic_to_ecef(1.0, -FRAC_PI_2, -5.0),
+            [0.0, 0.0, -WGS84_B + 5.0],
+            1e-6,
+        );
+    }
+
+    #[test]
+    fn geodetic_round_trip() {
+        for &longitude in &[-3.1, -1.3197, 0.0, 0.5, 3.0] {
+            for &latitude in &[-1.57, -0.7, 0.0, 0.6988, 1.2, 1.57] {
+                for &height in &[-100.0, 0.0, 67.0, 10_000.0, 1_000_000.0] {
+                    let ecef = geodetic_to_ecef(longitude, latitude, height);
+                    let [lon, lat, h] = ecef_to_geodetic(ecef);
+                    assert!((lon - longitude).abs() < 1e-12);
+                    assert!((lat - latitude).abs() < 1e-12);
+                    assert!((h - height).abs() < 1e-6, "{} != {}", h, height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normals() {
+        let (longitude, latitude) = (-1.3197, 0.6988);
+        let normal = geodetic_surface_normal(longitude, latitude);
+        assert!((length(normal) - 1.0).abs() < 1e-12);
+        assert_close(
+            surface_normal(geodetic_to_ecef(longitude, latitude, 0.0)),
+            normal,
+            1e-12,
+        );
+        assert_close(
+            surface_normal(geodetic_to_ecef(longitude, latitude, 100.0)),
+            normal,
+            1e-6,
+        );
+    }
+
+    #[test]
+    fn east_north_up() {
+        let [east, north, up] = east_north_up_axes(geodetic_to_ecef(0.0, 0.0, 0.0));
+        assert_close(east, [0.0, 1.0, 0.0], 1e-12);
+        assert_close(north, [0.0, 0.0, 1.0], 1e-12);
+        assert_close(up, [1.0, 0.0, 0.0], 1e-12);
+
+        let position = geodetic_to_ecef(-1.3197, 0.6988, 20.0);
+        let axes = east_north_up_axes(position);
+        for i in 0..3 {
+            assert!((length(axes[i]) - 1.0).abs() < 1e-12);
+            assert!(dot(axes[i], axes[(i + 1) % 3]).abs() < 1e-12);
+        }
+        assert_close(cross(axes[0], axes[1]), axes[2], 1e-12);
+
+        let [east, north, up] = east_north_up_axes([0.0, 0.0, -WGS84_B]);
+        assert_eq!(
+            (east, north, up),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0])
+        );
+    }
+
+    #[test]
+    fn sample_root_transform() {
+        // The root transform of the sample is an east-north-up frame scaled by 100
+        let data = crate::sample("TilesetWithDiscreteLOD/tileset.json");
+        let tileset = crate::tileset::Tileset::from_reader(&data[..]).unwrap();
+        let transform = tileset.root.transform.unwrap();
+        let expected = east_north_up_to_ecef(transform.translation());
+        for (i, (actual, expected)) in transform.0.iter().zip(&expected.0).enumerate() {
+            let expected = if i < 12 { expected * 100.0 } else { *expected };
+            assert!(
+                (actual - expected).abs() < 1e-4,
+                "{}: {} != {}",
+                i,
+                actual,
+                expected
+            );
+        }
+    }
+}
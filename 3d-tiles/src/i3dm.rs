@@ -1,28 +1,49 @@
 use crate::batch_table::BatchTable;
-use crate::error::Error;
+use crate::binary::{
+    body_slice, check_alignment, check_header, collect, json_section, layout, read_section,
+};
+use crate::encoding::oct32p_decode;
+use crate::error::{Error, Section};
 use crate::feature_table::{
-    BinaryBodyReference, GlobalPropertyCartesian3, GlobalPropertyScalar, Property,
+    dequantize, BinaryBodyReference, ComponentType, GlobalPropertyCartesian3, GlobalPropertyScalar,
+    Property,
 };
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::geodesy::east_north_up_axes;
+use crate::limits::Limits;
+use crate::math::cross;
+use crate::matrix::Matrix4;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
+use std::io::{BufReader, Read, Write};
+use std::mem;
 use std::path::Path;
+use std::slice::ChunksExact;
 
 /// Instanced 3D Model tile.
 ///
 /// <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct I3dm {
     pub header: I3dmHeader,
     pub feature_table: FeatureTable,
     pub batch_table: BatchTable,
-    // GlTF
+    /// glTF uri or embedded binary glTF
+    pub gltf: I3dmGltf,
+}
+
+/// The glTF field of an Instanced 3D Model tile.
+#[derive(Debug, Clone)]
+pub enum I3dmGltf {
+    /// A uri pointing to the glTF model (`gltfFormat` 0).
+    Uri(String),
+    /// Embedded binary glTF (`gltfFormat` 1).
+    Embedded(Vec<u8>),
 }
 
 /// The header section of a .i3dm file.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[repr(C)]
 pub struct I3dmHeader {
     /// Must be `b"i3dm"`. This can be used to identify the content as an Instanced 3D Model tile.
@@ -46,6 +67,8 @@ pub struct I3dmHeader {
 impl I3dmHeader {
     fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
         use Error::Io;
+        let buf = read_section(&mut reader, Section::Header, 0, mem::size_of::<Self>())?;
+        let mut reader = &buf[..];
         let mut magic = [0; 4];
         reader.read_exact(&mut magic).map_err(Io)?;
         if &magic == b"i3dm" {
@@ -63,14 +86,93 @@ impl I3dmHeader {
             Err(Error::Magic(magic))
         }
     }
+
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        use Error::Io;
+        writer.write_all(&self.magic).map_err(Io)?;
+        writer.write_u32::<LittleEndian>(self.version).map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.feature_table_json_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.feature_table_binary_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.batch_table_json_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.batch_table_binary_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.gltf_format)
+            .map_err(Io)?;
+        Ok(())
+    }
+
+    /// Lengths of the tables following the header.
+    fn table_lengths(&self) -> [(Section, u32); 4] {
+        [
+            (
+                Section::FeatureTableJson,
+                self.feature_table_json_byte_length,
+            ),
+            (
+                Section::FeatureTableBinary,
+                self.feature_table_binary_byte_length,
+            ),
+            (Section::BatchTableJson, self.batch_table_json_byte_length),
+            (
+                Section::BatchTableBinary,
+                self.batch_table_binary_byte_length,
+            ),
+        ]
+    }
+
+    /// Lengths of the tables following the header, for [`layout`].
+    fn declared_lengths(&self) -> Vec<Option<usize>> {
+        self.table_lengths()
+            .iter()
+            .map(|(_, length)| Some(*length as usize))
+            .collect()
+    }
+
+    /// Byte offset of the Batch Table in the tile.
+    fn batch_table_offset(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.feature_table_json_byte_length as usize
+            + self.feature_table_binary_byte_length as usize
+    }
+
+    /// The length of the glTF field in bytes.
+    fn gltf_byte_length(&self) -> Result<usize, Error> {
+        let sections = mem::size_of::<Self>() as u64
+            + u64::from(self.feature_table_json_byte_length)
+            + u64::from(self.feature_table_binary_byte_length)
+            + u64::from(self.batch_table_json_byte_length)
+            + u64::from(self.batch_table_binary_byte_length);
+        u64::from(self.byte_length)
+            .checked_sub(sections)
+            .map(|len| len as usize)
+            .ok_or(Error::ByteLength {
+                section: Section::Header,
+                offset: 8,
+                declared: u64::from(self.byte_length),
+                actual: sections,
+            })
+    }
 }
 
 /// A Feature Table is a component of a tile's binary body and describes position and appearance properties required to render each feature in a tile.
 // <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/FeatureTable/README.md>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FeatureTable {
     /// JSON header
     pub header: InstancedFeatureTable,
+    /// JSON header as read, written instead of `header` while it has the same content.
+    pub raw_header: Option<Vec<u8>>,
     /// Binary body
     pub body: Vec<u8>,
 }
@@ -81,30 +183,314 @@ impl FeatureTable {
         json_byte_length: u32,
         binary_byte_length: u32,
     ) -> Result<Self, Error> {
-        use self::Error::Io;
-        let mut buf = vec![0; json_byte_length as usize];
-        reader.read_exact(&mut buf).map_err(Io)?;
-        dbg!(&std::str::from_utf8(&buf));
+        let offset = mem::size_of::<I3dmHeader>();
+        let json_byte_length = json_byte_length as usize;
+        let buf = read_section(
+            &mut reader,
+            Section::FeatureTableJson,
+            offset,
+            json_byte_length,
+        )?;
+        // dbg!(&std::str::from_utf8(&buf));
         let header: InstancedFeatureTable = serde_json::from_slice(&buf).map_err(Error::Json)?;
-        let mut body = vec![0; binary_byte_length as usize];
-        reader.read_exact(&mut body).map_err(Io)?;
-        Ok(FeatureTable { header, body })
+        let body = read_section(
+            &mut reader,
+            Section::FeatureTableBinary,
+            offset + json_byte_length,
+            binary_byte_length as usize,
+        )?;
+        Ok(FeatureTable {
+            header,
+            raw_header: Some(buf),
+            body,
+        })
+    }
+
+    /// Serialize the JSON header and the binary body.
+    fn to_bytes(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let json = json_section(&self.header, self.raw_header.as_deref())?;
+        Ok((json, self.body.clone()))
+    }
+
+    /// Number of instances (`INSTANCES_LENGTH`).
+    pub fn instances_length(&self) -> Result<usize, Error> {
+        self.header
+            .instances_length
+            .length("INSTANCES_LENGTH", &self.body)
+    }
+
+    /// Check that all semantics fit into the binary body.
+    fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        collect(&mut errors, self.positions_f64(true));
+        collect(&mut errors, self.normals_up_right());
+        collect(&mut errors, self.scales());
+        if let Some(ref reference) = self.header.batch_id {
+            let size = match reference.component_type {
+                Some(ComponentType::UnsignedByte) => 1,
+                None | Some(ComponentType::UnsignedShort) => 2,
+                Some(ComponentType::UnsignedInt) => 4,
+                Some(_) => {
+                    errors.push(Error::format(
+                        Section::FeatureTableJson,
+                        "invalid BATCH_ID componentType",
+                    ));
+                    return errors;
+                }
+            };
+            collect(&mut errors, self.instance_data("BATCH_ID", reference, size));
+        }
+        errors
+    }
+
+    /// Per-instance elements of the semantic `name` with `size` bytes in the binary body.
+    fn instance_data(
+        &self,
+        name: &str,
+        reference: &BinaryBodyReference,
+        size: usize,
+    ) -> Result<ChunksExact<'_, u8>, Error> {
+        let instances_length = self.instances_length()?;
+        let data = body_slice(
+            &self.body,
+            Section::FeatureTableBinary,
+            name,
+            reference.byte_offset,
+            instances_length,
+            size,
+        )?;
+        Ok(data.chunks_exact(size))
+    }
+
+    /// Instance positions as `f64`, dequantized if the tile uses `POSITION_QUANTIZED`.
+    ///
+    /// With `add_rtc_center`, `RTC_CENTER` is added to the positions.
+    pub fn positions_f64(&self, add_rtc_center: bool) -> Result<Vec<[f64; 3]>, Error> {
+        let mut positions: Vec<[f64; 3]> = if let Some(ref reference) = self.header.position {
+            self.instance_data("POSITION", reference, 12)?
+                .map(|c| {
+                    [
+                        f64::from(LittleEndian::read_f32(&c[0..4])),
+                        f64::from(LittleEndian::read_f32(&c[4..8])),
+                        f64::from(LittleEndian::read_f32(&c[8..12])),
+                    ]
+                })
+                .collect()
+        } else if let Some(ref reference) = self.header.position_quantized {
+            let missing = |name| {
+                Error::format(
+                    Section::FeatureTableJson,
+                    format!("{} required for POSITION_QUANTIZED", name),
+                )
+            };
+            let offset = self
+                .header
+                .quantized_volume_offset
+                .as_ref()
+                .ok_or_else(|| missing("QUANTIZED_VOLUME_OFFSET"))?
+                .value("QUANTIZED_VOLUME_OFFSET", &self.body)?;
+            let scale = self
+                .header
+                .quantized_volume_scale
+                .as_ref()
+                .ok_or_else(|| missing("QUANTIZED_VOLUME_SCALE"))?
+                .value("QUANTIZED_VOLUME_SCALE", &self.body)?;
+            self.instance_data("POSITION_QUANTIZED", reference, 6)?
+                .map(|c| {
+                    let quantized = [
+                        LittleEndian::read_u16(&c[0..2]),
+                        LittleEndian::read_u16(&c[2..4]),
+                        LittleEndian::read_u16(&c[4..6]),
+                    ];
+                    dequantize(quantized, offset, scale)
+                })
+                .collect()
+        } else {
+            return Err(Error::format(
+                Section::FeatureTableJson,
+                "POSITION or POSITION_QUANTIZED required",
+            ));
+        };
+        if add_rtc_center {
+            if let Some(ref rtc_center) = self.header.rtc_center {
+                let center = rtc_center.value("RTC_CENTER", &self.body)?;
+                for p in positions.iter_mut() {
+                    p[0] += center[0];
+                    p[1] += center[1];
+                    p[2] += center[2];
+                }
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Per-instance unit vectors from the float semantic `name` or its oct-encoded variant.
+    fn unit_vectors(
+        &self,
+        name: &str,
+        float: &Option<BinaryBodyReference>,
+        oct32p: &Option<BinaryBodyReference>,
+    ) -> Result<Option<Vec<[f32; 3]>>, Error> {
+        if let Some(ref reference) = float {
+            let vectors = self
+                .instance_data(name, reference, 12)?
+                .map(|c| {
+                    [
+                        LittleEndian::read_f32(&c[0..4]),
+                        LittleEndian::read_f32(&c[4..8]),
+                        LittleEndian::read_f32(&c[8..12]),
+                    ]
+                })
+                .collect();
+            Ok(Some(vectors))
+        } else if let Some(ref reference) = oct32p {
+            let vectors = self
+                .instance_data(&format!("{}_OCT32P", name), reference, 4)?
+                .map(|c| {
+                    oct32p_decode([
+                        LittleEndian::read_u16(&c[0..2]),
+                        LittleEndian::read_u16(&c[2..4]),
+                    ])
+                })
+                .collect();
+            Ok(Some(vectors))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Instance up vectors, decoded from `NORMAL_UP` or `NORMAL_UP_OCT32P`.
+    pub fn normals_up(&self) -> Result<Option<Vec<[f32; 3]>>, Error> {
+        self.unit_vectors(
+            "NORMAL_UP",
+            &self.header.normal_up,
+            &self.header.normal_up_oct32_p,
+        )
+    }
+
+    /// Instance right vectors, decoded from `NORMAL_RIGHT` or `NORMAL_RIGHT_OCT32P`.
+    pub fn normals_right(&self) -> Result<Option<Vec<[f32; 3]>>, Error> {
+        self.unit_vectors(
+            "NORMAL_RIGHT",
+            &self.header.normal_right,
+            &self.header.normal_right_oct32_p,
+        )
+    }
+
+    /// Instance scales from `SCALE_NON_UNIFORM` or `SCALE`.
+    pub fn scales(&self) -> Result<Option<Vec<[f32; 3]>>, Error> {
+        if let Some(ref reference) = self.header.scale_non_uniform {
+            let scales = self
+                .instance_data("SCALE_NON_UNIFORM", reference, 12)?
+                .map(|c| {
+                    [
+                        LittleEndian::read_f32(&c[0..4]),
+                        LittleEndian::read_f32(&c[4..8]),
+                        LittleEndian::read_f32(&c[8..12]),
+                    ]
+                })
+                .collect();
+            Ok(Some(scales))
+        } else if let Some(ref reference) = self.header.scale {
+            let scales = self
+                .instance_data("SCALE", reference, 4)?
+                .map(|c| {
+                    let scale = LittleEndian::read_f32(c);
+                    [scale, scale, scale]
+                })
+                .collect();
+            Ok(Some(scales))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Instance up and right vectors, which must be defined together.
+    fn normals_up_right(&self) -> Result<Option<Vec<[[f32; 3]; 2]>>, Error> {
+        match (self.normals_up()?, self.normals_right()?) {
+            (Some(up), Some(right)) => Ok(Some(
+                up.into_iter()
+                    .zip(right)
+                    .map(|(up, right)| [up, right])
+                    .collect(),
+            )),
+            (None, None) => Ok(None),
+            (Some(_), None) => Err(Error::format(
+                Section::FeatureTableJson,
+                "NORMAL_UP requires NORMAL_RIGHT",
+            )),
+            (None, Some(_)) => Err(Error::format(
+                Section::FeatureTableJson,
+                "NORMAL_RIGHT requires NORMAL_UP",
+            )),
+        }
+    }
+
+    /// Transform of each instance, composed of translation, rotation and scale.
+    ///
+    /// The translation includes `RTC_CENTER`. Instances are rotated by `NORMAL_UP` and
+    /// `NORMAL_RIGHT`, by the east-north-up frame on the WGS84 ellipsoid at their position if
+    /// `EAST_NORTH_UP` is set, or not at all.
+    pub fn instance_transforms(&self) -> Result<Vec<Matrix4>, Error> {
+        let positions = self.positions_f64(true)?;
+        let normals = self.normals_up_right()?;
+        let scales = self.scales()?;
+        let east_north_up = self.header.east_north_up.unwrap_or(false);
+        let transforms = positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                let [right, up, forward] = if let Some(ref normals) = normals {
+                    let [up, right] = normals[i];
+                    let (up, right) = (to_f64(up), to_f64(right));
+                    [right, up, cross(right, up)]
+                } else if east_north_up {
+                    east_north_up_axes(*position)
+                } else {
+                    [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+                };
+                let scale = scales.as_ref().map_or([1.0; 3], |scales| to_f64(scales[i]));
+                Matrix4([
+                    right[0] * scale[0],
+                    right[1] * scale[0],
+                    right[2] * scale[0],
+                    0.0,
+                    up[0] * scale[1],
+                    up[1] * scale[1],
+                    up[2] * scale[1],
+                    0.0,
+                    forward[0] * scale[2],
+                    forward[1] * scale[2],
+                    forward[2] * scale[2],
+                    0.0,
+                    position[0],
+                    position[1],
+                    position[2],
+                    1.0,
+                ])
+            })
+            .collect();
+        Ok(transforms)
     }
 }
 
+fn to_f64(v: [f32; 3]) -> [f64; 3] {
+    [f64::from(v[0]), f64::from(v[1]), f64::from(v[2])]
+}
+
 /// A set of semantics containing per-tile and per-feature values defining the position and
 /// appearance properties for features in a tile.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstancedFeatureTable {
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "BATCH_ID")]
+    #[serde(rename = "BATCH_ID", skip_serializing_if = "Option::is_none")]
     pub batch_id: Option<BinaryBodyReference>,
     /// A `GlobalPropertyBoolean` object defining a boolean property for all features. See the
     /// corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "EAST_NORTH_UP")]
+    #[serde(rename = "EAST_NORTH_UP", skip_serializing_if = "Option::is_none")]
     pub east_north_up: Option<bool>,
     /// A `GlobalPropertyScalar` object defining a numeric property for all features. See the
     /// corresponding property semantic in
@@ -114,73 +500,95 @@ pub struct InstancedFeatureTable {
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "NORMAL_RIGHT")]
+    #[serde(rename = "NORMAL_RIGHT", skip_serializing_if = "Option::is_none")]
     pub normal_right: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "NORMAL_RIGHT_OCT32P")]
+    #[serde(
+        rename = "NORMAL_RIGHT_OCT32P",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub normal_right_oct32_p: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "NORMAL_UP")]
+    #[serde(rename = "NORMAL_UP", skip_serializing_if = "Option::is_none")]
     pub normal_up: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "NORMAL_UP_OCT32P")]
+    #[serde(rename = "NORMAL_UP_OCT32P", skip_serializing_if = "Option::is_none")]
     pub normal_up_oct32_p: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "POSITION")]
+    #[serde(rename = "POSITION", skip_serializing_if = "Option::is_none")]
     pub position: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "POSITION_QUANTIZED")]
+    #[serde(rename = "POSITION_QUANTIZED", skip_serializing_if = "Option::is_none")]
     pub position_quantized: Option<BinaryBodyReference>,
     /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
     /// features. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "QUANTIZED_VOLUME_OFFSET")]
+    #[serde(
+        rename = "QUANTIZED_VOLUME_OFFSET",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub quantized_volume_offset: Option<GlobalPropertyCartesian3>,
     /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
     /// features. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "QUANTIZED_VOLUME_SCALE")]
+    #[serde(
+        rename = "QUANTIZED_VOLUME_SCALE",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub quantized_volume_scale: Option<GlobalPropertyCartesian3>,
     /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
     /// features. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "RTC_CENTER")]
+    #[serde(rename = "RTC_CENTER", skip_serializing_if = "Option::is_none")]
     pub rtc_center: Option<GlobalPropertyCartesian3>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "SCALE")]
+    #[serde(rename = "SCALE", skip_serializing_if = "Option::is_none")]
     pub scale: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/Instanced3DModel/README.md#semantics).
-    #[serde(rename = "SCALE_NON_UNIFORM")]
+    #[serde(rename = "SCALE_NON_UNIFORM", skip_serializing_if = "Option::is_none")]
     pub scale_non_uniform: Option<BinaryBodyReference>,
 
     #[serde(flatten)]
     pub properties: HashMap<String, Property>,
     /// Dictionary object with extension-specific objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
     /// Application-specific data.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
 }
 
 impl I3dm {
-    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Read tile, checking the header against `limits` before reading any section.
+    pub fn from_reader_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, Error> {
         let header = I3dmHeader::from_reader(&mut reader)?;
         if header.version != 1 {
             return Err(Error::Version(header.version));
         }
+        check_header(
+            header.byte_length,
+            mem::size_of::<I3dmHeader>(),
+            &header.table_lengths(),
+            limits,
+        )?;
         let feature_table = FeatureTable::from_reader(
             &mut reader,
             header.feature_table_json_byte_length,
@@ -188,34 +596,344 @@ impl I3dm {
         )?;
         let batch_table = BatchTable::from_reader(
             &mut reader,
+            header.batch_table_offset(),
             header.batch_table_json_byte_length,
             header.batch_table_binary_byte_length,
+            feature_table.instances_length()?,
         )?;
+        let gltf_byte_length = header.gltf_byte_length()?;
+        let buf = read_section(
+            &mut reader,
+            Section::Gltf,
+            header.byte_length as usize - gltf_byte_length,
+            gltf_byte_length,
+        )?;
+        let gltf = match header.gltf_format {
+            0 => {
+                let uri = String::from_utf8(buf)
+                    .map_err(|e| Error::format(Section::Gltf, format!("invalid uri: {}", e)))?;
+                // The uri may be padded with spaces, or zeros when written by older tools
+                I3dmGltf::Uri(uri.trim_end_matches(&[' ', '\0'][..]).to_string())
+            }
+            1 => I3dmGltf::Embedded(buf),
+            format => {
+                return Err(Error::format(
+                    Section::Header,
+                    format!("invalid gltfFormat {}", format),
+                ))
+            }
+        };
         Ok(I3dm {
             header,
             feature_table,
             batch_table,
+            gltf,
         })
     }
+
+    /// Transform of each instance, see [`FeatureTable::instance_transforms`].
+    pub fn instance_transforms(&self) -> Result<Vec<Matrix4>, Error> {
+        self.feature_table.instance_transforms()
+    }
+
+    /// Check the binary layout of the tile.
+    ///
+    /// All sections must end on an 8-byte boundary, semantics and properties must fit into the
+    /// binary bodies and the Batch Table must have a value per instance for every property.
+    pub fn validate(&self) -> Vec<Error> {
+        let gltf_byte_length = match self.gltf {
+            I3dmGltf::Uri(_) => self.header.gltf_byte_length().unwrap_or(0),
+            I3dmGltf::Embedded(ref glb) => glb.len(),
+        };
+        let mut sections = self.header.table_lengths().to_vec();
+        sections.push((Section::Gltf, gltf_byte_length as u32));
+        let mut errors = check_alignment(mem::size_of::<I3dmHeader>(), &sections);
+        errors.extend(self.feature_table.validate());
+        errors.extend(self.batch_table.validate());
+        errors
+    }
+
+    /// Write tile.
+    ///
+    /// `gltfFormat` is derived from the glTF field. The header lengths are computed from the
+    /// tables and the glTF. Each section is padded to an 8-byte boundary, unless all sections
+    /// are unchanged since reading the tile, which is then written exactly as read.
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let header_length = mem::size_of::<I3dmHeader>();
+        let (feature_table_json, feature_table_binary) = self.feature_table.to_bytes()?;
+        let (batch_table_json, batch_table_binary) = self.batch_table.to_bytes()?;
+        let mut declared = self.header.declared_lengths();
+        declared.push(self.header.gltf_byte_length().ok());
+        let (gltf_format, gltf, fill) = match self.gltf {
+            I3dmGltf::Uri(ref uri) => {
+                // The uri is read without its padding, restore it for an unchanged tile
+                let mut uri = uri.as_bytes().to_vec();
+                let padded = declared[4]
+                    .filter(|length| self.header.gltf_format == 0 && uri.len() <= *length);
+                if let Some(length) = padded {
+                    uri.resize(length, b' ');
+                }
+                (0, uri, b' ')
+            }
+            I3dmGltf::Embedded(ref glb) => (1, glb.clone(), 0),
+        };
+        let sections = layout(
+            header_length,
+            vec![
+                (feature_table_json, b' '),
+                (feature_table_binary, 0),
+                (batch_table_json, b' '),
+                (batch_table_binary, 0),
+                (gltf, fill),
+            ],
+            &declared,
+        );
+        let header = I3dmHeader {
+            magic: *b"i3dm",
+            version: 1,
+            byte_length: (header_length + sections.iter().map(Vec::len).sum::<usize>()) as u32,
+            feature_table_json_byte_length: sections[0].len() as u32,
+            feature_table_binary_byte_length: sections[1].len() as u32,
+            batch_table_json_byte_length: sections[2].len() as u32,
+            batch_table_binary_byte_length: sections[3].len() as u32,
+            gltf_format,
+        };
+        header.to_writer(&mut writer)?;
+        for section in &sections {
+            writer.write_all(section).map_err(Error::Io)?;
+        }
+        Ok(())
+    }
 }
 
 /// Read i3dm file and extract binary glTF
 ///
 /// Empty output if glTF is an embedded URI
 pub fn extract_gltf(path: &str) -> Result<I3dm, Error> {
-    let file = File::open(path).map_err(Error::Io)?;
-    let mut reader = BufReader::new(file);
-    let i3dm = I3dm::from_reader(&mut reader)?;
-
-    if i3dm.header.gltf_format == 0 {
-        let mut url = String::new();
-        reader.read_to_string(&mut url).map_err(Error::Io)?;
-        println!("glTF URL: {}", &url);
-    } else if i3dm.header.gltf_format == 1 {
-        let dest = Path::new(path).with_extension("glb");
-        println!("Writing {:?}", &dest);
-        let mut file = File::create(dest).map_err(Error::Io)?;
-        io::copy(&mut reader, &mut file).map_err(Error::Io)?;
+    let file = File::open(path).map_err(|e| Error::Io(e).with_path(path))?;
+    let i3dm = I3dm::from_reader(BufReader::new(file)).map_err(|e| e.with_path(path))?;
+
+    match i3dm.gltf {
+        I3dmGltf::Uri(ref url) => {
+            println!("glTF URL: {}", url);
+        }
+        I3dmGltf::Embedded(ref glb) => {
+            let dest = Path::new(path).with_extension("glb");
+            println!("Writing {:?}", &dest);
+            let mut file = File::create(&dest).map_err(|e| Error::Io(e).with_path(&dest))?;
+            file.write_all(glb)
+                .map_err(|e| Error::Io(e).with_path(&dest))?;
+        }
     }
     Ok(i3dm)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample;
+    use crate::test_util::feature_table;
+
+    #[test]
+    fn round_trip_samples() {
+        for name in &[
+            "TilesetWithTreeBillboards/tree.i3dm",
+            "TilesetWithTreeBillboards/tree_billboard.i3dm",
+        ] {
+            let data = sample(name);
+            let i3dm = I3dm::from_reader(&data[..]).unwrap();
+            let mut written = Vec::new();
+            i3dm.to_writer(&mut written).unwrap();
+            assert!(written == data, "{} differs after writing", name);
+        }
+    }
+
+    #[test]
+    fn write_edited_feature_table() {
+        let data = sample("TilesetWithTreeBillboards/tree.i3dm");
+        let mut i3dm = I3dm::from_reader(&data[..]).unwrap();
+        i3dm.feature_table.header.east_north_up = None;
+        let mut written = Vec::new();
+        i3dm.to_writer(&mut written).unwrap();
+
+        let read = I3dm::from_reader(&written[..]).unwrap();
+        assert!(read.validate().is_empty());
+        assert_eq!(written.len() % 8, 0);
+        assert_eq!(read.feature_table.instances_length().unwrap(), 25);
+        assert!(read.feature_table.header.east_north_up.is_none());
+        let json = read.feature_table.raw_header.unwrap();
+        assert!(std::str::from_utf8(&json)
+            .unwrap()
+            .contains(r#""INSTANCES_LENGTH":25"#));
+    }
+
+    #[test]
+    fn embedded_gltf() {
+        let data = sample("TilesetWithTreeBillboards/tree.i3dm");
+        let i3dm = I3dm::from_reader(&data[..]).unwrap();
+        match i3dm.gltf {
+            I3dmGltf::Embedded(ref glb) => {
+                assert_eq!(&glb[0..4], b"glTF");
+                assert_eq!(LittleEndian::read_u32(&glb[8..12]) as usize, glb.len());
+                assert!(data.ends_with(glb));
+            }
+            ref gltf => panic!("unexpected glTF {:?}", gltf),
+        }
+    }
+
+    #[test]
+    fn gltf_uri() {
+        let data = sample("TilesetWithTreeBillboards/tree.i3dm");
+        let mut i3dm = I3dm::from_reader(&data[..]).unwrap();
+        i3dm.gltf = I3dmGltf::Uri("tree.glb".to_string());
+        let mut written = Vec::new();
+        i3dm.to_writer(&mut written).unwrap();
+        assert!(written.ends_with(b"tree.glb"));
+
+        let read = I3dm::from_reader(&written[..]).unwrap();
+        assert_eq!(read.header.gltf_format, 0);
+        assert!(matches!(read.gltf, I3dmGltf::Uri(ref uri) if uri == "tree.glb"));
+
+        // Uri padded with spaces is read without padding and written unchanged
+        let mut padded = written.clone();
+        padded.extend_from_slice(b"        ");
+        let byte_length = padded.len() as u32;
+        LittleEndian::write_u32(&mut padded[8..12], byte_length);
+        let read = I3dm::from_reader(&padded[..]).unwrap();
+        assert!(matches!(read.gltf, I3dmGltf::Uri(ref uri) if uri == "tree.glb"));
+        let mut rewritten = Vec::new();
+        read.to_writer(&mut rewritten).unwrap();
+        assert!(rewritten == padded);
+    }
+
+    #[test]
+    fn invalid_gltf_format() {
+        let mut data = sample("TilesetWithTreeBillboards/tree.i3dm");
+        LittleEndian::write_u32(&mut data[28..32], 2);
+        assert!(matches!(
+            I3dm::from_reader(&data[..]),
+            Err(Error::Format {
+                section: Section::Header,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn sample_positions() {
+        let data = sample("TilesetWithTreeBillboards/tree.i3dm");
+        let i3dm = I3dm::from_reader(&data[..]).unwrap();
+        let positions = i3dm.feature_table.positions_f64(true).unwrap();
+        assert_eq!(positions.len(), 25);
+        assert_eq!(positions, i3dm.feature_table.positions_f64(false).unwrap());
+    }
+
+    #[test]
+    fn dequantized_positions() {
+        let header = serde_json::json!({
+            "INSTANCES_LENGTH": 2,
+            "POSITION_QUANTIZED": {"byteOffset": 0},
+            "QUANTIZED_VOLUME_OFFSET": [10, 20, 30],
+            "QUANTIZED_VOLUME_SCALE": [1, 1, 1],
+        });
+        let mut body = vec![0; 12];
+        LittleEndian::write_u16_into(&[0, 0, 0, 65535, 0, 65535], &mut body);
+        let positions = feature_table::<FeatureTable>(header, body)
+            .positions_f64(true)
+            .unwrap();
+        assert_eq!(positions, vec![[10.0, 20.0, 30.0], [11.0, 20.0, 31.0]]);
+    }
+
+    #[test]
+    fn missing_positions() {
+        let header = serde_json::json!({"INSTANCES_LENGTH": 1});
+        assert!(matches!(
+            feature_table::<FeatureTable>(header, Vec::new()).positions_f64(false),
+            Err(Error::Format {
+                section: Section::FeatureTableJson,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn east_north_up_transforms() {
+        let data = sample("TilesetWithTreeBillboards/tree.i3dm");
+        let i3dm = I3dm::from_reader(&data[..]).unwrap();
+        let positions = i3dm.feature_table.positions_f64(true).unwrap();
+        let transforms = i3dm.instance_transforms().unwrap();
+        assert_eq!(transforms.len(), 25);
+        for (transform, position) in transforms.iter().zip(&positions) {
+            let m = transform.0;
+            assert_eq!(&m[12..15], &position[..]);
+            let axes = [&m[0..3], &m[4..7], &m[8..11]];
+            for (i, a) in axes.iter().enumerate() {
+                for (j, b) in axes.iter().enumerate() {
+                    let dot: f64 = a.iter().zip(b.iter()).map(|(a, b)| a * b).sum();
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    assert!((dot - expected).abs() < 1e-6);
+                }
+            }
+            // Up points away from the earth center
+            let up_dot: f64 = m[8..11].iter().zip(position).map(|(a, b)| a * b).sum();
+            assert!(up_dot > 0.0);
+        }
+    }
+
+    #[test]
+    fn normal_and_scale_transforms() {
+        let header = serde_json::json!({
+            "INSTANCES_LENGTH": 1,
+            "POSITION": {"byteOffset": 0},
+            "NORMAL_UP": {"byteOffset": 12},
+            "NORMAL_RIGHT_OCT32P": {"byteOffset": 24},
+            "SCALE_NON_UNIFORM": {"byteOffset": 28},
+        });
+        let mut body = vec![0; 40];
+        LittleEndian::write_f32_into(&[1.0, 2.0, 3.0, 0.0, 0.0, 1.0], &mut body[0..24]);
+        LittleEndian::write_u16_into(
+            &crate::encoding::oct32p_encode([1.0, 0.0, 0.0]),
+            &mut body[24..28],
+        );
+        LittleEndian::write_f32_into(&[2.0, 3.0, 4.0], &mut body[28..40]);
+        let transforms = feature_table::<FeatureTable>(header, body)
+            .instance_transforms()
+            .unwrap();
+        let expected = [
+            2.0, 0.0, 0.0, 0.0, //
+            0.0, 0.0, 3.0, 0.0, //
+            0.0, -4.0, 0.0, 0.0, //
+            1.0, 2.0, 3.0, 1.0,
+        ];
+        for (actual, expected) in transforms[0].0.iter().zip(&expected) {
+            assert!((actual - expected).abs() < 1e-4, "{:?}", transforms[0]);
+        }
+    }
+
+    #[test]
+    fn unpaired_normals() {
+        let header = serde_json::json!({
+            "INSTANCES_LENGTH": 1,
+            "POSITION": {"byteOffset": 0},
+            "NORMAL_UP_OCT32P": {"byteOffset": 12},
+        });
+        let feature_table = feature_table::<FeatureTable>(header, vec![0; 16]);
+        match feature_table.instance_transforms() {
+            Err(Error::Format { message, .. }) => assert!(message.contains("NORMAL_RIGHT")),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(feature_table.validate().len(), 1);
+    }
+
+    #[test]
+    fn invalid_instances_length() {
+        for length in &[serde_json::json!(-1), serde_json::json!(2.5)] {
+            let feature_table = feature_table::<FeatureTable>(
+                serde_json::json!({"INSTANCES_LENGTH": length, "POSITION": {"byteOffset": 0}}),
+                vec![0; 24],
+            );
+            assert!(feature_table.instances_length().is_err());
+            assert!(!feature_table.validate().is_empty());
+        }
+    }
+}
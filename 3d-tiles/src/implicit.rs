@@ -0,0 +1,843 @@
+//! Implicit tiling of 3D Tiles 1.1.
+//!
+//! A tile with `implicitTiling` is the root of a quadtree or octree, whose tiles are
+//! identified by their level and x, y and z index. Bounding volumes and geometric errors of
+//! the tiles are derived from the root tile, content and subtree URIs from templates with the
+//! placeholders `{level}`, `{x}`, `{y}` and `{z}`. See
+//! <https://github.com/CesiumGS/3d-tiles/tree/main/specification/ImplicitTiling>
+
+use crate::bounding_volume::Volume;
+use crate::error::{Error, Section};
+use crate::loader::{Source, TilesetLoader};
+use crate::subtree::SubtreeAvailability;
+use crate::tileset::{BoundingVolume, ImplicitTiling, SubdivisionScheme, Tile, TileContent};
+use std::collections::HashMap;
+
+/// Maximum `availableLevels` of an implicit tileset, such that tile indexes fit into `u32`.
+pub const MAX_AVAILABLE_LEVELS: u32 = 32;
+
+/// Coordinate of a tile in an implicit tileset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileCoordinate {
+    /// Level of the tile, 0 for the root tile.
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+    /// Index along the z axis, only defined in an octree.
+    pub z: Option<u32>,
+}
+
+impl TileCoordinate {
+    /// Coordinate of the root tile.
+    pub fn root(scheme: SubdivisionScheme) -> Self {
+        TileCoordinate {
+            level: 0,
+            x: 0,
+            y: 0,
+            z: match scheme {
+                SubdivisionScheme::Quadtree => None,
+                SubdivisionScheme::Octree => Some(0),
+            },
+        }
+    }
+
+    /// Coordinates of the four or eight children, in Morton order.
+    ///
+    /// Children with indexes exceeding 32 bits are omitted.
+    pub fn children(&self) -> impl Iterator<Item = TileCoordinate> + '_ {
+        let count = if self.z.is_some() { 8 } else { 4 };
+        let child = |index: u32, bit: u32| index.checked_mul(2).map(|index| index + bit);
+        (0..count).filter_map(move |i| {
+            Some(TileCoordinate {
+                level: self.level.checked_add(1)?,
+                x: child(self.x, i & 1)?,
+                y: child(self.y, i >> 1 & 1)?,
+                z: match self.z {
+                    Some(z) => Some(child(z, i >> 2 & 1)?),
+                    None => None,
+                },
+            })
+        })
+    }
+
+    /// Coordinate of the tile with Morton index `index` on `level`.
+    pub fn from_morton_index(scheme: SubdivisionScheme, level: u32, index: u64) -> Self {
+        let dimensions = match scheme {
+            SubdivisionScheme::Quadtree => 2,
+            SubdivisionScheme::Octree => 3,
+        };
+        let mut coordinates = [0u32; 3];
+        for bit in 0..level.min(32) {
+            for dimension in 0..dimensions {
+                let source = bit * dimensions + dimension;
+                if source < 64 {
+                    coordinates[dimension as usize] |= ((index >> source & 1) as u32) << bit;
+                }
+            }
+        }
+        TileCoordinate {
+            level,
+            x: coordinates[0],
+            y: coordinates[1],
+            z: Some(coordinates[2]).filter(|_| dimensions == 3),
+        }
+    }
+
+    /// Morton index of the tile within its level, interleaving the bits of x, y and z, with
+    /// x in the lowest bit.
+    ///
+    /// `None` if the index exceeds 64 bits, i.e. above level 32 of a quadtree or level 21 of
+    /// an octree.
+    pub fn morton_index(&self) -> Option<u64> {
+        let coordinates = [Some(self.x), Some(self.y), self.z];
+        let dimensions = if self.z.is_some() { 3 } else { 2 };
+        if self.level * dimensions > 64 {
+            return None;
+        }
+        let mut index = 0;
+        for bit in 0..self.level {
+            for (dimension, coordinate) in coordinates.iter().flatten().enumerate() {
+                index |= u64::from(coordinate >> bit & 1) << (bit * dimensions + dimension as u32);
+            }
+        }
+        Some(index)
+    }
+
+    /// Coordinate of the parent tile, `None` for the root tile.
+    pub fn parent(&self) -> Option<TileCoordinate> {
+        self.level
+            .checked_sub(1)
+            .and_then(|level| self.ancestor(level))
+    }
+
+    /// Coordinate of the ancestor on `level`, the tile itself on its own level and `None`
+    /// below it.
+    pub fn ancestor(&self, level: u32) -> Option<TileCoordinate> {
+        let shift = self.level.checked_sub(level)?;
+        let index = |index: u32| index.checked_shr(shift).unwrap_or(0);
+        Some(TileCoordinate {
+            level,
+            x: index(self.x),
+            y: index(self.y),
+            z: self.z.map(index),
+        })
+    }
+
+    /// Whether the tile is `ancestor` or one of its descendants.
+    pub fn is_descendant_of(&self, ancestor: &TileCoordinate) -> bool {
+        self.ancestor(ancestor.level).as_ref() == Some(ancestor)
+    }
+
+    /// Coordinate relative to `ancestor`, as if `ancestor` were the root tile. `None` if the
+    /// tile isn't a descendant of `ancestor`.
+    pub fn relative_to(&self, ancestor: &TileCoordinate) -> Option<TileCoordinate> {
+        if !self.is_descendant_of(ancestor) {
+            return None;
+        }
+        let shift = self.level - ancestor.level;
+        let mask = |index: u32| index & 1u32.checked_shl(shift).map_or(u32::MAX, |m| m - 1);
+        Some(TileCoordinate {
+            level: shift,
+            x: mask(self.x),
+            y: mask(self.y),
+            z: self.z.map(mask),
+        })
+    }
+
+    /// Coordinate of the root tile of the subtree containing the tile, given the number of
+    /// levels per subtree.
+    pub fn subtree_root(&self, subtree_levels: u32) -> TileCoordinate {
+        let level = self.level - self.level % subtree_levels.max(1);
+        self.ancestor(level).expect("level not below the tile")
+    }
+
+    /// Location of the tile in the subtree containing it, `None` if the Morton index within
+    /// the subtree exceeds 64 bits.
+    pub fn subtree_location(&self, subtree_levels: u32) -> Option<SubtreeLocation> {
+        let root = self.subtree_root(subtree_levels);
+        let relative = self.relative_to(&root)?;
+        Some(SubtreeLocation {
+            root,
+            level: relative.level,
+            morton_index: relative.morton_index()?,
+        })
+    }
+
+    /// Substitute the placeholders of a URI template with the coordinate.
+    ///
+    /// `{z}` is left unchanged for quadtree coordinates.
+    pub fn expand_template(&self, template: &str) -> String {
+        let mut uri = template
+            .replace("{level}", &self.level.to_string())
+            .replace("{x}", &self.x.to_string())
+            .replace("{y}", &self.y.to_string());
+        if let Some(z) = self.z {
+            uri = uri.replace("{z}", &z.to_string());
+        }
+        uri
+    }
+}
+
+/// Location of a tile in a subtree, see [`TileCoordinate::subtree_location`].
+///
+/// The availability of the tile is queried from the subtree with `level` and
+/// `morton_index`, e.g. with [`SubtreeAvailability::is_tile_available`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubtreeLocation {
+    /// Coordinate of the root tile of the subtree.
+    pub root: TileCoordinate,
+    /// Level relative to the root of the subtree.
+    pub level: u32,
+    /// Morton index within the level of the subtree.
+    pub morton_index: u64,
+}
+
+/// Availability of tiles and contents in an implicit tileset.
+pub trait Availability {
+    /// Whether the tile at `coordinate` exists.
+    fn is_tile_available(&mut self, coordinate: &TileCoordinate) -> Result<bool, Error>;
+
+    /// Whether content `content` of the tile at `coordinate` is available. Contents are
+    /// numbered in the order of the content templates of the implicit root tile.
+    fn is_content_available(
+        &mut self,
+        coordinate: &TileCoordinate,
+        content: usize,
+    ) -> Result<bool, Error>;
+}
+
+/// Availability of a full tree, where all tiles up to `availableLevels` exist and have
+/// content.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FullAvailability;
+
+impl Availability for FullAvailability {
+    fn is_tile_available(&mut self, _coordinate: &TileCoordinate) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    fn is_content_available(
+        &mut self,
+        _coordinate: &TileCoordinate,
+        _content: usize,
+    ) -> Result<bool, Error> {
+        Ok(true)
+    }
+}
+
+/// Availability from the subtree files of an implicit tileset, which are read on demand and
+/// kept in memory.
+pub struct SubtreeCache<'a, S: Source> {
+    loader: &'a TilesetLoader<S>,
+    tree: ImplicitTree<'a>,
+    /// Subtrees by the coordinate of their root tile, `None` if unavailable.
+    subtrees: HashMap<TileCoordinate, Option<SubtreeAvailability>>,
+}
+
+impl<'a, S: Source> SubtreeCache<'a, S> {
+    /// Read the subtrees of `tree` with `loader`. Subtree URIs must be resolved, as done by
+    /// the loader.
+    pub fn new(loader: &'a TilesetLoader<S>, tree: ImplicitTree<'a>) -> Self {
+        SubtreeCache {
+            loader,
+            tree,
+            subtrees: HashMap::new(),
+        }
+    }
+
+    /// The subtree starting at `root`, `None` if the parent subtree marks it as unavailable.
+    pub fn subtree(
+        &mut self,
+        root: &TileCoordinate,
+    ) -> Result<Option<&SubtreeAvailability>, Error> {
+        if !self.subtrees.contains_key(root) {
+            let uri = self.tree.subtree_uri(root).ok_or_else(|| {
+                Error::format(
+                    Section::Tileset,
+                    format!("no subtree starts at level {}", root.level),
+                )
+            })?;
+            let available = match root.parent() {
+                None => true,
+                Some(parent) => {
+                    // Child subtrees are indexed on the level below the parent subtree
+                    let parent_root = parent.subtree_root(self.levels());
+                    let index = root
+                        .relative_to(&parent_root)
+                        .and_then(|relative| relative.morton_index())
+                        .ok_or_else(|| self.unsupported_levels())?;
+                    self.subtree(&parent_root)?
+                        .map_or(false, |subtree| subtree.is_child_subtree_available(index))
+                }
+            };
+            let subtree = if available {
+                Some(self.loader.read_subtree(&uri, self.tree.tiling())?)
+            } else {
+                None
+            };
+            self.subtrees.insert(*root, subtree);
+        }
+        Ok(self.subtrees[root].as_ref())
+    }
+
+    fn levels(&self) -> u32 {
+        self.tree.tiling().subtree_levels
+    }
+
+    fn unsupported_levels(&self) -> Error {
+        Error::format(
+            Section::Tileset,
+            format!("unsupported number of subtree levels {}", self.levels()),
+        )
+    }
+
+    fn location(&self, coordinate: &TileCoordinate) -> Result<SubtreeLocation, Error> {
+        coordinate
+            .subtree_location(self.levels())
+            .ok_or_else(|| self.unsupported_levels())
+    }
+}
+
+impl<'a, S: Source> Availability for SubtreeCache<'a, S> {
+    fn is_tile_available(&mut self, coordinate: &TileCoordinate) -> Result<bool, Error> {
+        let location = self.location(coordinate)?;
+        Ok(self.subtree(&location.root)?.map_or(false, |subtree| {
+            subtree.is_tile_available(location.level, location.morton_index)
+        }))
+    }
+
+    fn is_content_available(
+        &mut self,
+        coordinate: &TileCoordinate,
+        content: usize,
+    ) -> Result<bool, Error> {
+        let location = self.location(coordinate)?;
+        Ok(self.subtree(&location.root)?.map_or(false, |subtree| {
+            subtree.is_content_available(content, location.level, location.morton_index)
+        }))
+    }
+}
+
+/// A tile of an implicit tileset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplicitTile {
+    pub coordinate: TileCoordinate,
+    /// Bounding volume, in the coordinate system of the implicit root tile.
+    pub bounding_volume: Volume,
+    pub geometric_error: f64,
+    /// URIs of the contents, one per content template of the implicit root tile, `None` if
+    /// the content isn't available.
+    pub content_uris: Vec<Option<String>>,
+}
+
+/// The tree of tiles below a tile with implicit tiling.
+#[derive(Debug, Clone, Copy)]
+pub struct ImplicitTree<'a> {
+    root: &'a Tile,
+    tiling: &'a ImplicitTiling,
+}
+
+impl<'a> ImplicitTree<'a> {
+    /// Tree of `root`. Fails if the tile has no implicit tiling or a bounding sphere.
+    pub fn new(root: &'a Tile) -> Result<Self, Error> {
+        let tiling = root
+            .implicit_tiling
+            .as_ref()
+            .ok_or_else(|| Error::format(Section::Tileset, "tile has no implicit tiling"))?;
+        if let Volume::Sphere(_) = root.bounding_volume.volume {
+            return Err(Error::format(
+                Section::Tileset,
+                "implicit tiling requires a box or region bounding volume",
+            ));
+        }
+        if tiling.subtree_levels == 0 || tiling.available_levels == 0 {
+            return Err(Error::format(
+                Section::Tileset,
+                "subtreeLevels and availableLevels must be at least 1",
+            ));
+        }
+        if tiling.available_levels > MAX_AVAILABLE_LEVELS {
+            return Err(Error::format(
+                Section::Tileset,
+                format!(
+                    "availableLevels {} exceeds the supported maximum of {}",
+                    tiling.available_levels, MAX_AVAILABLE_LEVELS
+                ),
+            ));
+        }
+        Ok(ImplicitTree { root, tiling })
+    }
+
+    /// The tile with the implicit tiling, whose content URIs are templates.
+    pub fn root_tile(&self) -> &'a Tile {
+        self.root
+    }
+
+    pub fn tiling(&self) -> &'a ImplicitTiling {
+        self.tiling
+    }
+
+    /// Whether `coordinate` is within the available levels and matches the subdivision scheme.
+    pub fn contains(&self, coordinate: &TileCoordinate) -> bool {
+        let within = |index: u32| (index as u64) < 1u64 << coordinate.level.min(63);
+        let octree = self.tiling.subdivision_scheme == SubdivisionScheme::Octree;
+        coordinate.level < self.tiling.available_levels
+            && within(coordinate.x)
+            && within(coordinate.y)
+            && coordinate.z.is_some() == octree
+            && coordinate.z.map_or(true, within)
+    }
+
+    /// The tile at `coordinate`, assuming that it and its content are available.
+    pub fn tile(&self, coordinate: TileCoordinate) -> ImplicitTile {
+        ImplicitTile {
+            coordinate,
+            bounding_volume: self
+                .root
+                .bounding_volume
+                .volume
+                .subdivide(&coordinate)
+                .expect("sphere rejected by ImplicitTree::new"),
+            geometric_error: self.root.geometric_error * 0.5f64.powi(coordinate.level as i32),
+            content_uris: self
+                .root
+                .all_contents()
+                .map(|content| Some(coordinate.expand_template(&content.uri)))
+                .collect(),
+        }
+    }
+
+    /// URI of the subtree starting at `coordinate`, `None` if no subtree starts at this level.
+    pub fn subtree_uri(&self, coordinate: &TileCoordinate) -> Option<String> {
+        if coordinate.level % self.tiling.subtree_levels == 0 {
+            Some(coordinate.expand_template(&self.tiling.subtrees.uri))
+        } else {
+            None
+        }
+    }
+
+    /// The root tile.
+    pub fn root<A: Availability>(&self, availability: &mut A) -> Result<ImplicitTile, Error> {
+        let coordinate = TileCoordinate::root(self.tiling.subdivision_scheme);
+        self.available_tile(coordinate, availability)
+    }
+
+    /// The available children of `tile`.
+    pub fn children<A: Availability>(
+        &self,
+        tile: &ImplicitTile,
+        availability: &mut A,
+    ) -> Result<Vec<ImplicitTile>, Error> {
+        let mut children = Vec::new();
+        for coordinate in tile.coordinate.children() {
+            if self.contains(&coordinate) && availability.is_tile_available(&coordinate)? {
+                children.push(self.available_tile(coordinate, availability)?);
+            }
+        }
+        Ok(children)
+    }
+
+    fn available_tile<A: Availability>(
+        &self,
+        coordinate: TileCoordinate,
+        availability: &mut A,
+    ) -> Result<ImplicitTile, Error> {
+        let mut tile = self.tile(coordinate);
+        for (content, uri) in tile.content_uris.iter_mut().enumerate() {
+            if !availability.is_content_available(&coordinate, content)? {
+                *uri = None;
+            }
+        }
+        Ok(tile)
+    }
+
+    /// Expand the available tiles up to `max_level` into an explicit tile tree.
+    ///
+    /// The returned root tile keeps the transform, refinement and other properties of the
+    /// implicit root tile, but no implicit tiling.
+    pub fn expand<A: Availability>(
+        &self,
+        availability: &mut A,
+        max_level: Option<u32>,
+    ) -> Result<Tile, Error> {
+        let root = self.root(availability)?;
+        let mut tile = self.expand_tile(&root, availability, max_level)?;
+        tile.expire = self.root.expire.clone();
+        tile.extensions = self.root.extensions.clone();
+        tile.extras = self.root.extras.clone();
+        tile.refine = self.root.refine;
+        tile.transform = self.root.transform;
+        tile.viewer_request_volume = self.root.viewer_request_volume.clone();
+        tile.other = self.root.other.clone();
+        Ok(tile)
+    }
+
+    fn expand_tile<A: Availability>(
+        &self,
+        tile: &ImplicitTile,
+        availability: &mut A,
+        max_level: Option<u32>,
+    ) -> Result<Tile, Error> {
+        let children = if max_level.map_or(true, |max_level| tile.coordinate.level < max_level) {
+            let mut children = Vec::new();
+            for child in self.children(tile, availability)? {
+                children.push(self.expand_tile(&child, availability, max_level)?);
+            }
+            Some(children).filter(|children| !children.is_empty())
+        } else {
+            None
+        };
+        Ok(self.explicit_tile(tile, children))
+    }
+
+    /// Explicit tile without transform and refinement. The available contents keep the group
+    /// of their template.
+    fn explicit_tile(&self, tile: &ImplicitTile, children: Option<Vec<Tile>>) -> Tile {
+        let mut contents = self
+            .root
+            .all_contents()
+            .zip(&tile.content_uris)
+            .filter_map(|(template, uri)| {
+                uri.as_ref().map(|uri| TileContent {
+                    bounding_volume: None,
+                    extensions: None,
+                    extras: None,
+                    group: template.group,
+                    uri: uri.clone(),
+                    other: HashMap::new(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let (content, contents) = if self.root.contents.is_some() {
+            (None, Some(contents).filter(|contents| !contents.is_empty()))
+        } else {
+            (contents.pop(), None)
+        };
+        Tile {
+            bounding_volume: BoundingVolume::from(tile.bounding_volume),
+            children,
+            content,
+            contents,
+            expire: None,
+            extensions: None,
+            extras: None,
+            geometric_error: tile.geometric_error,
+            implicit_tiling: None,
+            refine: None,
+            transform: None,
+            viewer_request_volume: None,
+            other: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding_volume::OrientedBox;
+    use crate::matrix::Matrix4;
+    use crate::subtree::SubtreeAvailability;
+    use crate::test_util::MemorySource;
+    use crate::tileset::Refine;
+
+    /// Quadtree root tile with a 8x8x2 box and content template, with `tiling` overriding
+    /// the default implicit tiling.
+    fn implicit_root(tiling: serde_json::Value) -> Tile {
+        let mut json = serde_json::json!({
+            "boundingVolume": {"box": [0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 1]},
+            "geometricError": 16,
+            "refine": "REPLACE",
+            "transform": [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 10, 20, 30, 1],
+            "content": {"uri": "content/{level}/{x}/{y}.glb"},
+            "implicitTiling": {
+                "subdivisionScheme": "QUADTREE",
+                "subtreeLevels": 2,
+                "availableLevels": 3,
+                "subtrees": {"uri": "subtrees/{level}/{x}/{y}.subtree"},
+            },
+        });
+        for (key, value) in tiling.as_object().unwrap() {
+            json["implicitTiling"][key] = value.clone();
+        }
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn coordinate(level: u32, x: u32, y: u32) -> TileCoordinate {
+        TileCoordinate {
+            level,
+            x,
+            y,
+            z: None,
+        }
+    }
+
+    /// Content URIs of the tiles of an expanded tree, depth-first, `-` for tiles without content.
+    fn content_uris(tile: &Tile) -> Vec<String> {
+        tile.depth_first()
+            .map(|node| {
+                node.tile
+                    .content
+                    .as_ref()
+                    .map_or("-".to_string(), |content| content.uri.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn children() {
+        let children: Vec<_> = coordinate(1, 1, 0).children().collect();
+        assert_eq!(
+            children,
+            vec![
+                coordinate(2, 2, 0),
+                coordinate(2, 3, 0),
+                coordinate(2, 2, 1),
+                coordinate(2, 3, 1)
+            ]
+        );
+        let octree = TileCoordinate::root(SubdivisionScheme::Octree);
+        let children: Vec<_> = octree.children().collect();
+        assert_eq!(children.len(), 8);
+        assert_eq!(children[5].z, Some(1));
+        assert_eq!((children[5].x, children[5].y), (1, 0));
+        assert!(children.iter().all(|child| child.parent() == Some(octree)));
+
+        // Indexes beyond 32 bits
+        assert_eq!(coordinate(32, u32::MAX, 0).children().count(), 0);
+        let last = coordinate(31, u32::MAX >> 1, u32::MAX >> 1);
+        assert_eq!(
+            last.children().last(),
+            Some(coordinate(32, u32::MAX, u32::MAX))
+        );
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(
+            coordinate(3, 5, 1).expand_template("{level}/{x}/{y}/{z}.glb"),
+            "3/5/1/{z}.glb"
+        );
+        let octree = TileCoordinate {
+            z: Some(7),
+            ..coordinate(3, 5, 1)
+        };
+        assert_eq!(octree.expand_template("{level}-{x}-{y}-{z}"), "3-5-1-7");
+    }
+
+    #[test]
+    fn reject_invalid_tiling() {
+        let root = implicit_root(serde_json::json!({}));
+        assert!(ImplicitTree::new(&root).is_ok());
+        for tiling in &[
+            serde_json::json!({"subtreeLevels": 0}),
+            serde_json::json!({"availableLevels": 0}),
+            serde_json::json!({"availableLevels": 33}),
+        ] {
+            let root = implicit_root(tiling.clone());
+            assert!(ImplicitTree::new(&root).is_err(), "{}", tiling);
+        }
+        let root = implicit_root(serde_json::json!({"availableLevels": 32}));
+        assert!(ImplicitTree::new(&root).is_ok());
+
+        let mut sphere = implicit_root(serde_json::json!({}));
+        sphere.bounding_volume = serde_json::from_str(r#"{"sphere": [0, 0, 0, 1]}"#).unwrap();
+        assert!(ImplicitTree::new(&sphere).is_err());
+        sphere.implicit_tiling = None;
+        assert!(ImplicitTree::new(&sphere).is_err());
+    }
+
+    #[test]
+    fn derived_tiles() {
+        let root = implicit_root(serde_json::json!({}));
+        let tree = ImplicitTree::new(&root).unwrap();
+        assert!(tree.contains(&coordinate(2, 3, 3)));
+        assert!(!tree.contains(&coordinate(2, 4, 0)));
+        assert!(!tree.contains(&coordinate(3, 0, 0)));
+        assert!(!tree.contains(&TileCoordinate {
+            z: Some(0),
+            ..coordinate(1, 0, 0)
+        }));
+
+        let tile = tree.tile(coordinate(1, 1, 0));
+        assert_eq!(
+            tile.bounding_volume,
+            Volume::Box(OrientedBox {
+                center: [2.0, -2.0, 0.0],
+                half_axes: [[2.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 1.0]],
+            })
+        );
+        assert_eq!(tile.geometric_error, 8.0);
+        assert_eq!(
+            tile.content_uris,
+            vec![Some("content/1/1/0.glb".to_string())]
+        );
+
+        assert_eq!(
+            tree.subtree_uri(&coordinate(2, 3, 1)),
+            Some("subtrees/2/3/1.subtree".to_string())
+        );
+        assert_eq!(tree.subtree_uri(&coordinate(1, 1, 0)), None);
+    }
+
+    #[test]
+    fn expand_full_tree() {
+        let root = implicit_root(serde_json::json!({}));
+        let tree = ImplicitTree::new(&root).unwrap();
+        let expanded = tree.expand(&mut FullAvailability, None).unwrap();
+        assert_eq!(expanded.depth_first().count(), 1 + 4 + 16);
+        assert!(expanded.implicit_tiling.is_none());
+        assert_eq!(expanded.refine, Some(Refine::Replace));
+        assert_eq!(
+            expanded.transform,
+            Some(Matrix4::from_translation([10.0, 20.0, 30.0]))
+        );
+        let leaf = &expanded.children.as_ref().unwrap()[3]
+            .children
+            .as_ref()
+            .unwrap()[0];
+        assert_eq!(leaf.geometric_error, 4.0);
+        assert_eq!(leaf.content.as_ref().unwrap().uri, "content/2/2/2.glb");
+
+        let expanded = tree.expand(&mut FullAvailability, Some(1)).unwrap();
+        assert_eq!(expanded.depth_first().count(), 1 + 4);
+        let expanded = tree.expand(&mut FullAvailability, Some(0)).unwrap();
+        assert!(expanded.children.is_none());
+    }
+
+    fn subtree_bytes(availability: &SubtreeAvailability) -> Vec<u8> {
+        let mut data = Vec::new();
+        availability.to_subtree().to_writer(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn expand_with_subtrees() {
+        let scheme = SubdivisionScheme::Quadtree;
+        // Root and tile (1, 1, 0) with content, the child subtree below it at (2, 3, 0)
+        let mut root_subtree = SubtreeAvailability::new(scheme, 2, 1).unwrap();
+        root_subtree.set_tile_available(0, 0, true);
+        root_subtree.set_tile_available(1, 1, true);
+        root_subtree.set_content_available(0, 1, 1, true);
+        root_subtree.set_child_subtree_available(5, true);
+        let mut child_subtree = SubtreeAvailability::new(scheme, 2, 1).unwrap();
+        child_subtree.set_tile_available(0, 0, true);
+        child_subtree.set_content_available(0, 0, 0, true);
+        let mut resources = HashMap::new();
+        resources.insert(
+            "subtrees/0/0/0.subtree".to_string(),
+            subtree_bytes(&root_subtree),
+        );
+        resources.insert(
+            "subtrees/2/3/0.subtree".to_string(),
+            subtree_bytes(&child_subtree),
+        );
+        let loader = TilesetLoader::new(MemorySource(resources));
+
+        let root = implicit_root(serde_json::json!({}));
+        let tree = ImplicitTree::new(&root).unwrap();
+        let mut subtrees = SubtreeCache::new(&loader, tree);
+        assert!(subtrees.is_tile_available(&coordinate(2, 3, 0)).unwrap());
+        assert!(!subtrees.is_tile_available(&coordinate(2, 0, 0)).unwrap());
+        assert!(!subtrees
+            .is_content_available(&coordinate(0, 0, 0), 0)
+            .unwrap());
+        let expanded = tree.expand(&mut subtrees, None).unwrap();
+        assert_eq!(
+            content_uris(&expanded),
+            vec!["-", "content/1/1/0.glb", "content/2/3/0.glb"]
+        );
+
+        // Missing subtrees are errors
+        let loader = TilesetLoader::new(MemorySource(HashMap::new()));
+        let mut subtrees = SubtreeCache::new(&loader, tree);
+        assert!(tree.expand(&mut subtrees, None).is_err());
+    }
+
+    #[test]
+    fn morton_index() {
+        assert_eq!(coordinate(0, 0, 0).morton_index(), Some(0));
+        assert_eq!(coordinate(2, 3, 0).morton_index(), Some(5));
+        assert_eq!(coordinate(2, 0, 3).morton_index(), Some(10));
+        assert_eq!(coordinate(2, 3, 3).morton_index(), Some(15));
+        let octree = TileCoordinate {
+            z: Some(1),
+            ..coordinate(1, 1, 0)
+        };
+        assert_eq!(octree.morton_index(), Some(5));
+        assert_eq!(
+            coordinate(32, u32::MAX, u32::MAX).morton_index(),
+            Some(u64::MAX)
+        );
+        assert_eq!(coordinate(33, 0, 0).morton_index(), None);
+        let deep_octree = TileCoordinate {
+            z: Some(0),
+            ..coordinate(22, 0, 0)
+        };
+        assert_eq!(deep_octree.morton_index(), None);
+    }
+
+    #[test]
+    fn morton_round_trip() {
+        for &(scheme, dimensions, max_level) in &[
+            (SubdivisionScheme::Quadtree, 2, 32),
+            (SubdivisionScheme::Octree, 3, 21),
+        ] {
+            for level in 0..=max_level {
+                // Number of tiles on the level
+                let count = 1u128 << (dimensions * level);
+                for &index in &[0, 1, 6, count / 3, count - 1] {
+                    if index >= count {
+                        continue;
+                    }
+                    let coordinate = TileCoordinate::from_morton_index(scheme, level, index as u64);
+                    assert_eq!(coordinate.level, level);
+                    assert_eq!(coordinate.z.is_some(), dimensions == 3);
+                    assert_eq!(coordinate.morton_index(), Some(index as u64));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ancestors() {
+        let tile = coordinate(3, 5, 2);
+        assert_eq!(tile.parent(), Some(coordinate(2, 2, 1)));
+        assert_eq!(tile.ancestor(1), Some(coordinate(1, 1, 0)));
+        assert_eq!(tile.ancestor(3), Some(tile));
+        assert_eq!(tile.ancestor(4), None);
+        assert_eq!(coordinate(0, 0, 0).parent(), None);
+        assert!(tile.is_descendant_of(&coordinate(1, 1, 0)));
+        assert!(tile.is_descendant_of(&tile));
+        assert!(!tile.is_descendant_of(&coordinate(1, 0, 0)));
+        assert!(!coordinate(1, 1, 0).is_descendant_of(&tile));
+        assert_eq!(
+            tile.relative_to(&coordinate(1, 1, 0)),
+            Some(coordinate(2, 1, 2))
+        );
+        assert_eq!(tile.relative_to(&coordinate(1, 0, 0)), None);
+        for child in tile.children() {
+            assert_eq!(child.parent(), Some(tile));
+        }
+    }
+
+    #[test]
+    fn subtree_locations() {
+        let tile = coordinate(5, 13, 6);
+        assert_eq!(tile.subtree_root(2), coordinate(4, 6, 3));
+        assert_eq!(tile.subtree_root(3), coordinate(3, 3, 1));
+        assert_eq!(tile.subtree_root(10), coordinate(0, 0, 0));
+        assert_eq!(
+            tile.subtree_location(3),
+            Some(SubtreeLocation {
+                root: coordinate(3, 3, 1),
+                level: 2,
+                // x = 0b01, y = 0b10
+                morton_index: 0b1001,
+            })
+        );
+        let location = coordinate(4, 6, 3).subtree_location(2).unwrap();
+        assert_eq!((location.level, location.morton_index), (0, 0));
+        // The Morton index within a subtree of 40 levels exceeds 64 bits
+        assert_eq!(coordinate(33, 0, 0).subtree_location(40), None);
+    }
+}
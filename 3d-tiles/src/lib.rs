@@ -1,7 +1,42 @@
 pub mod b3dm;
 pub mod batch_table;
+pub mod batch_table_hierarchy;
+mod binary;
+pub mod bounding_volume;
+pub mod cmpt;
+pub mod content;
+pub mod encoding;
 pub mod error;
 pub mod feature_table;
+pub mod geodesy;
 pub mod i3dm;
+pub mod implicit;
+pub mod limits;
+pub mod loader;
+mod math;
+pub mod matrix;
 pub mod pnts;
+pub mod selection;
+pub mod subtree;
 pub mod tileset;
+pub mod validate;
+
+#[cfg(test)]
+mod test_util;
+
+/// Path of a file of `data/3d-tiles-samples`.
+#[cfg(test)]
+pub(crate) fn sample_path(name: &str) -> String {
+    format!(
+        "{}/../data/3d-tiles-samples/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+/// Read a file of `data/3d-tiles-samples`.
+#[cfg(test)]
+pub(crate) fn sample(name: &str) -> Vec<u8> {
+    let path = sample_path(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
@@ -0,0 +1,39 @@
+//! Limits for reading untrusted tiles.
+
+/// Limits checked against the tile header before any section is read.
+///
+/// Sections are read incrementally, so memory use is bounded by the data actually available,
+/// but a single tile may still be up to 4 GiB. The default limits only prevent excessive
+/// recursion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum `byteLength` of a tile and maximum length of a subtree binary chunk.
+    pub max_byte_length: u64,
+    /// Maximum length of a Feature Table, Batch Table or subtree JSON section.
+    pub max_json_byte_length: u64,
+    /// Maximum nesting depth of Composite tiles. A Composite without nested Composites has
+    /// depth 1.
+    pub max_composite_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_byte_length: u64::from(u32::MAX),
+            max_json_byte_length: u64::from(u32::MAX),
+            max_composite_depth: 16,
+        }
+    }
+}
+
+impl Limits {
+    /// Limits for tiles from untrusted sources, e.g. user uploads: 256 MiB per tile, 16 MiB
+    /// per JSON section and 4 levels of Composite tiles.
+    pub fn untrusted() -> Self {
+        Limits {
+            max_byte_length: 256 * 1024 * 1024,
+            max_json_byte_length: 16 * 1024 * 1024,
+            max_composite_depth: 4,
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! Loading of tilesets with external tilesets.
+//!
+//! A tile content may reference another tileset JSON, which is grafted into the referring
+//! tileset with its root tile as only child of the referring tile. See
+//! <https://github.com/CesiumGS/3d-tiles/tree/1.0/specification#external-tilesets>
+//!
+//! Subtrees of implicit tilesets are read with [`TilesetLoader::read_subtree`].
+
+use crate::content::ContentType;
+use crate::error::{Error, Section};
+use crate::subtree::{Subtree, SubtreeAvailability};
+use crate::tileset::{ImplicitTiling, Tile, Tileset};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Extensions which may be listed in `extensionsRequired` of a loaded tileset.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["3DTILES_batch_table_hierarchy"];
+
+/// Backend providing tilesets and tile contents.
+pub trait Source {
+    /// Read the resource identified by `uri`.
+    fn read(&self, uri: &str) -> Result<Vec<u8>, Error>;
+
+    /// Resolve `uri`, relative to the resource identified by `base`, to an identifier for
+    /// [`Source::read`].
+    fn resolve(&self, base: &str, uri: &str) -> String;
+}
+
+/// Source reading files. Relative URIs are resolved against the directory of the referring
+/// file.
+#[derive(Debug, Clone, Default)]
+pub struct FileSource;
+
+impl Source for FileSource {
+    fn read(&self, uri: &str) -> Result<Vec<u8>, Error> {
+        fs::read(uri).map_err(|e| Error::Io(e).with_path(uri))
+    }
+
+    fn resolve(&self, base: &str, uri: &str) -> String {
+        let path = Path::new(base)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(uri);
+        // Normalize lexically, so that references to the same file are equal
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => match normalized.components().next_back() {
+                    Some(Component::Normal(_)) => {
+                        normalized.pop();
+                    }
+                    Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                    _ => normalized.push(".."),
+                },
+                component => normalized.push(component),
+            }
+        }
+        normalized.to_string_lossy().into_owned()
+    }
+}
+
+/// Whether the content `uri` references an external tileset, judging by its `.json`
+/// extension.
+///
+/// This is a heuristic for when the content isn't available: content URIs don't need an
+/// extension and glTF JSON may use `.json` too. Use [`ContentType::detect`] on the data to
+/// know for sure.
+pub fn is_tileset_uri(uri: &str) -> bool {
+    let path = uri.split(['?', '#']).next().unwrap_or(uri);
+    path.to_ascii_lowercase().ends_with(".json")
+}
+
+/// A tileset and the identifier it was loaded from.
+///
+/// All content URIs in the tree are resolved against the source, including the ones of
+/// grafted external tilesets.
+#[derive(Debug)]
+pub struct LoadedTileset {
+    /// Source identifier of the tileset.
+    pub uri: String,
+    pub tileset: Tileset,
+}
+
+/// Loads tilesets from a [`Source`] and resolves external tilesets.
+#[derive(Debug, Clone)]
+pub struct TilesetLoader<S: Source> {
+    source: S,
+}
+
+impl<S: Source> TilesetLoader<S> {
+    pub fn new(source: S) -> Self {
+        TilesetLoader { source }
+    }
+
+    /// The source of the loader, e.g. for reading tile contents.
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Load the tileset at `uri` and resolve its content URIs, without loading external
+    /// tilesets.
+    ///
+    /// Fails if the tileset requires an extension which isn't in [`SUPPORTED_EXTENSIONS`].
+    pub fn load(&self, uri: &str) -> Result<LoadedTileset, Error> {
+        let tileset = self.read_tileset(uri)?;
+        Ok(LoadedTileset {
+            uri: uri.to_string(),
+            tileset,
+        })
+    }
+
+    /// Load the tileset at `uri` and graft all external tilesets recursively.
+    pub fn load_eager(&self, uri: &str) -> Result<LoadedTileset, Error> {
+        let mut loaded = self.load(uri)?;
+        let mut chain = vec![loaded.uri.clone()];
+        self.expand_all(&mut loaded.tileset.root, &mut chain)?;
+        Ok(loaded)
+    }
+
+    /// Graft the external tileset referenced by the tile at `path`, given as child indexes
+    /// from the root.
+    ///
+    /// Returns `false` if the tile doesn't reference an external tileset or if it was already
+    /// grafted. Fails with [`Error::TileNotFound`] if there is no tile at `path`, and with
+    /// [`Error::Format`] if the external tileset is one of the tilesets it was reached from.
+    pub fn expand(&self, loaded: &mut LoadedTileset, path: &[usize]) -> Result<bool, Error> {
+        let mut chain = vec![loaded.uri.clone()];
+        let mut tile = &mut loaded.tileset.root;
+        for index in path {
+            chain.extend(external_tileset_uri(tile).map(str::to_string));
+            tile = tile
+                .children
+                .as_mut()
+                .and_then(|children| children.get_mut(*index))
+                .ok_or_else(|| Error::TileNotFound {
+                    path: path.to_vec(),
+                })?;
+        }
+        self.graft(tile, &chain)
+    }
+
+    /// Read the subtree at `uri` of an implicit tileset with `tiling`, in binary or JSON form,
+    /// and decode its availability. External buffers are resolved against `uri`.
+    pub fn read_subtree(
+        &self,
+        uri: &str,
+        tiling: &ImplicitTiling,
+    ) -> Result<SubtreeAvailability, Error> {
+        let data = self.source.read(uri)?;
+        let subtree = if data.starts_with(b"subt") {
+            Subtree::from_reader(&data[..])
+        } else {
+            Subtree::from_json_reader(&data[..])
+        }
+        .map_err(|e| e.with_path(uri))?;
+        let buffers = subtree
+            .buffer_data(|buffer_uri| self.source.read(&self.source.resolve(uri, buffer_uri)))?;
+        subtree
+            .availability(&buffers, tiling.subdivision_scheme, tiling.subtree_levels)
+            .map_err(|e| e.with_path(uri))
+    }
+
+    fn read_tileset(&self, uri: &str) -> Result<Tileset, Error> {
+        self.parse_tileset(uri, &self.source.read(uri)?)
+    }
+
+    fn parse_tileset(&self, uri: &str, json: &[u8]) -> Result<Tileset, Error> {
+        let tileset = self.parse_tileset_unchecked(uri, json)?;
+        if let Some(name) = tileset
+            .extensions_required
+            .iter()
+            .flatten()
+            .find(|name| !SUPPORTED_EXTENSIONS.contains(&name.as_str()))
+        {
+            return Err(Error::UnsupportedExtension {
+                section: Section::Tileset,
+                offset: 0,
+                name: name.clone(),
+            }
+            .with_path(uri));
+        }
+        Ok(tileset)
+    }
+
+    /// Read the tileset at `uri` and resolve its content URIs, without checking the required
+    /// extensions.
+    pub(crate) fn read_tileset_unchecked(&self, uri: &str) -> Result<Tileset, Error> {
+        self.parse_tileset_unchecked(uri, &self.source.read(uri)?)
+    }
+
+    fn parse_tileset_unchecked(&self, uri: &str, json: &[u8]) -> Result<Tileset, Error> {
+        let mut tileset = Tileset::from_reader(json).map_err(|e| e.with_path(uri))?;
+        self.resolve_uris(&mut tileset.root, uri);
+        Ok(tileset)
+    }
+
+    fn resolve_uris(&self, tile: &mut Tile, base: &str) {
+        for content in tile
+            .content
+            .iter_mut()
+            .chain(tile.contents.iter_mut().flatten())
+        {
+            content.uri = self.source.resolve(base, &content.uri);
+        }
+        if let Some(ref mut tiling) = tile.implicit_tiling {
+            tiling.subtrees.uri = self.source.resolve(base, &tiling.subtrees.uri);
+        }
+        for child in tile.children.iter_mut().flatten() {
+            self.resolve_uris(child, base);
+        }
+    }
+
+    /// Graft the external tileset of `tile`, which is reached through the tilesets in `chain`.
+    ///
+    /// Candidates are found by their URI, but only grafted if their data is a tileset.
+    fn graft(&self, tile: &mut Tile, chain: &[String]) -> Result<bool, Error> {
+        let uri = match external_tileset_uri(tile) {
+            Some(uri) => uri.to_string(),
+            None => return Ok(false),
+        };
+        if tile.children.as_ref().map_or(false, |c| !c.is_empty()) {
+            return Ok(false);
+        }
+        if chain.contains(&uri) {
+            return Err(Error::format(
+                Section::Tileset,
+                format!("external tileset cycle: {} -> {}", chain.join(" -> "), uri),
+            ));
+        }
+        let data = self.source.read(&uri)?;
+        match ContentType::detect(&data) {
+            // Invalid JSON is parsed for the error
+            ContentType::Tileset | ContentType::Unknown => {}
+            _ => return Ok(false),
+        }
+        let tileset = self.parse_tileset(&uri, &data)?;
+        tile.children = Some(vec![tileset.root]);
+        Ok(true)
+    }
+
+    fn expand_all(&self, tile: &mut Tile, chain: &mut Vec<String>) -> Result<(), Error> {
+        self.graft(tile, chain)?;
+        let external = external_tileset_uri(tile).map(str::to_string);
+        let pushed = external.is_some();
+        chain.extend(external);
+        for child in tile.children.iter_mut().flatten() {
+            self.expand_all(child, chain)?;
+        }
+        if pushed {
+            chain.pop();
+        }
+        Ok(())
+    }
+}
+
+fn external_tileset_uri(tile: &Tile) -> Option<&str> {
+    if tile.implicit_tiling.is_some() {
+        // The content URI is a template for the implicit tiles
+        return None;
+    }
+    tile.content
+        .as_ref()
+        .map(|content| content.uri.as_str())
+        .filter(|uri| is_tileset_uri(uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_path;
+    use crate::test_util::{tileset, MemorySource};
+
+    #[test]
+    fn resolve_file_uris() {
+        assert_eq!(
+            FileSource.resolve("a/b/tileset.json", "c.b3dm"),
+            "a/b/c.b3dm"
+        );
+        assert_eq!(
+            FileSource.resolve("a/b/tileset.json", "./c/../../d.json"),
+            "a/d.json"
+        );
+        assert_eq!(FileSource.resolve("tileset.json", "../c.b3dm"), "../c.b3dm");
+        assert_eq!(FileSource.resolve("/tileset.json", "../c.b3dm"), "/c.b3dm");
+    }
+
+    #[test]
+    fn tileset_uris() {
+        assert!(is_tileset_uri("external.json"));
+        assert!(is_tileset_uri("dir/External.JSON?v=1"));
+        assert!(!is_tileset_uri("tile.b3dm"));
+        assert!(!is_tileset_uri("tile.b3dm?format=.json"));
+    }
+
+    #[test]
+    fn load_sample_eager() {
+        let loader = TilesetLoader::new(FileSource);
+        let uri = sample_path("TilesetWithRequestVolume/tileset.json");
+        let loaded = loader.load_eager(&uri).unwrap();
+        let children = loaded.tileset.root.children.as_ref().unwrap();
+        let city = &children[0].children.as_ref().unwrap()[0];
+        let uris: Vec<_> = city
+            .children
+            .iter()
+            .flatten()
+            .map(|tile| tile.content.as_ref().unwrap().uri.clone())
+            .collect();
+        assert_eq!(uris.len(), 4);
+        assert!(uris[0].ends_with("TilesetWithRequestVolume/city/ll.b3dm"));
+        assert!(Path::new(&uris[0]).exists());
+    }
+
+    #[test]
+    fn expand_lazily() {
+        let loader = TilesetLoader::new(FileSource);
+        let uri = sample_path("TilesetWithRequestVolume/tileset.json");
+        let mut loaded = loader.load(&uri).unwrap();
+        assert!(!loader.expand(&mut loaded, &[1]).unwrap());
+        assert!(loader.expand(&mut loaded, &[0]).unwrap());
+        assert!(!loader.expand(&mut loaded, &[0]).unwrap());
+        assert!(!loader.expand(&mut loaded, &[0, 0, 2]).unwrap());
+        match loader.expand(&mut loaded, &[0, 0, 7]) {
+            Err(Error::TileNotFound { path }) => assert_eq!(path, [0, 0, 7]),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn external_tileset_cycle() {
+        let loader = TilesetLoader::new(MemorySource::new(&[
+            ("a.json", tileset("b.json")),
+            ("b.json", tileset("sub/c.json")),
+            ("sub/c.json", tileset("../a.json")),
+        ]));
+        match loader.load_eager("a.json") {
+            Err(Error::Format { message, .. }) => {
+                assert!(message.contains("a.json -> b.json -> sub/c.json -> a.json"))
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        // Referencing the same tileset from siblings is not a cycle
+        let mut root = tileset("b.json");
+        let child = root["root"].clone();
+        root["root"]["content"]["uri"] = serde_json::json!("tile.b3dm");
+        root["root"]["children"] = serde_json::json!([child, child]);
+        let loader = TilesetLoader::new(MemorySource::new(&[
+            ("a.json", root),
+            ("b.json", tileset("tile.b3dm")),
+        ]));
+        let loaded = loader.load_eager("a.json").unwrap();
+        for child in loaded.tileset.root.children.iter().flatten() {
+            assert_eq!(child.children.as_ref().map(Vec::len), Some(1));
+        }
+    }
+
+    #[test]
+    fn unsupported_extension() {
+        let mut json = tileset("tile.b3dm");
+        json["extensionsRequired"] = serde_json::json!(["3DTILES_unknown"]);
+        let loader = TilesetLoader::new(MemorySource::new(&[("a.json", json)]));
+        let error = loader.load("a.json").unwrap_err();
+        assert_eq!(error.path(), Some(&PathBuf::from("a.json")));
+        assert!(error.to_string().contains("3DTILES_unknown"));
+    }
+
+    #[test]
+    fn gltf_json_content() {
+        let gltf = serde_json::json!({"asset": {"version": "2.0"}});
+        let loader = TilesetLoader::new(MemorySource::new(&[
+            ("a.json", tileset("model.json")),
+            ("model.json", gltf),
+        ]));
+        let loaded = loader.load_eager("a.json").unwrap();
+        assert!(loaded.tileset.root.children.is_none());
+        let mut loaded = loader.load("a.json").unwrap();
+        assert!(!loader.expand(&mut loaded, &[]).unwrap());
+    }
+}
@@ -0,0 +1,248 @@
+//! 4x4 transformation matrices.
+
+use crate::math::{add, length, sub};
+use serde_derive::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Mul;
+
+/// A 4x4 affine transformation matrix with `f64` elements, stored in column-major order.
+///
+/// Deserializing validates that the matrix has 16 finite elements and that its last row is
+/// `[0, 0, 0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<f64>", into = "Vec<f64>")]
+pub struct Matrix4(pub [f64; 16]);
+
+/// Error of an invalid transformation matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidMatrix(String);
+
+impl fmt::Display for InvalidMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid transform matrix: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidMatrix {}
+
+impl Matrix4 {
+    pub const IDENTITY: Matrix4 = Matrix4([
+        1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+    ]);
+
+    /// Translation matrix.
+    pub fn from_translation(translation: [f64; 3]) -> Self {
+        let mut m = Self::IDENTITY;
+        m.0[12..15].copy_from_slice(&translation);
+        m
+    }
+
+    /// Matrix from `f32` elements in column-major order.
+    pub fn from_f32(elements: [f32; 16]) -> Self {
+        let mut m = [0.0; 16];
+        for (v, e) in m.iter_mut().zip(elements.iter()) {
+            *v = f64::from(*e);
+        }
+        Matrix4(m)
+    }
+
+    /// Translation part of the matrix.
+    pub fn translation(&self) -> [f64; 3] {
+        [self.0[12], self.0[13], self.0[14]]
+    }
+
+    /// Product `self * other`, i.e. `other` is applied first.
+    pub fn multiply(&self, other: &Matrix4) -> Matrix4 {
+        let (a, b) = (&self.0, &other.0);
+        let mut m = [0.0; 16];
+        for col in 0..4 {
+            for row in 0..4 {
+                m[col * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[col * 4 + k]).sum();
+            }
+        }
+        Matrix4(m)
+    }
+
+    /// Transform a point.
+    pub fn transform_point(&self, p: [f64; 3]) -> [f64; 3] {
+        add(self.transform_vector(p), self.translation())
+    }
+
+    /// Transform a direction, ignoring the translation.
+    pub fn transform_vector(&self, v: [f64; 3]) -> [f64; 3] {
+        let m = &self.0;
+        [
+            m[0] * v[0] + m[4] * v[1] + m[8] * v[2],
+            m[1] * v[0] + m[5] * v[1] + m[9] * v[2],
+            m[2] * v[0] + m[6] * v[1] + m[10] * v[2],
+        ]
+    }
+
+    /// Largest scale factor along the axes.
+    pub fn max_scale(&self) -> f64 {
+        let m = &self.0;
+        length([m[0], m[1], m[2]])
+            .max(length([m[4], m[5], m[6]]))
+            .max(length([m[8], m[9], m[10]]))
+    }
+
+    /// Convert to `f32` with the translation relative to `origin`.
+    ///
+    /// ECEF translations lose about half a meter in `f32`, so renderers should place an
+    /// origin close to the content and convert relative to it.
+    pub fn to_f32_relative_to(&self, origin: [f64; 3]) -> [f32; 16] {
+        let mut m = *self;
+        m.0[12..15].copy_from_slice(&sub(self.translation(), origin));
+        let mut elements = [0.0; 16];
+        for (e, v) in elements.iter_mut().zip(m.0.iter()) {
+            *e = *v as f32;
+        }
+        elements
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        self.multiply(&other)
+    }
+}
+
+impl TryFrom<Vec<f64>> for Matrix4 {
+    type Error = InvalidMatrix;
+
+    fn try_from(elements: Vec<f64>) -> Result<Self, Self::Error> {
+        if elements.len() != 16 {
+            return Err(InvalidMatrix(format!(
+                "expected 16 elements, found {}",
+                elements.len()
+            )));
+        }
+        if elements.iter().any(|e| !e.is_finite()) {
+            return Err(InvalidMatrix("elements must be finite".to_string()));
+        }
+        if elements[3] != 0.0 || elements[7] != 0.0 || elements[11] != 0.0 || elements[15] != 1.0 {
+            return Err(InvalidMatrix("last row must be [0, 0, 0, 1]".to_string()));
+        }
+        let mut m = [0.0; 16];
+        m.copy_from_slice(&elements);
+        Ok(Matrix4(m))
+    }
+}
+
+impl From<Matrix4> for Vec<f64> {
+    fn from(m: Matrix4) -> Self {
+        m.0.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scale by 2 followed by a translation to an ECEF position.
+    fn ecef_transform() -> Matrix4 {
+        Matrix4([
+            2.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            2.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            2.0,
+            0.0,
+            1215012.8828876738,
+            -4736313.051199853,
+            4081605.22126042,
+            1.0,
+        ])
+    }
+
+    #[test]
+    fn transform_points() {
+        let m = ecef_transform();
+        assert_eq!(
+            m.transform_point([1.0, 2.0, 3.0]),
+            [1215014.8828876738, -4736309.051199853, 4081611.22126042]
+        );
+        assert_eq!(m.transform_vector([1.0, 2.0, 3.0]), [2.0, 4.0, 6.0]);
+        assert_eq!(m.max_scale(), 2.0);
+        assert_eq!(Matrix4::default(), Matrix4::IDENTITY);
+    }
+
+    #[test]
+    fn multiply() {
+        let translation = Matrix4::from_translation([1.0, 2.0, 3.0]);
+        let m = ecef_transform();
+        // The translation is applied first and scaled
+        let product = m * translation;
+        assert_eq!(
+            product.transform_point([0.0; 3]),
+            m.transform_point([1.0, 2.0, 3.0])
+        );
+        assert_eq!(
+            (translation * m).translation(),
+            [1215013.8828876738, -4736311.051199853, 4081608.22126042]
+        );
+        assert_eq!(m * Matrix4::IDENTITY, m);
+        assert_eq!(Matrix4::IDENTITY * m, m);
+    }
+
+    #[test]
+    fn relative_f32() {
+        let m = ecef_transform();
+        let origin = [1215000.0, -4736300.0, 4081600.0];
+        let elements = m.to_f32_relative_to(origin);
+        assert_eq!(
+            elements[..12],
+            [2.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0]
+        );
+        assert_eq!(elements[15], 1.0);
+        let translation = [elements[12], elements[13], elements[14]];
+        for (actual, expected) in
+            translation
+                .iter()
+                .zip(&[12.8828876738, -13.051199853, 5.22126042])
+        {
+            assert!((f64::from(*actual) - expected).abs() < 1e-5);
+        }
+        // Without origin, f32 loses the fractional part of ECEF coordinates
+        let absolute = m.to_f32_relative_to([0.0; 3]);
+        assert!((f64::from(absolute[13]) - m.0[13]).abs() > 0.01);
+    }
+
+    #[test]
+    fn deserialize() {
+        let m: Matrix4 = serde_json::from_str("[1,0,0,0,0,1,0,0,0,0,1,0,1,2,3,1]").unwrap();
+        assert_eq!(m, Matrix4::from_translation([1.0, 2.0, 3.0]));
+        assert_eq!(
+            serde_json::to_string(&m).unwrap(),
+            "[1.0,0.0,0.0,0.0,0.0,1.0,0.0,0.0,0.0,0.0,1.0,0.0,1.0,2.0,3.0,1.0]"
+        );
+        for invalid in &[
+            "[1,0,0,0,0,1,0,0,0,0,1,0,1,2,3]",
+            "[1,0,0,1,0,1,0,0,0,0,1,0,1,2,3,1]",
+            "[1,0,0,0,0,1,0,0,0,0,1,0,1,2,3,2]",
+            "[1,0,0,0,0,1,0,0,0,0,1,0,1,2,3,1,0]",
+        ] {
+            let error = serde_json::from_str::<Matrix4>(invalid).unwrap_err();
+            assert!(
+                error.to_string().starts_with("invalid transform matrix"),
+                "{}",
+                error
+            );
+        }
+    }
+}
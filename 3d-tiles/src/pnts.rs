@@ -1,27 +1,37 @@
 use crate::batch_table::BatchTable;
-use crate::error::Error;
+use crate::binary::{
+    body_slice, check_alignment, check_header, collect, json_section, layout, read_section,
+};
+use crate::encoding::{oct16p_decode, rgb565_decode};
+use crate::error::{Error, Section};
 use crate::feature_table::{
-    BinaryBodyReference, GlobalPropertyCartesian3, GlobalPropertyCartesian4, Property,
-    PurpleGlobalPropertyScalar,
+    dequantize, BinaryBodyReference, ComponentType, GlobalPropertyCartesian3,
+    GlobalPropertyCartesian4, Property, PurpleGlobalPropertyScalar,
 };
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::limits::Limits;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Read, Write};
+use std::mem;
+use std::slice::ChunksExact;
+
+/// Name of the Draco point compression extension in `PntsTable.extensions`.
+const DRACO_EXTENSION_NAME: &str = "3DTILES_draco_point_compression";
 
 /// Point Cloud tile.
 ///
 /// <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Pnts {
     pub header: PntsHeader,
     pub feature_table: FeatureTable,
-    // pub batch_table: BatchTable,
+    pub batch_table: BatchTable,
 }
 
 /// The header section of a .pnts file.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[repr(C)]
 pub struct PntsHeader {
     /// Must be `b"pnts"`. This can be used to identify the content as a Point Cloud tile.
@@ -43,6 +53,8 @@ pub struct PntsHeader {
 impl PntsHeader {
     fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
         use self::Error::Io;
+        let buf = read_section(&mut reader, Section::Header, 0, mem::size_of::<Self>())?;
+        let mut reader = &buf[..];
         let mut magic = [0; 4];
         reader.read_exact(&mut magic).map_err(Io)?;
         if &magic == b"pnts" {
@@ -59,56 +71,423 @@ impl PntsHeader {
             Err(Error::Magic(magic))
         }
     }
+
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        use self::Error::Io;
+        writer.write_all(&self.magic).map_err(Io)?;
+        writer.write_u32::<LittleEndian>(self.version).map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.feature_table_json_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.feature_table_binary_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.batch_table_json_byte_length)
+            .map_err(Io)?;
+        writer
+            .write_u32::<LittleEndian>(self.batch_table_binary_byte_length)
+            .map_err(Io)?;
+        Ok(())
+    }
+
+    /// Lengths of the tables following the header.
+    fn table_lengths(&self) -> [(Section, u32); 4] {
+        [
+            (
+                Section::FeatureTableJson,
+                self.feature_table_json_byte_length,
+            ),
+            (
+                Section::FeatureTableBinary,
+                self.feature_table_binary_byte_length,
+            ),
+            (Section::BatchTableJson, self.batch_table_json_byte_length),
+            (
+                Section::BatchTableBinary,
+                self.batch_table_binary_byte_length,
+            ),
+        ]
+    }
+
+    /// Byte offset of the Batch Table in the tile.
+    fn batch_table_offset(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.feature_table_json_byte_length as usize
+            + self.feature_table_binary_byte_length as usize
+    }
 }
 
 /// A Feature Table is a component of a tile's binary body and describes position and appearance properties required to render each feature in a tile.
 // <https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/FeatureTable/README.md>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FeatureTable {
     /// JSON header
     pub header: PntsTable,
-    // Binary body
-    // pub body: Vec<u8>,
+    /// JSON header as read, written instead of `header` while it has the same content.
+    pub raw_header: Option<Vec<u8>>,
+    /// Binary body
+    pub body: Vec<u8>,
 }
 
 impl FeatureTable {
-    fn from_reader<R: Read>(mut reader: R, json_byte_length: u32) -> Result<Self, Error> {
-        let mut buf = vec![0; json_byte_length as usize];
-        reader.read_exact(&mut buf).map_err(self::Error::Io)?;
+    fn from_reader<R: Read>(
+        mut reader: R,
+        json_byte_length: u32,
+        binary_byte_length: u32,
+    ) -> Result<Self, Error> {
+        let offset = mem::size_of::<PntsHeader>();
+        let json_byte_length = json_byte_length as usize;
+        let buf = read_section(
+            &mut reader,
+            Section::FeatureTableJson,
+            offset,
+            json_byte_length,
+        )?;
         // dbg!(&std::str::from_utf8(&buf));
         let header: PntsTable = serde_json::from_slice(&buf).map_err(Error::Json)?;
-        Ok(FeatureTable { header })
+        let body = read_section(
+            &mut reader,
+            Section::FeatureTableBinary,
+            offset + json_byte_length,
+            binary_byte_length as usize,
+        )?;
+        Ok(FeatureTable {
+            header,
+            raw_header: Some(buf),
+            body,
+        })
+    }
+
+    /// Serialize the JSON header and the binary body.
+    fn to_bytes(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let json = json_section(&self.header, self.raw_header.as_deref())?;
+        Ok((json, self.body.clone()))
+    }
+
+    /// Number of features in the Batch Table.
+    ///
+    /// This is `BATCH_LENGTH` if points are grouped by `BATCH_ID` and `POINTS_LENGTH` otherwise.
+    pub fn batch_length(&self) -> Result<usize, Error> {
+        match (&self.header.batch_id, &self.header.batch_length) {
+            (Some(_), Some(batch_length)) => batch_length.length("BATCH_LENGTH", &self.body),
+            (Some(_), None) => Err(Error::format(
+                Section::FeatureTableJson,
+                "BATCH_LENGTH required for BATCH_ID",
+            )),
+            (None, _) => Ok(self.header.points_length as usize),
+        }
+    }
+
+    /// Check that all semantics fit into the binary body and that batch IDs are smaller than
+    /// `BATCH_LENGTH`.
+    fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        collect(&mut errors, self.positions_f64(true));
+        collect(&mut errors, self.rgba());
+        collect(&mut errors, self.rgb());
+        collect(&mut errors, self.rgb565());
+        collect(&mut errors, self.normals());
+        let batch_length = collect(&mut errors, self.batch_length());
+        let batch_ids = collect(&mut errors, self.batch_id()).flatten();
+        if let (Some(batch_length), Some(batch_ids)) = (batch_length, batch_ids) {
+            let max = match batch_ids {
+                PointValues::BatchIdU8(ids) => ids.into_iter().map(u64::from).max(),
+                PointValues::BatchId(ids) => ids.into_iter().map(u64::from).max(),
+                PointValues::BatchIdU32(ids) => ids.into_iter().map(u64::from).max(),
+                _ => None,
+            };
+            if let Some(max) = max.filter(|max| *max >= batch_length as u64) {
+                errors.push(Error::format(
+                    Section::FeatureTableBinary,
+                    format!("BATCH_ID {} exceeds BATCH_LENGTH {}", max, batch_length),
+                ));
+            }
+        }
+        errors
+    }
+
+    /// Per-point elements of the semantic `name` with `size` bytes in the binary body.
+    fn point_data(
+        &self,
+        name: &str,
+        reference: &BinaryBodyReference,
+        size: usize,
+    ) -> Result<ChunksExact<'_, u8>, Error> {
+        let points_length = self.header.points_length as usize;
+        let data = body_slice(
+            &self.body,
+            Section::FeatureTableBinary,
+            name,
+            reference.byte_offset,
+            points_length,
+            size,
+        )?;
+        Ok(data.chunks_exact(size))
+    }
+
+    /// Point positions (`POSITION`).
+    pub fn position(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.position {
+            let values = self
+                .point_data("POSITION", reference, 12)?
+                .map(|c| {
+                    [
+                        LittleEndian::read_f32(&c[0..4]),
+                        LittleEndian::read_f32(&c[4..8]),
+                        LittleEndian::read_f32(&c[8..12]),
+                    ]
+                })
+                .collect();
+            Ok(Some(PointValues::Position(values)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Quantized point positions (`POSITION_QUANTIZED`).
+    pub fn position_quantized(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.position_quantized {
+            let values = self
+                .point_data("POSITION_QUANTIZED", reference, 6)?
+                .map(|c| {
+                    [
+                        LittleEndian::read_u16(&c[0..2]),
+                        LittleEndian::read_u16(&c[2..4]),
+                        LittleEndian::read_u16(&c[4..6]),
+                    ]
+                })
+                .collect();
+            Ok(Some(PointValues::PositionQuantized(values)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Point colors (`RGBA`).
+    pub fn rgba(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.rgba {
+            let values = self
+                .point_data("RGBA", reference, 4)?
+                .map(|c| [c[0], c[1], c[2], c[3]])
+                .collect();
+            Ok(Some(PointValues::Rgba(values)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Point colors (`RGB`).
+    pub fn rgb(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.rgb {
+            let values = self
+                .point_data("RGB", reference, 3)?
+                .map(|c| [c[0], c[1], c[2]])
+                .collect();
+            Ok(Some(PointValues::Rgb(values)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Compressed point colors (`RGB565`).
+    pub fn rgb565(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.rgb565 {
+            let values = self
+                .point_data("RGB565", reference, 2)?
+                .map(LittleEndian::read_u16)
+                .collect();
+            Ok(Some(PointValues::Rgb565(values)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Point normals (`NORMAL`).
+    pub fn normal(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.normal {
+            let values = self
+                .point_data("NORMAL", reference, 12)?
+                .map(|c| {
+                    [
+                        LittleEndian::read_f32(&c[0..4]),
+                        LittleEndian::read_f32(&c[4..8]),
+                        LittleEndian::read_f32(&c[8..12]),
+                    ]
+                })
+                .collect();
+            Ok(Some(PointValues::Normal(values)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Oct-encoded point normals (`NORMAL_OCT16P`).
+    pub fn normal_oct16p(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.normal_oct16_p {
+            let values = self
+                .point_data("NORMAL_OCT16P", reference, 2)?
+                .map(|c| [c[0], c[1]])
+                .collect();
+            Ok(Some(PointValues::NormalOct16p(values)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Point positions as `f64`, dequantized if the tile uses `POSITION_QUANTIZED`.
+    ///
+    /// With `add_rtc_center`, `RTC_CENTER` is added to the positions.
+    pub fn positions_f64(&self, add_rtc_center: bool) -> Result<Vec<[f64; 3]>, Error> {
+        let mut positions: Vec<[f64; 3]> = if let Some(PointValues::Position(positions)) =
+            self.position()?
+        {
+            positions
+                .iter()
+                .map(|p| [f64::from(p[0]), f64::from(p[1]), f64::from(p[2])])
+                .collect()
+        } else if let Some(PointValues::PositionQuantized(positions)) = self.position_quantized()? {
+            let missing = |name| {
+                Error::format(
+                    Section::FeatureTableJson,
+                    format!("{} required for POSITION_QUANTIZED", name),
+                )
+            };
+            let offset = self
+                .header
+                .quantized_volume_offset
+                .as_ref()
+                .ok_or_else(|| missing("QUANTIZED_VOLUME_OFFSET"))?
+                .value("QUANTIZED_VOLUME_OFFSET", &self.body)?;
+            let scale = self
+                .header
+                .quantized_volume_scale
+                .as_ref()
+                .ok_or_else(|| missing("QUANTIZED_VOLUME_SCALE"))?
+                .value("QUANTIZED_VOLUME_SCALE", &self.body)?;
+            positions
+                .iter()
+                .map(|q| dequantize(*q, offset, scale))
+                .collect()
+        } else if self.header.extensions.as_ref().map_or(false, |extensions| {
+            extensions.contains_key(DRACO_EXTENSION_NAME)
+        }) {
+            // Positions are compressed with Draco
+            return Err(Error::UnsupportedExtension {
+                section: Section::FeatureTableJson,
+                offset: mem::size_of::<PntsHeader>() as u64,
+                name: DRACO_EXTENSION_NAME.to_string(),
+            });
+        } else {
+            return Err(Error::format(
+                Section::FeatureTableJson,
+                "POSITION or POSITION_QUANTIZED required",
+            ));
+        };
+        if add_rtc_center {
+            if let Some(ref rtc_center) = self.header.rtc_center {
+                let center = rtc_center.value("RTC_CENTER", &self.body)?;
+                for p in positions.iter_mut() {
+                    p[0] += center[0];
+                    p[1] += center[1];
+                    p[2] += center[2];
+                }
+            }
+        }
+        Ok(positions)
+    }
+
+    /// Point normals, decoded from `NORMAL` or `NORMAL_OCT16P`.
+    pub fn normals(&self) -> Result<Option<Vec<[f32; 3]>>, Error> {
+        match (self.normal()?, self.normal_oct16p()?) {
+            (Some(PointValues::Normal(normals)), _) => Ok(Some(normals)),
+            (_, Some(PointValues::NormalOct16p(normals))) => {
+                Ok(Some(normals.into_iter().map(oct16p_decode).collect()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Point colors, decoded from `RGBA`, `RGB` or `RGB565`.
+    ///
+    /// The alpha component of `RGBA` is dropped, use [`FeatureTable::rgba`] for translucent
+    /// points.
+    pub fn colors(&self) -> Result<Option<Vec<[u8; 3]>>, Error> {
+        if let Some(PointValues::Rgba(colors)) = self.rgba()? {
+            Ok(Some(colors.iter().map(|c| [c[0], c[1], c[2]]).collect()))
+        } else if let Some(PointValues::Rgb(colors)) = self.rgb()? {
+            Ok(Some(colors))
+        } else if let Some(PointValues::Rgb565(colors)) = self.rgb565()? {
+            Ok(Some(colors.into_iter().map(rgb565_decode).collect()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Batch IDs of the points (`BATCH_ID`).
+    pub fn batch_id(&self) -> Result<Option<PointValues>, Error> {
+        if let Some(ref reference) = self.header.batch_id {
+            let values = match reference.component_type {
+                Some(ComponentType::UnsignedByte) => PointValues::BatchIdU8(
+                    self.point_data("BATCH_ID", reference, 1)?
+                        .map(|c| c[0])
+                        .collect(),
+                ),
+                None | Some(ComponentType::UnsignedShort) => PointValues::BatchId(
+                    self.point_data("BATCH_ID", reference, 2)?
+                        .map(LittleEndian::read_u16)
+                        .collect(),
+                ),
+                Some(ComponentType::UnsignedInt) => PointValues::BatchIdU32(
+                    self.point_data("BATCH_ID", reference, 4)?
+                        .map(LittleEndian::read_u32)
+                        .collect(),
+                ),
+                Some(_) => {
+                    return Err(Error::format(
+                        Section::FeatureTableJson,
+                        "invalid BATCH_ID componentType",
+                    ))
+                }
+            };
+            Ok(Some(values))
+        } else {
+            Ok(None)
+        }
     }
 }
 
 /// A set of Point Cloud semantics that contains values defining the position and appearance
 /// properties for points in a tile.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PntsTable {
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "BATCH_ID")]
+    #[serde(rename = "BATCH_ID", skip_serializing_if = "Option::is_none")]
     pub batch_id: Option<BinaryBodyReference>,
     /// A `GlobalPropertyScalar` object defining a numeric property for all points. See the
     /// corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "BATCH_LENGTH")]
+    #[serde(rename = "BATCH_LENGTH", skip_serializing_if = "Option::is_none")]
     pub batch_length: Option<PurpleGlobalPropertyScalar>,
     /// A `GlobalPropertyCartesian4` object defining a 4-component numeric property for all
     /// points. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "CONSTANT_RGBA")]
+    #[serde(rename = "CONSTANT_RGBA", skip_serializing_if = "Option::is_none")]
     pub constant_rgba: Option<GlobalPropertyCartesian4>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "NORMAL")]
+    #[serde(rename = "NORMAL", skip_serializing_if = "Option::is_none")]
     pub normal: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "NORMAL_OCT16P")]
+    #[serde(rename = "NORMAL_OCT16P", skip_serializing_if = "Option::is_none")]
     pub normal_oct16_p: Option<BinaryBodyReference>,
     /// A `GlobalPropertyScalar` object defining a numeric property for all points. See the
     /// corresponding property semantic in
@@ -118,52 +497,62 @@ pub struct PntsTable {
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "POSITION")]
+    #[serde(rename = "POSITION", skip_serializing_if = "Option::is_none")]
     pub position: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "POSITION_QUANTIZED")]
+    #[serde(rename = "POSITION_QUANTIZED", skip_serializing_if = "Option::is_none")]
     pub position_quantized: Option<BinaryBodyReference>,
     /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
     /// points. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "QUANTIZED_VOLUME_OFFSET")]
+    #[serde(
+        rename = "QUANTIZED_VOLUME_OFFSET",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub quantized_volume_offset: Option<GlobalPropertyCartesian3>,
     /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
     /// points. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "QUANTIZED_VOLUME_SCALE")]
+    #[serde(
+        rename = "QUANTIZED_VOLUME_SCALE",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub quantized_volume_scale: Option<GlobalPropertyCartesian3>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "RGB")]
+    #[serde(rename = "RGB", skip_serializing_if = "Option::is_none")]
     pub rgb: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "RGB565")]
+    #[serde(rename = "RGB565", skip_serializing_if = "Option::is_none")]
     pub rgb565: Option<BinaryBodyReference>,
     /// A `BinaryBodyReference` object defining the reference to a section of the binary body
     /// where the property values are stored. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "RGBA")]
+    #[serde(rename = "RGBA", skip_serializing_if = "Option::is_none")]
     pub rgba: Option<BinaryBodyReference>,
     /// A `GlobalPropertyCartesian3` object defining a 3-component numeric property for all
     /// points. See the corresponding property semantic in
     /// [Semantics](https://github.com/CesiumGS/3d-tiles/blob/1.0/specification/TileFormats/PointCloud/README.md#semantics).
-    #[serde(rename = "RTC_CENTER")]
+    #[serde(rename = "RTC_CENTER", skip_serializing_if = "Option::is_none")]
     pub rtc_center: Option<GlobalPropertyCartesian3>,
 
     #[serde(flatten)]
     pub properties: HashMap<String, Property>,
     /// Dictionary object with extension-specific objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
     /// Application-specific data.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
 }
 
+/// Per-point values of a Point Cloud semantic.
+#[derive(Debug, Clone)]
 pub enum PointValues {
     /// A 3-component array of numbers containing x, y, and z Cartesian coordinates for the position of the point.
     Position(Vec<[f32; 3]>),
@@ -188,36 +577,336 @@ pub enum PointValues {
 }
 
 impl Pnts {
-    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, Error> {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Read tile, checking the header against `limits` before reading any section.
+    pub fn from_reader_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, Error> {
         let header = PntsHeader::from_reader(&mut reader)?;
         if header.version != 1 {
             return Err(Error::Version(header.version));
         }
-        let feature_table =
-            FeatureTable::from_reader(&mut reader, header.feature_table_json_byte_length)?;
-
+        check_header(
+            header.byte_length,
+            mem::size_of::<PntsHeader>(),
+            &header.table_lengths(),
+            limits,
+        )?;
+        let feature_table = FeatureTable::from_reader(
+            &mut reader,
+            header.feature_table_json_byte_length,
+            header.feature_table_binary_byte_length,
+        )?;
+        let batch_table = BatchTable::from_reader(
+            &mut reader,
+            header.batch_table_offset(),
+            header.batch_table_json_byte_length,
+            header.batch_table_binary_byte_length,
+            feature_table.batch_length()?,
+        )?;
         Ok(Pnts {
             header,
             feature_table,
+            batch_table,
         })
     }
+
+    /// Check the binary layout of the tile.
+    ///
+    /// `byteLength` must equal the length of the header and the tables, which must end on an
+    /// 8-byte boundary. Semantics and properties must fit into the binary bodies and the Batch
+    /// Table must have a value per feature for every property.
+    pub fn validate(&self) -> Vec<Error> {
+        let header_length = mem::size_of::<PntsHeader>();
+        let sections = self.header.table_lengths();
+        let length = header_length as u64
+            + sections
+                .iter()
+                .map(|(_, length)| u64::from(*length))
+                .sum::<u64>();
+        let mut errors = Vec::new();
+        if u64::from(self.header.byte_length) != length {
+            errors.push(Error::ByteLength {
+                section: Section::Header,
+                offset: 8,
+                declared: u64::from(self.header.byte_length),
+                actual: length,
+            });
+        }
+        errors.extend(check_alignment(header_length, &sections));
+        errors.extend(self.feature_table.validate());
+        errors.extend(self.batch_table.validate());
+        errors
+    }
+
+    /// Write tile.
+    ///
+    /// The header lengths are computed from the tables. Each section is padded to an 8-byte
+    /// boundary, unless all sections are unchanged since reading the tile, which is then
+    /// written exactly as read.
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let header_length = mem::size_of::<PntsHeader>();
+        let (feature_table_json, feature_table_binary) = self.feature_table.to_bytes()?;
+        let (batch_table_json, batch_table_binary) = self.batch_table.to_bytes()?;
+        let declared: Vec<_> = self
+            .header
+            .table_lengths()
+            .iter()
+            .map(|(_, length)| Some(*length as usize))
+            .collect();
+        let sections = layout(
+            header_length,
+            vec![
+                (feature_table_json, b' '),
+                (feature_table_binary, 0),
+                (batch_table_json, b' '),
+                (batch_table_binary, 0),
+            ],
+            &declared,
+        );
+        let header = PntsHeader {
+            magic: *b"pnts",
+            version: 1,
+            byte_length: (header_length + sections.iter().map(Vec::len).sum::<usize>()) as u32,
+            feature_table_json_byte_length: sections[0].len() as u32,
+            feature_table_binary_byte_length: sections[1].len() as u32,
+            batch_table_json_byte_length: sections[2].len() as u32,
+            batch_table_binary_byte_length: sections[3].len() as u32,
+        };
+        header.to_writer(&mut writer)?;
+        for section in &sections {
+            writer.write_all(section).map_err(Error::Io)?;
+        }
+        Ok(())
+    }
 }
 
 /// Read pnts file
 pub fn extract(path: &str) -> Result<Pnts, Error> {
-    use self::Error::Io;
-    let file = File::open(path).map_err(Io)?;
+    let file = File::open(path).map_err(|e| Error::Io(e).with_path(path))?;
     let mut reader = BufReader::new(file);
-    let pnts = Pnts::from_reader(&mut reader)?;
+    Pnts::from_reader(&mut reader).map_err(|e| e.with_path(path))
+}
 
-    let mut body = vec![0; pnts.header.feature_table_binary_byte_length as usize];
-    reader.read_exact(&mut body).map_err(Io)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample;
+    use crate::test_util::feature_table;
 
-    let _batch_table = BatchTable::from_reader(
-        &mut reader,
-        pnts.header.batch_table_json_byte_length,
-        pnts.header.batch_table_binary_byte_length,
-    )?;
+    const SAMPLES: &[&str] = &[
+        "TilesetWithExpiration/points.pnts",
+        "TilesetWithExpiration/cache/points_0.pnts",
+        "TilesetWithExpiration/cache/points_1.pnts",
+        "TilesetWithExpiration/cache/points_2.pnts",
+        "TilesetWithExpiration/cache/points_3.pnts",
+        "TilesetWithExpiration/cache/points_4.pnts",
+        "TilesetWithRequestVolume/points.pnts",
+    ];
+
+    #[test]
+    fn round_trip_samples() {
+        for name in SAMPLES {
+            let data = sample(name);
+            let pnts = Pnts::from_reader(&data[..]).unwrap();
+            let mut written = Vec::new();
+            pnts.to_writer(&mut written).unwrap();
+            assert!(written == data, "{} differs after writing", name);
+        }
+    }
 
-    Ok(pnts)
+    #[test]
+    fn write_edited_feature_table() {
+        let data = sample("TilesetWithExpiration/cache/points_0.pnts");
+        let mut pnts = Pnts::from_reader(&data[..]).unwrap();
+        pnts.feature_table.header.extras = Some(serde_json::json!({"edited": true}));
+        let mut written = Vec::new();
+        pnts.to_writer(&mut written).unwrap();
+
+        let read = Pnts::from_reader(&written[..]).unwrap();
+        assert!(read.validate().is_empty());
+        assert_eq!(written.len() % 8, 0);
+        assert_eq!(read.feature_table.header.points_length, 8000);
+        assert_eq!(read.feature_table.body, pnts.feature_table.body);
+        let json = read.feature_table.raw_header.unwrap();
+        assert!(std::str::from_utf8(&json)
+            .unwrap()
+            .contains(r#""POINTS_LENGTH":8000"#));
+    }
+
+    #[test]
+    fn sample_point_values() {
+        let data = sample("TilesetWithExpiration/cache/points_0.pnts");
+        let pnts = Pnts::from_reader(&data[..]).unwrap();
+        let feature_table = &pnts.feature_table;
+        match feature_table.position().unwrap() {
+            Some(PointValues::Position(positions)) => assert_eq!(positions.len(), 8000),
+            values => panic!("unexpected positions {:?}", values),
+        }
+        match feature_table.rgb().unwrap() {
+            Some(PointValues::Rgb(colors)) => assert_eq!(colors.len(), 8000),
+            values => panic!("unexpected colors {:?}", values),
+        }
+        assert!(feature_table.rgba().unwrap().is_none());
+        assert!(feature_table.normal().unwrap().is_none());
+        assert!(feature_table.batch_id().unwrap().is_none());
+        assert_eq!(feature_table.batch_length().unwrap(), 8000);
+    }
+
+    #[test]
+    fn typed_point_values() {
+        let header = serde_json::json!({
+            "POINTS_LENGTH": 2,
+            "POSITION": {"byteOffset": 0},
+            "RGB565": {"byteOffset": 24},
+            "NORMAL_OCT16P": {"byteOffset": 28},
+            "BATCH_ID": {"byteOffset": 32, "componentType": "UNSIGNED_BYTE"},
+            "BATCH_LENGTH": 2,
+        });
+        let mut body = vec![0; 24];
+        body.extend_from_slice(&[0x1f, 0x00, 0x00, 0xf8, 1, 2, 3, 4, 1, 0]);
+        let feature_table = feature_table::<FeatureTable>(header, body);
+        assert!(matches!(
+            feature_table.rgb565().unwrap(),
+            Some(PointValues::Rgb565(ref colors)) if colors == &[0x001f, 0xf800]
+        ));
+        assert!(matches!(
+            feature_table.normal_oct16p().unwrap(),
+            Some(PointValues::NormalOct16p(ref normals)) if normals == &[[1, 2], [3, 4]]
+        ));
+        assert!(matches!(
+            feature_table.batch_id().unwrap(),
+            Some(PointValues::BatchIdU8(ref ids)) if ids == &[1, 0]
+        ));
+        assert_eq!(feature_table.batch_length().unwrap(), 2);
+        assert!(feature_table.validate().is_empty());
+    }
+
+    #[test]
+    fn reference_exceeding_body() {
+        let header = serde_json::json!({"POINTS_LENGTH": 2, "RGB": {"byteOffset": 2}});
+        let feature_table = feature_table::<FeatureTable>(header, vec![0; 6]);
+        assert!(matches!(
+            feature_table.rgb(),
+            Err(Error::PropertyReference {
+                offset: 2,
+                length: 6,
+                available: 6,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn batch_id_exceeding_batch_length() {
+        let header = serde_json::json!({
+            "POINTS_LENGTH": 2,
+            "POSITION": {"byteOffset": 0},
+            "BATCH_ID": {"byteOffset": 24},
+            "BATCH_LENGTH": 1,
+        });
+        let mut body = vec![0; 24];
+        body.extend_from_slice(&[0, 0, 1, 0]);
+        let errors = feature_table::<FeatureTable>(header, body).validate();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            Error::Format {
+                section: Section::FeatureTableBinary,
+                ..
+            }
+        ));
+        let header = serde_json::json!({"POINTS_LENGTH": 2, "BATCH_ID": {"byteOffset": 0}});
+        assert!(feature_table::<FeatureTable>(header, vec![0; 4])
+            .batch_length()
+            .is_err());
+    }
+
+    #[test]
+    fn dequantized_positions() {
+        let header = serde_json::json!({
+            "POINTS_LENGTH": 2,
+            "POSITION_QUANTIZED": {"byteOffset": 0},
+            "QUANTIZED_VOLUME_OFFSET": [-1, -2, -3],
+            "QUANTIZED_VOLUME_SCALE": [2, 4, 6],
+            "RTC_CENTER": [100, 200, 300],
+        });
+        let mut body = vec![0; 12];
+        LittleEndian::write_u16_into(&[0, 0, 0, 65535, 65535, 65535], &mut body);
+        let feature_table = feature_table::<FeatureTable>(header, body);
+        assert_eq!(
+            feature_table.positions_f64(false).unwrap(),
+            vec![[-1.0, -2.0, -3.0], [1.0, 2.0, 3.0]]
+        );
+        assert_eq!(
+            feature_table.positions_f64(true).unwrap(),
+            vec![[99.0, 198.0, 297.0], [101.0, 202.0, 303.0]]
+        );
+    }
+
+    #[test]
+    fn quantized_positions_without_volume() {
+        let header = serde_json::json!({
+            "POINTS_LENGTH": 1,
+            "POSITION_QUANTIZED": {"byteOffset": 0},
+            "QUANTIZED_VOLUME_SCALE": [2, 4, 6],
+        });
+        match feature_table::<FeatureTable>(header, vec![0; 6]).positions_f64(false) {
+            Err(Error::Format { message, .. }) => {
+                assert!(message.contains("QUANTIZED_VOLUME_OFFSET"))
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn decoded_normals_and_colors() {
+        let header = serde_json::json!({
+            "POINTS_LENGTH": 2,
+            "RGB565": {"byteOffset": 0},
+            "NORMAL_OCT16P": {"byteOffset": 4},
+        });
+        let body = vec![0x00, 0xf8, 0xe0, 0x07, 128, 128, 0, 0];
+        let feature_table = feature_table::<FeatureTable>(header, body);
+        assert_eq!(
+            feature_table.colors().unwrap(),
+            Some(vec![[255, 0, 0], [0, 255, 0]])
+        );
+        let normals = feature_table.normals().unwrap().unwrap();
+        assert!(normals[0][2] > 0.99);
+        assert_eq!(normals[1], [0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn colors_prefer_rgba() {
+        let header = serde_json::json!({
+            "POINTS_LENGTH": 1,
+            "RGBA": {"byteOffset": 0},
+            "RGB": {"byteOffset": 4},
+        });
+        let feature_table = feature_table::<FeatureTable>(header, vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(feature_table.colors().unwrap(), Some(vec![[1, 2, 3]]));
+        assert!(feature_table.normals().unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_batch_length() {
+        let feature_table = feature_table::<FeatureTable>(
+            serde_json::json!({
+                "POINTS_LENGTH": 2,
+                "POSITION": {"byteOffset": 0},
+                "BATCH_ID": {"byteOffset": 24, "componentType": "UNSIGNED_BYTE"},
+                "BATCH_LENGTH": 1.5,
+            }),
+            vec![0; 26],
+        );
+        assert!(matches!(
+            feature_table.batch_length(),
+            Err(Error::Format {
+                section: Section::FeatureTableJson,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,426 @@
+//! Screen space error based tile selection, independent of any renderer.
+//!
+//! <https://github.com/CesiumGS/3d-tiles/tree/1.0/specification#geometric-error>
+
+use crate::bounding_volume::Volume;
+use crate::loader::is_tileset_uri;
+use crate::math::{dot, length, normalize, sub};
+use crate::tileset::{Refine, Tile, TileNode};
+
+/// Perspective camera in the coordinate system of the tileset.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub position: [f64; 3],
+    /// View direction, not necessarily normalized.
+    pub direction: [f64; 3],
+    /// Vertical field of view in radians.
+    pub fov: f64,
+    /// Viewport width in pixels.
+    pub viewport_width: f64,
+    /// Viewport height in pixels.
+    pub viewport_height: f64,
+}
+
+impl Camera {
+    /// Screen space error in pixels of a geometric error at the given distance.
+    pub fn screen_space_error(&self, geometric_error: f64, distance: f64) -> f64 {
+        let distance = distance.max(f64::EPSILON);
+        geometric_error * self.viewport_height / (2.0 * distance * (self.fov / 2.0).tan())
+    }
+
+    /// Whether the volume intersects the view cone enclosing the viewport.
+    pub fn is_visible(&self, volume: &Volume) -> bool {
+        let sphere = volume.to_sphere();
+        let offset = sub(sphere.center, self.position);
+        let distance = length(offset);
+        if distance <= sphere.radius {
+            return true;
+        }
+        let aspect_ratio = self.viewport_width / self.viewport_height;
+        let half_angle =
+            ((self.fov / 2.0).tan() * (1.0 + aspect_ratio * aspect_ratio).sqrt()).atan();
+        let axial = dot(offset, normalize(self.direction));
+        let radial = (distance * distance - axial * axial).max(0.0).sqrt();
+        radial * half_angle.cos() - axial * half_angle.sin() <= sphere.radius
+    }
+}
+
+/// Result of a tile selection.
+#[derive(Debug, Clone, Default)]
+pub struct Selection<'a> {
+    /// Tiles with loaded content to render.
+    pub render: Vec<TileNode<'a>>,
+    /// Tiles with content to load, including unloaded external tilesets.
+    pub request: Vec<TileNode<'a>>,
+}
+
+/// Select the tiles to render and to request for the camera.
+///
+/// Tiles are refined while their screen space error exceeds `maximum_screen_space_error`.
+/// Tiles outside the view or with a `viewerRequestVolume` not containing the camera are
+/// skipped. With replacement refinement, a loaded tile is rendered instead of its
+/// descendants until their content is loaded. `is_loaded` tells whether the contents of a
+/// tile, including the entries of `contents`, are available.
+pub fn select_tiles<'a, F>(
+    root: &'a Tile,
+    camera: &Camera,
+    maximum_screen_space_error: f64,
+    is_loaded: F,
+) -> Selection<'a>
+where
+    F: Fn(&TileNode<'a>) -> bool,
+{
+    let selector = Selector {
+        camera,
+        maximum_screen_space_error,
+        is_loaded,
+    };
+    let mut selection = Selection::default();
+    selector.select(TileNode::root(root), &mut selection);
+    selection
+}
+
+struct Selector<'c, F> {
+    camera: &'c Camera,
+    maximum_screen_space_error: f64,
+    is_loaded: F,
+}
+
+impl<'c, 'a, F> Selector<'c, F>
+where
+    F: Fn(&TileNode<'a>) -> bool,
+{
+    fn select(&self, node: TileNode<'a>, selection: &mut Selection<'a>) {
+        let tile = node.tile;
+        let volume = tile.bounding_volume.volume.transform(&node.transform);
+        if !self.camera.is_visible(&volume) {
+            return;
+        }
+        if let Some(ref request_volume) = tile.viewer_request_volume {
+            let request_volume = request_volume.volume.transform(&node.transform);
+            if !request_volume.contains(self.camera.position) {
+                return;
+            }
+        }
+
+        let has_children = tile.children.as_ref().map_or(false, |c| !c.is_empty());
+        let sse = self
+            .camera
+            .screen_space_error(tile.geometric_error, volume.distance(self.camera.position));
+        if !has_children || sse <= self.maximum_screen_space_error {
+            self.add_content(node, selection);
+            return;
+        }
+
+        match node.refine {
+            Some(Refine::Add) => {
+                let children: Vec<_> = node.children().collect();
+                self.add_content(node, selection);
+                for child in children {
+                    self.select(child, selection);
+                }
+            }
+            _ => {
+                let mut descendants = Selection::default();
+                for child in node.children() {
+                    self.select(child, &mut descendants);
+                }
+                if !descendants.request.is_empty()
+                    && self.has_visible_content(&node)
+                    && (self.is_loaded)(&node)
+                {
+                    // Keep the tile until the content replacing it is loaded
+                    selection.render.push(node);
+                } else {
+                    selection.render.append(&mut descendants.render);
+                }
+                selection.request.append(&mut descendants.request);
+            }
+        }
+    }
+
+    /// Whether the tile has renderable content within the view.
+    ///
+    /// Contents are told apart from external tilesets by the heuristic of
+    /// [`is_tileset_uri`], since their data isn't available here.
+    fn has_visible_content(&self, node: &TileNode<'a>) -> bool {
+        node.tile
+            .all_contents()
+            .filter(|content| !is_tileset_uri(&content.uri))
+            .any(|content| match content.bounding_volume {
+                Some(ref volume) => self
+                    .camera
+                    .is_visible(&volume.volume.transform(&node.transform)),
+                None => true,
+            })
+    }
+
+    fn add_content(&self, node: TileNode<'a>, selection: &mut Selection<'a>) {
+        let external_tileset = node
+            .tile
+            .all_contents()
+            .any(|content| is_tileset_uri(&content.uri));
+        // Grafted external tilesets are traversed as children
+        if external_tileset && node.tile.children.as_ref().map_or(true, |c| c.is_empty()) {
+            selection.request.push(node);
+        } else if self.has_visible_content(&node) {
+            if (self.is_loaded)(&node) {
+                selection.render.push(node);
+            } else {
+                selection.request.push(node);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{FileSource, TilesetLoader};
+    use crate::math::{add, scale};
+    use crate::sample_path;
+    use crate::tileset::{TileContent, Tileset};
+
+    fn load(name: &str) -> Tileset {
+        TilesetLoader::new(FileSource)
+            .load_eager(&sample_path(name))
+            .unwrap()
+            .tileset
+    }
+
+    /// Camera at `height` above the origin of `tile` along its local z axis, looking down.
+    fn camera_above(tile: &Tile, height: f64) -> Camera {
+        let transform = tile.local_transform();
+        let up = normalize(transform.transform_vector([0.0, 0.0, 1.0]));
+        Camera {
+            position: add(transform.translation(), scale(up, height)),
+            direction: scale(up, -1.0),
+            fov: 60f64.to_radians(),
+            viewport_width: 1000.0,
+            viewport_height: 1000.0,
+        }
+    }
+
+    /// File names of the contents of the tiles.
+    fn names(nodes: &[TileNode]) -> Vec<String> {
+        let mut names: Vec<_> = nodes
+            .iter()
+            .flat_map(|node| node.tile.all_contents())
+            .map(|content| content.uri.rsplit('/').next().unwrap().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn loaded(names: &'static [&'static str]) -> impl Fn(&TileNode) -> bool {
+        move |node| {
+            node.tile
+                .all_contents()
+                .all(|content| names.iter().any(|name| content.uri.ends_with(name)))
+        }
+    }
+
+    #[test]
+    fn screen_space_error() {
+        let camera = Camera {
+            position: [0.0; 3],
+            direction: [0.0, 0.0, -1.0],
+            fov: 90f64.to_radians(),
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+        };
+        assert!((camera.screen_space_error(1.0, 100.0) - 3.0).abs() < 1e-9);
+        assert!(camera.screen_space_error(1.0, 0.0).is_finite());
+    }
+
+    #[test]
+    fn visibility() {
+        let camera = Camera {
+            position: [0.0; 3],
+            direction: [0.0, 0.0, -1.0],
+            fov: 90f64.to_radians(),
+            viewport_width: 100.0,
+            viewport_height: 100.0,
+        };
+        let sphere =
+            |center, radius| Volume::Sphere(crate::bounding_volume::Sphere { center, radius });
+        assert!(camera.is_visible(&sphere([0.0, 0.0, -10.0], 1.0)));
+        assert!(camera.is_visible(&sphere([0.0, 0.0, 0.5], 1.0)));
+        assert!(!camera.is_visible(&sphere([0.0, 0.0, 10.0], 1.0)));
+        // The diagonal of the viewport is wider than 90 degrees
+        assert!(camera.is_visible(&sphere([9.0, 9.0, -10.0], 0.1)));
+        assert!(!camera.is_visible(&sphere([20.0, 0.0, -10.0], 1.0)));
+    }
+
+    #[test]
+    fn replace_refinement() {
+        let tileset = load("TilesetWithDiscreteLOD/tileset.json");
+        let root = &tileset.root;
+        // The dragon box has a half height of about 504 m
+        let far = camera_above(root, 2000.0);
+        let selection = select_tiles(root, &far, 16.0, |_| true);
+        assert_eq!(names(&selection.render), ["dragon_low.b3dm"]);
+        assert!(selection.request.is_empty());
+
+        let near = camera_above(root, 520.0);
+        let all = loaded(&["dragon_low.b3dm", "dragon_medium.b3dm"]);
+        let selection = select_tiles(root, &near, 16.0, all);
+        assert_eq!(names(&selection.render), ["dragon_medium.b3dm"]);
+        assert!(selection.request.is_empty());
+
+        // The loaded parent is rendered until the refined content is available
+        let selection = select_tiles(root, &near, 16.0, loaded(&["dragon_low.b3dm"]));
+        assert_eq!(names(&selection.render), ["dragon_low.b3dm"]);
+        assert_eq!(names(&selection.request), ["dragon_medium.b3dm"]);
+
+        let selection = select_tiles(root, &near, 0.0, |_| true);
+        assert_eq!(names(&selection.render), ["dragon_high.b3dm"]);
+    }
+
+    #[test]
+    fn add_refinement() {
+        let mut tileset = load("TilesetWithDiscreteLOD/tileset.json");
+        tileset.root.refine = Some(Refine::Add);
+        let near = camera_above(&tileset.root, 520.0);
+        let selection = select_tiles(&tileset.root, &near, 16.0, loaded(&["dragon_low.b3dm"]));
+        assert_eq!(names(&selection.render), ["dragon_low.b3dm"]);
+        assert_eq!(names(&selection.request), ["dragon_medium.b3dm"]);
+        let selection = select_tiles(&tileset.root, &near, 0.0, |_| true);
+        assert_eq!(
+            names(&selection.render),
+            ["dragon_high.b3dm", "dragon_low.b3dm", "dragon_medium.b3dm"]
+        );
+    }
+
+    #[test]
+    fn view_frustum_culling() {
+        let tileset = load("TilesetWithDiscreteLOD/tileset.json");
+        let mut camera = camera_above(&tileset.root, 2000.0);
+        camera.direction = scale(camera.direction, -1.0);
+        let selection = select_tiles(&tileset.root, &camera, 16.0, |_| true);
+        assert!(selection.render.is_empty());
+        assert!(selection.request.is_empty());
+    }
+
+    #[test]
+    fn content_bounding_volume_culling() {
+        let mut tileset = load("TilesetWithDiscreteLOD/tileset.json");
+        let near = camera_above(&tileset.root, 520.0);
+        // Move the content volume of the medium tile behind the camera
+        let medium = &mut tileset.root.children.as_mut().unwrap()[0];
+        let mut volume = medium.bounding_volume.clone();
+        if let Volume::Box(ref mut b) = volume.volume {
+            b.center = [0.0, 0.0, 100.0];
+        }
+        medium.content.as_mut().unwrap().bounding_volume = Some(volume);
+        let selection = select_tiles(&tileset.root, &near, 16.0, |_| true);
+        assert!(selection.render.is_empty());
+        assert!(selection.request.is_empty());
+    }
+
+    #[test]
+    fn viewer_request_volume() {
+        let tileset = load("TilesetWithRequestVolume/tileset.json");
+        let points = &tileset.root.children.as_ref().unwrap()[2];
+        let loaded = loaded(&["building.b3dm", "points.pnts"]);
+
+        // The points are only requested within 15 m of their origin
+        let inside = camera_above(points, 10.0);
+        let selection = select_tiles(&tileset.root, &inside, 16.0, &loaded);
+        assert!(names(&selection.render).contains(&"points.pnts".to_string()));
+        let outside = camera_above(points, 100.0);
+        let selection = select_tiles(&tileset.root, &outside, 16.0, &loaded);
+        assert!(!names(&selection.render).contains(&"points.pnts".to_string()));
+        assert!(!names(&selection.request).contains(&"points.pnts".to_string()));
+        assert!(names(&selection.render).contains(&"building.b3dm".to_string()));
+    }
+
+    #[test]
+    fn render_and_request() {
+        let tileset = load("TilesetWithRequestVolume/tileset.json");
+        let points = &tileset.root.children.as_ref().unwrap()[2];
+        let camera = camera_above(points, 10.0);
+        let selection = select_tiles(
+            &tileset.root,
+            &camera,
+            16.0,
+            loaded(&["ll.b3dm", "lr.b3dm"]),
+        );
+        assert_eq!(names(&selection.render), ["ll.b3dm", "lr.b3dm"]);
+        assert_eq!(
+            names(&selection.request),
+            ["building.b3dm", "points.pnts", "ul.b3dm", "ur.b3dm"]
+        );
+    }
+
+    #[test]
+    fn request_external_tileset() {
+        let uri = sample_path("TilesetWithRequestVolume/tileset.json");
+        let tileset = TilesetLoader::new(FileSource).load(&uri).unwrap().tileset;
+        let points = &tileset.root.children.as_ref().unwrap()[2];
+        let camera = camera_above(points, 10.0);
+        let selection = select_tiles(&tileset.root, &camera, 16.0, |_| true);
+        assert_eq!(names(&selection.render), ["building.b3dm", "points.pnts"]);
+        assert_eq!(names(&selection.request), ["tileset.json"]);
+    }
+
+    /// Content entry referencing `uri`.
+    fn content(uri: &str) -> TileContent {
+        serde_json::from_value(serde_json::json!({ "uri": uri })).unwrap()
+    }
+
+    #[test]
+    fn multiple_contents() {
+        let mut tileset = load("TilesetWithDiscreteLOD/tileset.json");
+        let low = tileset.root.content.take().unwrap();
+        tileset.root.contents = Some(vec![low, content("labels.b3dm")]);
+        let far = camera_above(&tileset.root, 2000.0);
+        let selection = select_tiles(&tileset.root, &far, 16.0, |_| true);
+        assert_eq!(names(&selection.render), ["dragon_low.b3dm", "labels.b3dm"]);
+
+        // The tile is requested until all contents are loaded
+        let selection = select_tiles(&tileset.root, &far, 16.0, loaded(&["dragon_low.b3dm"]));
+        assert!(selection.render.is_empty());
+        assert_eq!(
+            names(&selection.request),
+            ["dragon_low.b3dm", "labels.b3dm"]
+        );
+    }
+
+    #[test]
+    fn multiple_content_volumes() {
+        let mut tileset = load("TilesetWithDiscreteLOD/tileset.json");
+        let far = camera_above(&tileset.root, 2000.0);
+        let mut behind = tileset.root.bounding_volume.clone();
+        if let Volume::Box(ref mut b) = behind.volume {
+            b.center = [0.0, 0.0, 3000.0];
+        }
+        let mut hidden = content("hidden.b3dm");
+        hidden.bounding_volume = Some(behind.clone());
+        let low = tileset.root.content.take().unwrap();
+        tileset.root.contents = Some(vec![hidden, low]);
+        // The tile is rendered if any of its contents is visible
+        let selection = select_tiles(&tileset.root, &far, 16.0, |_| true);
+        assert_eq!(names(&selection.render), ["dragon_low.b3dm", "hidden.b3dm"]);
+
+        tileset.root.contents.as_mut().unwrap()[1].bounding_volume = Some(behind);
+        let selection = select_tiles(&tileset.root, &far, 16.0, |_| true);
+        assert!(selection.render.is_empty());
+        assert!(selection.request.is_empty());
+    }
+
+    #[test]
+    fn external_tileset_in_contents() {
+        let mut tileset = load("TilesetWithDiscreteLOD/tileset.json");
+        let low = tileset.root.content.take().unwrap();
+        tileset.root.contents = Some(vec![low, content("external.json")]);
+        tileset.root.children = None;
+        let far = camera_above(&tileset.root, 2000.0);
+        let selection = select_tiles(&tileset.root, &far, 16.0, |_| true);
+        assert!(selection.render.is_empty());
+        assert_eq!(
+            names(&selection.request),
+            ["dragon_low.b3dm", "external.json"]
+        );
+    }
+}
@@ -0,0 +1,832 @@
+//! Subtrees of implicit tilesets.
+//!
+//! A subtree describes the availability of tiles, contents and child subtrees for a fixed
+//! number of levels of an implicit tileset. It is stored in a binary `.subtree` file with a
+//! JSON and a binary chunk, or as JSON with external buffers. See
+//! <https://github.com/CesiumGS/3d-tiles/tree/main/specification/ImplicitTiling#subtrees>
+
+use crate::binary::{body_slice, pad, read_section};
+use crate::error::{Error, Section};
+use crate::limits::Limits;
+use crate::tileset::SubdivisionScheme;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Length of the binary subtree header.
+const HEADER_LENGTH: usize = 24;
+
+/// A subtree, in binary or JSON form.
+#[derive(Debug, Clone)]
+pub struct Subtree {
+    pub json: SubtreeJson,
+    /// Binary chunk, referenced by the buffer without `uri`. Empty for subtrees in JSON form.
+    pub binary: Vec<u8>,
+}
+
+/// The JSON of a subtree.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubtreeJson {
+    /// An array of buffers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buffers: Vec<Buffer>,
+    /// An array of buffer views.
+    #[serde(rename = "bufferViews", default, skip_serializing_if = "Vec::is_empty")]
+    pub buffer_views: Vec<BufferView>,
+    /// The availability of tiles in the subtree.
+    #[serde(rename = "tileAvailability")]
+    pub tile_availability: Availability,
+    /// An array of content availability objects, one per content of a tile.
+    #[serde(
+        rename = "contentAvailability",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub content_availability: Option<Vec<Availability>>,
+    /// The availability of children subtrees.
+    #[serde(rename = "childSubtreeAvailability")]
+    pub child_subtree_availability: Availability,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
+    /// Properties not modeled by this struct, e.g. metadata, written back unchanged.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+/// A buffer is a binary blob. It is either the binary chunk of the subtree file or an
+/// external buffer referenced by URI.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Buffer {
+    /// The length of the buffer in bytes.
+    #[serde(rename = "byteLength")]
+    pub byte_length: u64,
+    /// The URI of an external buffer. When the uri is relative, it is relative to the
+    /// subtree file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
+}
+
+/// A contiguous subset of a buffer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferView {
+    /// The index of the buffer.
+    pub buffer: usize,
+    /// The offset into the buffer in bytes.
+    #[serde(rename = "byteOffset")]
+    pub byte_offset: u64,
+    /// The total byte length of the buffer view.
+    #[serde(rename = "byteLength")]
+    pub byte_length: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
+}
+
+/// An object describing the availability of a set of elements, either constant or as a
+/// bitstream in a buffer view.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Availability {
+    /// An index to a buffer view containing the availability bitstream.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitstream: Option<usize>,
+    /// A number indicating how many elements are available.
+    #[serde(rename = "availableCount", skip_serializing_if = "Option::is_none")]
+    pub available_count: Option<u64>,
+    /// Integer indicating whether all of the elements are available (1) or all are
+    /// unavailable (0).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constant: Option<u8>,
+}
+
+impl Subtree {
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::from_reader_with_limits(reader, &Limits::default())
+    }
+
+    /// Read binary subtree, checking the chunk lengths against `limits` before reading them.
+    pub fn from_reader_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, Error> {
+        use self::Error::Io;
+        let header = read_section(&mut reader, Section::Header, 0, HEADER_LENGTH)?;
+        let mut header = &header[..];
+        let mut magic = [0; 4];
+        header.read_exact(&mut magic).map_err(Io)?;
+        if &magic != b"subt" {
+            return Err(Error::Magic(magic));
+        }
+        let version = header.read_u32::<LittleEndian>().map_err(Io)?;
+        if version != 1 {
+            return Err(Error::Version(version));
+        }
+        let json_byte_length = header.read_u64::<LittleEndian>().map_err(Io)?;
+        let binary_byte_length = header.read_u64::<LittleEndian>().map_err(Io)?;
+        let offset = HEADER_LENGTH as u64;
+        for (section, offset, length, limit) in &[
+            (
+                Section::SubtreeJson,
+                offset,
+                json_byte_length,
+                limits.max_json_byte_length,
+            ),
+            (
+                Section::SubtreeBinary,
+                offset + json_byte_length,
+                binary_byte_length,
+                limits.max_byte_length,
+            ),
+        ] {
+            if length > limit {
+                return Err(Error::LimitExceeded {
+                    section: *section,
+                    offset: *offset,
+                    length: *length,
+                    limit: *limit,
+                });
+            }
+        }
+        let json = read_section(
+            &mut reader,
+            Section::SubtreeJson,
+            HEADER_LENGTH,
+            json_byte_length as usize,
+        )?;
+        let json = serde_json::from_slice(&json).map_err(Error::Json)?;
+        let binary = read_section(
+            &mut reader,
+            Section::SubtreeBinary,
+            HEADER_LENGTH + json_byte_length as usize,
+            binary_byte_length as usize,
+        )?;
+        Ok(Subtree { json, binary })
+    }
+
+    /// Read subtree in JSON form.
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Self, Error> {
+        let json = serde_json::from_reader(reader).map_err(Error::Json)?;
+        Ok(Subtree {
+            json,
+            binary: Vec::new(),
+        })
+    }
+
+    /// Write binary subtree. Both chunks are padded to an 8-byte boundary.
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        use self::Error::Io;
+        let json = serde_json::to_vec(&self.json).map_err(Error::Json)?;
+        let json = pad(json, HEADER_LENGTH, b' ');
+        let binary = pad(self.binary.clone(), 0, 0);
+        writer.write_all(b"subt").map_err(Io)?;
+        writer.write_u32::<LittleEndian>(1).map_err(Io)?;
+        writer
+            .write_u64::<LittleEndian>(json.len() as u64)
+            .map_err(Io)?;
+        writer
+            .write_u64::<LittleEndian>(binary.len() as u64)
+            .map_err(Io)?;
+        writer.write_all(&json).map_err(Io)?;
+        writer.write_all(&binary).map_err(Io)?;
+        Ok(())
+    }
+
+    /// Write subtree in JSON form. All buffers must be external.
+    pub fn to_json_writer<W: Write>(&self, writer: W) -> Result<(), Error> {
+        if self.json.buffers.iter().any(|buffer| buffer.uri.is_none()) {
+            return Err(Error::format(
+                Section::SubtreeJson,
+                "a subtree in JSON form can't have a buffer without uri",
+            ));
+        }
+        serde_json::to_writer(writer, &self.json).map_err(Error::Json)
+    }
+
+    /// Data of all buffers. The binary chunk is used for the buffer without `uri`, external
+    /// buffers are read with `read`.
+    pub fn buffer_data<F>(&self, mut read: F) -> Result<Vec<Vec<u8>>, Error>
+    where
+        F: FnMut(&str) -> Result<Vec<u8>, Error>,
+    {
+        self.json
+            .buffers
+            .iter()
+            .map(|buffer| match buffer.uri {
+                Some(ref uri) => read(uri),
+                None => Ok(self.binary.clone()),
+            })
+            .collect()
+    }
+
+    /// Decode the availability of a subtree with `subtree_levels` levels, given the data of
+    /// the buffers, see [`Subtree::buffer_data`].
+    pub fn availability(
+        &self,
+        buffers: &[Vec<u8>],
+        scheme: SubdivisionScheme,
+        subtree_levels: u32,
+    ) -> Result<SubtreeAvailability, Error> {
+        let lengths = AvailabilityLengths::new(scheme, subtree_levels)?;
+        let decode = |name: &str, availability: &Availability, length: u64| {
+            self.bitstream(buffers, name, availability, length)
+        };
+        let json = &self.json;
+        let mut contents = Vec::new();
+        for (i, availability) in json.content_availability.iter().flatten().enumerate() {
+            let name = format!("contentAvailability/{}", i);
+            contents.push(decode(&name, availability, lengths.tiles)?);
+        }
+        Ok(SubtreeAvailability {
+            scheme,
+            subtree_levels,
+            tiles: decode("tileAvailability", &json.tile_availability, lengths.tiles)?,
+            contents,
+            child_subtrees: decode(
+                "childSubtreeAvailability",
+                &json.child_subtree_availability,
+                lengths.child_subtrees,
+            )?,
+        })
+    }
+
+    /// Decode the availability `name` with `length` elements.
+    fn bitstream(
+        &self,
+        buffers: &[Vec<u8>],
+        name: &str,
+        availability: &Availability,
+        length: u64,
+    ) -> Result<Bitstream, Error> {
+        let invalid =
+            |message: &str| Error::format(Section::SubtreeJson, format!("{}: {}", name, message));
+        match (availability.constant, availability.bitstream) {
+            (Some(constant), None) if constant <= 1 => Ok(Bitstream::Constant(constant == 1)),
+            (None, Some(index)) => {
+                let view = self
+                    .json
+                    .buffer_views
+                    .get(index)
+                    .ok_or_else(|| invalid("bitstream references a missing buffer view"))?;
+                let buffer = buffers
+                    .get(view.buffer)
+                    .ok_or_else(|| invalid("buffer view references a missing buffer"))?;
+                let view_name = format!("bufferViews/{}", index);
+                let data = body_slice(
+                    buffer,
+                    Section::SubtreeBinary,
+                    &view_name,
+                    view.byte_offset as usize,
+                    view.byte_length as usize,
+                    1,
+                )?;
+                let byte_length = (length + 7) / 8;
+                if (data.len() as u64) < byte_length {
+                    return Err(invalid(&format!(
+                        "bitstream has {} bytes, expected {}",
+                        data.len(),
+                        byte_length
+                    )));
+                }
+                Ok(Bitstream::Bits(data[..byte_length as usize].to_vec()))
+            }
+            _ => Err(invalid(
+                "either constant 0 or 1 or bitstream must be defined",
+            )),
+        }
+    }
+}
+
+/// Number of elements of the availabilities of a subtree.
+struct AvailabilityLengths {
+    /// Tiles or contents on all levels.
+    tiles: u64,
+    /// Child subtrees, i.e. tiles on the level below the subtree.
+    child_subtrees: u64,
+}
+
+impl AvailabilityLengths {
+    fn new(scheme: SubdivisionScheme, subtree_levels: u32) -> Result<Self, Error> {
+        let branching = branching_factor(scheme);
+        branching
+            .checked_pow(subtree_levels)
+            .filter(|_| subtree_levels > 0)
+            .map(|child_subtrees| AvailabilityLengths {
+                tiles: (child_subtrees - 1) / (branching - 1),
+                child_subtrees,
+            })
+            .ok_or_else(|| {
+                Error::format(
+                    Section::SubtreeJson,
+                    format!("unsupported number of subtree levels {}", subtree_levels),
+                )
+            })
+    }
+}
+
+/// Number of children of a tile.
+fn branching_factor(scheme: SubdivisionScheme) -> u64 {
+    match scheme {
+        SubdivisionScheme::Quadtree => 4,
+        SubdivisionScheme::Octree => 8,
+    }
+}
+
+/// Decoded availability of a set of elements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bitstream {
+    /// All elements are available or unavailable.
+    Constant(bool),
+    /// Availability of element `i` in bit `i % 8` of byte `i / 8`.
+    Bits(Vec<u8>),
+}
+
+impl Bitstream {
+    /// Whether element `index` is available. Elements beyond the bitstream are unavailable.
+    pub fn get(&self, index: u64) -> bool {
+        match self {
+            Bitstream::Constant(available) => *available,
+            Bitstream::Bits(bits) => bits
+                .get((index / 8) as usize)
+                .map_or(false, |byte| byte >> (index % 8) & 1 == 1),
+        }
+    }
+
+    /// Set the availability of element `index` of `length` elements.
+    fn set(&mut self, index: u64, length: u64, available: bool) {
+        if let Bitstream::Constant(constant) = *self {
+            if constant == available {
+                return;
+            }
+            let fill = if constant { 0xff } else { 0 };
+            *self = Bitstream::Bits(vec![fill; ((length + 7) / 8) as usize]);
+        }
+        if let Bitstream::Bits(ref mut bits) = self {
+            let byte = &mut bits[(index / 8) as usize];
+            if available {
+                *byte |= 1 << (index % 8);
+            } else {
+                *byte &= !(1 << (index % 8));
+            }
+        }
+    }
+
+    /// Number of available elements out of `length`.
+    pub fn available_count(&self, length: u64) -> u64 {
+        match self {
+            Bitstream::Constant(true) => length,
+            Bitstream::Constant(false) => 0,
+            Bitstream::Bits(bits) => {
+                let full_bytes = length / 8;
+                let count: u64 = bits
+                    .iter()
+                    .take(full_bytes as usize)
+                    .map(|byte| u64::from(byte.count_ones()))
+                    .sum();
+                count + (full_bytes * 8..length).filter(|i| self.get(*i)).count() as u64
+            }
+        }
+    }
+}
+
+/// Decoded availability of a subtree.
+///
+/// Tiles are identified by their level relative to the subtree root and their Morton index
+/// within the level. Child subtrees are identified by the Morton index of their root tile on
+/// the level below the subtree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtreeAvailability {
+    pub scheme: SubdivisionScheme,
+    pub subtree_levels: u32,
+    pub tiles: Bitstream,
+    /// Availability of each content of the tiles.
+    pub contents: Vec<Bitstream>,
+    pub child_subtrees: Bitstream,
+}
+
+impl SubtreeAvailability {
+    /// Availability of a subtree with `content_count` contents per tile, where nothing is
+    /// available. Fails if the number of tiles in the subtree exceeds `u64`.
+    pub fn new(
+        scheme: SubdivisionScheme,
+        subtree_levels: u32,
+        content_count: usize,
+    ) -> Result<Self, Error> {
+        AvailabilityLengths::new(scheme, subtree_levels)?;
+        Ok(SubtreeAvailability {
+            scheme,
+            subtree_levels,
+            tiles: Bitstream::Constant(false),
+            contents: vec![Bitstream::Constant(false); content_count],
+            child_subtrees: Bitstream::Constant(false),
+        })
+    }
+
+    fn lengths(&self) -> AvailabilityLengths {
+        AvailabilityLengths::new(self.scheme, self.subtree_levels).expect("checked on creation")
+    }
+
+    /// Index of the tile with Morton index `morton` on level `level` of the subtree in the
+    /// tile and content bitstreams, `None` if the tile is outside of the subtree.
+    pub fn tile_index(&self, level: u32, morton: u64) -> Option<u64> {
+        let branching = branching_factor(self.scheme);
+        if level >= self.subtree_levels || morton >= branching.pow(level) {
+            return None;
+        }
+        Some((branching.pow(level) - 1) / (branching - 1) + morton)
+    }
+
+    pub fn is_tile_available(&self, level: u32, morton: u64) -> bool {
+        self.tile_index(level, morton)
+            .map_or(false, |index| self.tiles.get(index))
+    }
+
+    /// Whether content `content` of the tile is available.
+    pub fn is_content_available(&self, content: usize, level: u32, morton: u64) -> bool {
+        match (self.contents.get(content), self.tile_index(level, morton)) {
+            (Some(bitstream), Some(index)) => bitstream.get(index),
+            _ => false,
+        }
+    }
+
+    /// Whether the subtree rooted at Morton index `morton` on the level below the subtree is
+    /// available.
+    pub fn is_child_subtree_available(&self, morton: u64) -> bool {
+        morton < self.lengths().child_subtrees && self.child_subtrees.get(morton)
+    }
+
+    /// Set the availability of a tile. Does nothing if the tile is outside of the subtree.
+    pub fn set_tile_available(&mut self, level: u32, morton: u64, available: bool) {
+        let length = self.lengths().tiles;
+        if let Some(index) = self.tile_index(level, morton) {
+            self.tiles.set(index, length, available);
+        }
+    }
+
+    /// Set the availability of content `content` of a tile. Does nothing if the tile is
+    /// outside of the subtree or if the content doesn't exist.
+    pub fn set_content_available(
+        &mut self,
+        content: usize,
+        level: u32,
+        morton: u64,
+        available: bool,
+    ) {
+        let length = self.lengths().tiles;
+        if let (Some(index), Some(bitstream)) = (
+            self.tile_index(level, morton),
+            self.contents.get_mut(content),
+        ) {
+            bitstream.set(index, length, available);
+        }
+    }
+
+    /// Set the availability of a child subtree. Does nothing if `morton` is out of range.
+    pub fn set_child_subtree_available(&mut self, morton: u64, available: bool) {
+        let length = self.lengths().child_subtrees;
+        if morton < length {
+            self.child_subtrees.set(morton, length, available);
+        }
+    }
+
+    /// Binary subtree with the availability.
+    ///
+    /// Bitstreams where all elements are equal are written as constant, all others into
+    /// buffer views of the binary chunk, each starting on an 8-byte boundary.
+    pub fn to_subtree(&self) -> Subtree {
+        let lengths = self.lengths();
+        let mut binary = Vec::new();
+        let mut buffer_views = Vec::new();
+        let mut encode = |bitstream: &Bitstream, length: u64| {
+            let available_count = bitstream.available_count(length);
+            let mut availability = Availability {
+                available_count: Some(available_count),
+                ..Default::default()
+            };
+            if available_count == 0 || available_count == length {
+                availability.constant = Some((available_count > 0) as u8);
+            } else if let Bitstream::Bits(ref bits) = bitstream {
+                binary = pad(std::mem::take(&mut binary), 0, 0);
+                availability.bitstream = Some(buffer_views.len());
+                buffer_views.push(BufferView {
+                    buffer: 0,
+                    byte_offset: binary.len() as u64,
+                    byte_length: bits.len() as u64,
+                    name: None,
+                    extensions: None,
+                    extras: None,
+                });
+                binary.extend_from_slice(bits);
+            }
+            availability
+        };
+        let tile_availability = encode(&self.tiles, lengths.tiles);
+        let content_availability = self
+            .contents
+            .iter()
+            .map(|bitstream| encode(bitstream, lengths.tiles))
+            .collect::<Vec<_>>();
+        let child_subtree_availability = encode(&self.child_subtrees, lengths.child_subtrees);
+        let buffers = if binary.is_empty() {
+            Vec::new()
+        } else {
+            vec![Buffer {
+                byte_length: binary.len() as u64,
+                uri: None,
+                name: None,
+                extensions: None,
+                extras: None,
+            }]
+        };
+        Subtree {
+            json: SubtreeJson {
+                buffers,
+                buffer_views,
+                tile_availability,
+                content_availability: Some(content_availability)
+                    .filter(|availability| !availability.is_empty()),
+                child_subtree_availability,
+                ..Default::default()
+            },
+            binary,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::ByteOrder;
+
+    /// Quadtree availability with 3 levels: root, children 1 and 2, grandchild 5 of child 1
+    /// with content and child subtree 9.
+    fn sample_availability() -> SubtreeAvailability {
+        let mut availability = SubtreeAvailability::new(SubdivisionScheme::Quadtree, 3, 2).unwrap();
+        for (level, morton) in &[(0, 0), (1, 1), (1, 2), (2, 5)] {
+            availability.set_tile_available(*level, *morton, true);
+        }
+        availability.set_content_available(0, 2, 5, true);
+        availability.set_content_available(1, 0, 0, true);
+        availability.set_child_subtree_available(9, true);
+        availability
+    }
+
+    fn to_bytes(subtree: &Subtree) -> Vec<u8> {
+        let mut data = Vec::new();
+        subtree.to_writer(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn bitstreams() {
+        let mut bits = Bitstream::Constant(true);
+        assert!(bits.get(1000));
+        assert_eq!(bits.available_count(21), 21);
+        bits.set(3, 21, true);
+        assert_eq!(bits, Bitstream::Constant(true));
+        bits.set(20, 21, false);
+        assert_eq!(bits, Bitstream::Bits(vec![0xff, 0xff, 0xef]));
+        assert!(!bits.get(20));
+        assert!(bits.get(19));
+        assert_eq!(bits.available_count(21), 20);
+        // Elements beyond the length and the bitstream
+        assert_eq!(bits.available_count(16), 16);
+        assert!(!bits.get(24));
+
+        let mut bits = Bitstream::Constant(false);
+        bits.set(9, 10, true);
+        assert_eq!(bits, Bitstream::Bits(vec![0, 0b10]));
+        assert_eq!(bits.available_count(10), 1);
+    }
+
+    #[test]
+    fn tile_indexes() {
+        let quadtree = SubtreeAvailability::new(SubdivisionScheme::Quadtree, 3, 0).unwrap();
+        assert_eq!(quadtree.tile_index(0, 0), Some(0));
+        assert_eq!(quadtree.tile_index(1, 3), Some(4));
+        assert_eq!(quadtree.tile_index(2, 0), Some(5));
+        assert_eq!(quadtree.tile_index(2, 15), Some(20));
+        assert_eq!(quadtree.tile_index(1, 4), None);
+        assert_eq!(quadtree.tile_index(3, 0), None);
+        let octree = SubtreeAvailability::new(SubdivisionScheme::Octree, 2, 0).unwrap();
+        assert_eq!(octree.tile_index(1, 7), Some(8));
+        assert_eq!(octree.tile_index(1, 8), None);
+
+        assert!(SubtreeAvailability::new(SubdivisionScheme::Quadtree, 0, 0).is_err());
+        assert!(SubtreeAvailability::new(SubdivisionScheme::Quadtree, 31, 0).is_ok());
+        assert!(SubtreeAvailability::new(SubdivisionScheme::Quadtree, 32, 0).is_err());
+        assert!(SubtreeAvailability::new(SubdivisionScheme::Octree, 22, 0).is_err());
+    }
+
+    #[test]
+    fn query_availability() {
+        let availability = sample_availability();
+        assert!(availability.is_tile_available(2, 5));
+        assert!(!availability.is_tile_available(2, 4));
+        assert!(availability.is_content_available(0, 2, 5));
+        assert!(!availability.is_content_available(0, 0, 0));
+        assert!(availability.is_content_available(1, 0, 0));
+        assert!(!availability.is_content_available(2, 0, 0));
+        assert!(availability.is_child_subtree_available(9));
+        assert!(!availability.is_child_subtree_available(8));
+        assert!(!availability.is_child_subtree_available(64));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let availability = sample_availability();
+        let subtree = availability.to_subtree();
+        // Content 1 is only available for the root, but not constant
+        assert_eq!(subtree.json.buffer_views.len(), 4);
+        assert!(subtree
+            .json
+            .buffer_views
+            .iter()
+            .all(|view| view.byte_offset % 8 == 0));
+        let data = to_bytes(&subtree);
+        assert_eq!(&data[0..4], b"subt");
+        let json_length = LittleEndian::read_u64(&data[8..16]) as usize;
+        let binary_length = LittleEndian::read_u64(&data[16..24]) as usize;
+        assert_eq!(json_length % 8, 0);
+        assert_eq!(binary_length % 8, 0);
+        assert_eq!(data.len(), HEADER_LENGTH + json_length + binary_length);
+
+        let read = Subtree::from_reader(&data[..]).unwrap();
+        let buffers = read
+            .buffer_data(|uri| panic!("unexpected buffer {}", uri))
+            .unwrap();
+        let decoded = read
+            .availability(&buffers, SubdivisionScheme::Quadtree, 3)
+            .unwrap();
+        assert_eq!(decoded, availability);
+        assert_eq!(to_bytes(&read), data);
+    }
+
+    #[test]
+    fn constant_availability() {
+        let mut availability = SubtreeAvailability::new(SubdivisionScheme::Octree, 2, 1).unwrap();
+        for morton in 0..8 {
+            availability.set_tile_available(1, morton, true);
+        }
+        availability.set_tile_available(0, 0, true);
+        let subtree = availability.to_subtree();
+        assert!(subtree.binary.is_empty());
+        assert!(subtree.json.buffers.is_empty());
+        assert_eq!(subtree.json.tile_availability.constant, Some(1));
+        assert_eq!(subtree.json.tile_availability.available_count, Some(9));
+        assert_eq!(subtree.json.child_subtree_availability.constant, Some(0));
+        let decoded = subtree
+            .availability(&[], SubdivisionScheme::Octree, 2)
+            .unwrap();
+        assert_eq!(decoded.tiles, Bitstream::Constant(true));
+        assert_eq!(decoded.contents, vec![Bitstream::Constant(false)]);
+    }
+
+    #[test]
+    fn json_form() {
+        let json = serde_json::json!({
+            "buffers": [{"byteLength": 16, "uri": "availability.bin"}],
+            "bufferViews": [{"buffer": 0, "byteOffset": 8, "byteLength": 1}],
+            "tileAvailability": {"bitstream": 0, "availableCount": 3},
+            "childSubtreeAvailability": {"constant": 0},
+            "propertyTables": [{"class": "tile"}],
+        });
+        let subtree = Subtree::from_json_reader(json.to_string().as_bytes()).unwrap();
+        assert!(subtree.binary.is_empty());
+        let mut data = vec![0; 16];
+        data[8] = 0b0_1011;
+        let buffers = subtree
+            .buffer_data(|uri| {
+                assert_eq!(uri, "availability.bin");
+                Ok(data.clone())
+            })
+            .unwrap();
+        let availability = subtree
+            .availability(&buffers, SubdivisionScheme::Quadtree, 2)
+            .unwrap();
+        assert!(availability.is_tile_available(0, 0));
+        assert!(availability.is_tile_available(1, 0));
+        assert!(!availability.is_tile_available(1, 1));
+        assert!(availability.is_tile_available(1, 2));
+        assert!(availability.contents.is_empty());
+
+        let mut written = Vec::new();
+        subtree.to_json_writer(&mut written).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&written).unwrap();
+        assert_eq!(written, json);
+
+        // The binary chunk can't be referenced in JSON form
+        let binary = sample_availability().to_subtree();
+        assert!(binary.to_json_writer(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn invalid_header() {
+        let data = to_bytes(&sample_availability().to_subtree());
+        let mut magic = data.clone();
+        magic[0..4].copy_from_slice(b"sbtr");
+        assert!(matches!(
+            Subtree::from_reader(&magic[..]),
+            Err(Error::Magic(m)) if &m == b"sbtr"
+        ));
+        let mut version = data.clone();
+        LittleEndian::write_u32(&mut version[4..8], 2);
+        assert!(matches!(
+            Subtree::from_reader(&version[..]),
+            Err(Error::Version(2))
+        ));
+        assert!(matches!(
+            Subtree::from_reader(&data[..data.len() - 1]),
+            Err(Error::Truncated {
+                section: Section::SubtreeBinary,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Subtree::from_reader(&data[..10]),
+            Err(Error::Truncated {
+                section: Section::Header,
+                ..
+            })
+        ));
+        let limits = Limits {
+            max_json_byte_length: 8,
+            ..Default::default()
+        };
+        assert!(matches!(
+            Subtree::from_reader_with_limits(&data[..], &limits),
+            Err(Error::LimitExceeded {
+                section: Section::SubtreeJson,
+                offset: 24,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_availability() {
+        let decode = |json: serde_json::Value, binary: Vec<u8>| {
+            let subtree = Subtree {
+                json: serde_json::from_value(json).unwrap(),
+                binary,
+            };
+            let buffers = subtree.buffer_data(|_| Ok(Vec::new())).unwrap();
+            subtree.availability(&buffers, SubdivisionScheme::Quadtree, 2)
+        };
+        let child_subtrees = serde_json::json!({"constant": 0});
+        for tiles in &[
+            serde_json::json!({"constant": 2}),
+            serde_json::json!({}),
+            serde_json::json!({"constant": 1, "bitstream": 0}),
+            serde_json::json!({"bitstream": 1}),
+        ] {
+            let json = serde_json::json!({
+                "buffers": [{"byteLength": 8}],
+                "bufferViews": [{"buffer": 0, "byteOffset": 0, "byteLength": 1}],
+                "tileAvailability": tiles,
+                "childSubtreeAvailability": child_subtrees,
+            });
+            assert!(
+                matches!(
+                    decode(json, vec![0; 8]),
+                    Err(Error::Format {
+                        section: Section::SubtreeJson,
+                        ..
+                    })
+                ),
+                "{}",
+                tiles
+            );
+        }
+        // Five tiles need a byte, the view is outside of the buffer or missing
+        let json = serde_json::json!({
+            "buffers": [{"byteLength": 8}],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 0},
+                {"buffer": 0, "byteOffset": 8, "byteLength": 1},
+                {"buffer": 1, "byteOffset": 0, "byteLength": 1},
+            ],
+            "tileAvailability": {"bitstream": 0},
+            "childSubtreeAvailability": {"bitstream": 1},
+        });
+        assert!(decode(json.clone(), vec![0; 8]).is_err());
+        let mut view_outside = json.clone();
+        view_outside["tileAvailability"]["bitstream"] = 1.into();
+        assert!(matches!(
+            decode(view_outside, vec![0; 8]),
+            Err(Error::PropertyReference {
+                section: Section::SubtreeBinary,
+                ..
+            })
+        ));
+        let mut missing_buffer = json;
+        missing_buffer["tileAvailability"]["bitstream"] = 2.into();
+        assert!(decode(missing_buffer, vec![0; 8]).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! Fixtures shared by the tests of several modules.
+
+use crate::error::{Error, Section};
+use crate::loader::{FileSource, Source};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+/// Source with in-memory resources.
+pub(crate) struct MemorySource(pub HashMap<String, Vec<u8>>);
+
+impl MemorySource {
+    /// Source with JSON resources.
+    pub fn new(resources: &[(&str, serde_json::Value)]) -> Self {
+        MemorySource(
+            resources
+                .iter()
+                .map(|(uri, json)| (uri.to_string(), json.to_string().into_bytes()))
+                .collect(),
+        )
+    }
+}
+
+impl Source for MemorySource {
+    fn read(&self, uri: &str) -> Result<Vec<u8>, Error> {
+        self.0
+            .get(uri)
+            .cloned()
+            .ok_or_else(|| Error::format(Section::Tileset, format!("{} not found", uri)))
+    }
+
+    fn resolve(&self, base: &str, uri: &str) -> String {
+        FileSource.resolve(base, uri)
+    }
+}
+
+/// Tileset with a root tile referencing `uri`.
+pub(crate) fn tileset(uri: &str) -> serde_json::Value {
+    serde_json::json!({
+        "asset": {"version": "1.0"},
+        "geometricError": 10,
+        "root": {
+            "boundingVolume": {"sphere": [0, 0, 0, 1]},
+            "geometricError": 10,
+            "refine": "ADD",
+            "content": {"uri": uri},
+        },
+    })
+}
+
+/// Feature Table of a tile format with a typed JSON header.
+pub(crate) trait FeatureTableParts {
+    type Header: DeserializeOwned;
+
+    fn from_parts(header: Self::Header, body: Vec<u8>) -> Self;
+}
+
+impl FeatureTableParts for crate::i3dm::FeatureTable {
+    type Header = crate::i3dm::InstancedFeatureTable;
+
+    fn from_parts(header: Self::Header, body: Vec<u8>) -> Self {
+        crate::i3dm::FeatureTable {
+            header,
+            raw_header: None,
+            body,
+        }
+    }
+}
+
+impl FeatureTableParts for crate::pnts::FeatureTable {
+    type Header = crate::pnts::PntsTable;
+
+    fn from_parts(header: Self::Header, body: Vec<u8>) -> Self {
+        crate::pnts::FeatureTable {
+            header,
+            raw_header: None,
+            body,
+        }
+    }
+}
+
+/// Feature Table with the JSON `header` and binary `body`.
+pub(crate) fn feature_table<T: FeatureTableParts>(header: serde_json::Value, body: Vec<u8>) -> T {
+    T::from_parts(serde_json::from_value(header).unwrap(), body)
+}
@@ -1,7 +1,14 @@
-use crate::error::Error;
+use crate::bounding_volume::Volume;
+use crate::error::{Error, Section};
+use crate::implicit::TileCoordinate;
+use crate::matrix::Matrix4;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::Read;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// A 3D Tiles tileset.
 ///
@@ -11,14 +18,16 @@ pub struct Tileset {
     /// Metadata about the entire tileset.
     pub asset: Asset,
     /// Dictionary object with extension-specific objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
     /// Names of 3D Tiles extensions required to properly load this tileset.
-    #[serde(rename = "extensionsRequired")]
+    #[serde(rename = "extensionsRequired", skip_serializing_if = "Option::is_none")]
     pub extensions_required: Option<Vec<String>>,
     /// Names of 3D Tiles extensions used somewhere in this tileset.
-    #[serde(rename = "extensionsUsed")]
+    #[serde(rename = "extensionsUsed", skip_serializing_if = "Option::is_none")]
     pub extensions_used: Option<Vec<String>>,
     /// Application-specific data.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
     /// The error, in meters, introduced if this tileset is not rendered. At runtime, the
     /// geometric error is used to compute screen space error (SSE), i.e., the error measured in
@@ -26,29 +35,40 @@ pub struct Tileset {
     #[serde(rename = "geometricError")]
     pub geometric_error: f64,
     /// A dictionary object of metadata about per-feature properties.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub properties: Option<PropertiesUnion>,
     /// The root tile.
     pub root: Tile,
+    /// Properties not modeled by this struct, written back unchanged.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
 }
 
 /// Metadata about the entire tileset.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Asset {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
     /// Application-specific version of this tileset, e.g., for when an existing tileset is
     /// updated.
-    #[serde(rename = "tilesetVersion")]
+    #[serde(rename = "tilesetVersion", skip_serializing_if = "Option::is_none")]
     pub tileset_version: Option<String>,
     /// The 3D Tiles version.  The version defines the JSON schema for the tileset JSON and the
     /// base set of tile formats.
     pub version: String,
+    /// Properties not modeled by this struct, written back unchanged.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
 }
 
 /// A dictionary object of metadata about per-feature properties.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Properties {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
     /// The maximum value of this property of all the features in the tileset.
     pub maximum: f64,
@@ -78,22 +98,37 @@ pub struct Tile {
     /// its parent tile's bounding volume and, generally, has a geometricError less than its
     /// parent tile's geometricError. For leaf tiles, the length of this array is zero, and
     /// children may not be defined.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub children: Option<Vec<Tile>>,
     /// Metadata about the tile's content and a link to the content. When this is omitted the
     /// tile is just used for culling. This is required for leaf tiles.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<TileContent>,
+    /// An array of contents. When this is defined, `content` must be undefined.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<Vec<TileContent>>,
+    /// Expiration of the tile content. When expired, the content is reloaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire: Option<TileExpire>,
     /// Dictionary object with extension-specific objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
     /// Application-specific data.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
     /// The error, in meters, introduced if this tile is rendered and its children are not. At
     /// runtime, the geometric error is used to compute screen space error (SSE), i.e., the error
     /// measured in pixels.
     #[serde(rename = "geometricError")]
     pub geometric_error: f64,
+    /// An object that describes the implicit subdivision of this tile. The content URI and the
+    /// subtree URI are templates, see [`implicit`](crate::implicit).
+    #[serde(rename = "implicitTiling", skip_serializing_if = "Option::is_none")]
+    pub implicit_tiling: Option<ImplicitTiling>,
     /// Specifies if additive or replacement refinement is used when traversing the tileset for
     /// rendering.  This property is required for the root tile of a tileset; it is optional for
     /// all other tiles.  The default is to inherit from the parent tile.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub refine: Option<Refine>,
     /// A floating-point 4x4 affine transformation matrix, stored in column-major order, that
     /// transforms the tile's content--i.e., its features as well as content.boundingVolume,
@@ -102,12 +137,19 @@ pub struct Tile {
     /// coordinate system to the tileset's coordinate system.  transform does not apply to
     /// geometricError, nor does it apply any volume property when the volume is a region,
     /// defined in EPSG:4979 coordinates.
-    pub transform: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Matrix4>,
     /// Optional bounding volume that defines the volume the viewer must be inside of before the
     /// tile's content will be requested and before the tile will be refined based on
     /// geometricError.
-    #[serde(rename = "viewerRequestVolume")]
+    #[serde(
+        rename = "viewerRequestVolume",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub viewer_request_volume: Option<BoundingVolume>,
+    /// Properties not modeled by this struct, written back unchanged.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
 }
 
 /// The bounding volume that encloses the tile.
@@ -119,25 +161,193 @@ pub struct Tile {
 /// Optional bounding volume that defines the volume the viewer must be inside of before the
 /// tile's content will be requested and before the tile will be refined based on
 /// geometricError.
+///
+/// Deserializing fails if more than one of `box`, `region` and `sphere` is present.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawBoundingVolume")]
 pub struct BoundingVolume {
-    /// An array of 12 numbers that define an oriented bounding box.  The first three elements
-    /// define the x, y, and z values for the center of the box.  The next three elements (with
-    /// indices 3, 4, and 5) define the x axis direction and half-length.  The next three
-    /// elements (indices 6, 7, and 8) define the y axis direction and half-length.  The last
-    /// three elements (indices 9, 10, and 11) define the z axis direction and half-length.
-    #[serde(rename = "box")]
-    pub bounding_volume_box: Option<Vec<f64>>,
+    /// An oriented bounding box (`box`), a bounding geographic region in EPSG:4979 coordinates
+    /// (`region`) or a bounding sphere (`sphere`).
+    #[serde(flatten)]
+    pub volume: Volume,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
-    /// An array of six numbers that define a bounding geographic region in EPSG:4979 coordinates
-    /// with the order [west, south, east, north, minimum height, maximum height]. Longitudes and
-    /// latitudes are in radians, and heights are in meters above (or below) the WGS84 ellipsoid.
-    pub region: Option<Vec<f64>>,
-    /// An array of four numbers that define a bounding sphere.  The first three elements define
-    /// the x, y, and z values for the center of the sphere.  The last element (with index 3)
-    /// defines the radius in meters.
-    pub sphere: Option<Vec<f64>>,
+    /// Properties not modeled by this struct, written back unchanged.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+/// A bounding volume as read, before checking for further volumes in `other`.
+#[derive(Deserialize)]
+struct RawBoundingVolume {
+    #[serde(flatten)]
+    volume: Volume,
+    extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    extras: Option<serde_json::Value>,
+    #[serde(flatten)]
+    other: HashMap<String, serde_json::Value>,
+}
+
+impl TryFrom<RawBoundingVolume> for BoundingVolume {
+    type Error = String;
+
+    fn try_from(raw: RawBoundingVolume) -> Result<Self, Self::Error> {
+        let more: Vec<_> = ["box", "region", "sphere"]
+            .iter()
+            .filter(|name| raw.other.contains_key(**name))
+            .collect();
+        if !more.is_empty() {
+            return Err(format!(
+                "bounding volume must have one of box, region and sphere, found also {:?}",
+                more
+            ));
+        }
+        Ok(BoundingVolume {
+            volume: raw.volume,
+            extensions: raw.extensions,
+            extras: raw.extras,
+            other: raw.other,
+        })
+    }
+}
+
+impl BoundingVolume {
+    /// Bounding volume of the tile at `coordinate` in an implicit subdivision of this root
+    /// bounding volume, `None` for spheres. Extensions and extras are not inherited.
+    pub fn subdivide(&self, coordinate: &TileCoordinate) -> Option<BoundingVolume> {
+        self.volume.subdivide(coordinate).map(BoundingVolume::from)
+    }
+}
+
+impl From<Volume> for BoundingVolume {
+    fn from(volume: Volume) -> Self {
+        BoundingVolume {
+            volume,
+            extensions: None,
+            extras: None,
+            other: HashMap::new(),
+        }
+    }
+}
+
+/// Expiration of the tile content, either after a duration or at a date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileExpire {
+    /// Seconds after loading the content when it expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// Date when the content expires, in ISO 8601 format, e.g. `2021-05-21T08:30:00Z`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+
+impl TileExpire {
+    /// Time when content loaded at `loaded` expires, `None` if neither duration nor date is
+    /// defined.
+    pub fn expires_at(&self, loaded: SystemTime) -> Result<Option<SystemTime>, Error> {
+        if let Some(duration) = self.duration {
+            let duration = seconds(duration).ok_or_else(|| {
+                Error::format(
+                    Section::Tileset,
+                    format!("invalid expire duration {}", duration),
+                )
+            })?;
+            Ok(Some(loaded + duration))
+        } else if let Some(ref date) = self.date {
+            parse_date(date).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Whether content loaded at `loaded` has expired at `now`.
+    pub fn is_expired(&self, loaded: SystemTime, now: SystemTime) -> Result<bool, Error> {
+        Ok(self
+            .expires_at(loaded)?
+            .map_or(false, |expires_at| now >= expires_at))
+    }
+}
+
+/// Parse an ISO 8601 date with time, e.g. `2021-05-21T08:30:00.5+02:00`. Without time zone,
+/// UTC is assumed.
+fn parse_date(date: &str) -> Result<SystemTime, Error> {
+    let invalid = || Error::format(Section::Tileset, format!("invalid expire date `{}`", date));
+    let number = |s: &str| s.parse::<i64>().map_err(|_| invalid());
+    let (day, time) = match date.find(['T', ' ']) {
+        Some(pos) => (&date[..pos], &date[pos + 1..]),
+        None => (date, ""),
+    };
+    let mut parts = day.splitn(3, '-');
+    let (year, month, day) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(y), Some(m), Some(d)) => (number(y)?, number(m)?, number(d)?),
+        _ => return Err(invalid()),
+    };
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&month) || !(1..=days_in_month).contains(&day) {
+        return Err(invalid());
+    }
+    // Split the time zone designator from the time
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else if let Some(pos) = time.rfind(['+', '-']) {
+        let sign = if time[pos..].starts_with('-') { -1 } else { 1 };
+        let mut zone = time[pos + 1..].splitn(2, ':');
+        let hours = number(zone.next().unwrap_or(""))?;
+        let minutes = zone.next().map_or(Ok(0), number)?;
+        if !(0..24).contains(&hours) || !(0..60).contains(&minutes) {
+            return Err(invalid());
+        }
+        (&time[..pos], sign * (hours * 3600 + minutes * 60))
+    } else {
+        (time, 0)
+    };
+    let mut parts = time.splitn(3, ':');
+    let hours = parts
+        .next()
+        .filter(|s| !s.is_empty())
+        .map_or(Ok(0), number)?;
+    let minutes = parts.next().map_or(Ok(0), number)?;
+    let seconds: f64 = match parts.next() {
+        Some(s) => s.parse().map_err(|_| invalid())?,
+        None => 0.0,
+    };
+    // Seconds up to 60 allow for leap seconds
+    if !(0..24).contains(&hours) || !(0..60).contains(&minutes) || !(0.0..61.0).contains(&seconds) {
+        return Err(invalid());
+    }
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds = (days * 86400 + hours * 3600 + minutes * 60 - offset) as f64 + seconds;
+    let since_epoch = self::seconds(seconds.abs()).ok_or_else(invalid)?;
+    if seconds >= 0.0 {
+        Ok(UNIX_EPOCH + since_epoch)
+    } else {
+        Ok(UNIX_EPOCH - since_epoch)
+    }
+}
+
+/// Duration of `seconds`, `None` if negative, not finite or too large.
+fn seconds(seconds: f64) -> Option<Duration> {
+    if seconds >= 0.0 && seconds < u64::MAX as f64 {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
 }
 
 /// Metadata about the tile's content and a link to the content. When this is omitted the
@@ -147,19 +357,75 @@ pub struct TileContent {
     /// An optional bounding volume that tightly encloses just the tile's content.
     /// tile.boundingVolume provides spatial coherence and tile.content.boundingVolume enables
     /// tight view frustum culling. When this is omitted, tile.boundingVolume is used.
-    #[serde(rename = "boundingVolume")]
+    #[serde(rename = "boundingVolume", skip_serializing_if = "Option::is_none")]
     pub bounding_volume: Option<BoundingVolume>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extras: Option<serde_json::Value>,
+    /// The group this content belongs to, as index into the `groups` of the tileset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<usize>,
     /// A uri that points to the tile's content. When the uri is relative, it is relative to the
     /// referring tileset JSON file.
     pub uri: String,
+    /// Properties not modeled by this struct, written back unchanged.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+/// Implicit subdivision of a tile into a quadtree or octree.
+///
+/// <https://github.com/CesiumGS/3d-tiles/tree/main/specification/ImplicitTiling>
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImplicitTiling {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
+    /// The number of levels in the tree with available tiles, including the root level.
+    #[serde(rename = "availableLevels")]
+    pub available_levels: u32,
+    /// A string describing the subdivision scheme used within the tileset.
+    #[serde(rename = "subdivisionScheme")]
+    pub subdivision_scheme: SubdivisionScheme,
+    /// The number of distinct levels in each subtree.
+    #[serde(rename = "subtreeLevels")]
+    pub subtree_levels: u32,
+    /// An object describing the location of subtree files.
+    pub subtrees: Subtrees,
+    /// Properties not modeled by this struct, written back unchanged.
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+/// Subdivision scheme of an implicit tileset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubdivisionScheme {
+    /// Each tile is divided into four children along the x and y axes.
+    #[serde(rename = "QUADTREE")]
+    Quadtree,
+    /// Each tile is divided into eight children along the x, y and z axes.
+    #[serde(rename = "OCTREE")]
+    Octree,
+}
+
+/// Location of the subtree files of an implicit tileset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subtrees {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<HashMap<String, HashMap<String, Option<serde_json::Value>>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<serde_json::Value>,
+    /// A template URI pointing to subtree files. When the uri is relative, it is relative to
+    /// the referring tileset JSON file.
+    pub uri: String,
 }
 
 /// Specifies if additive or replacement refinement is used when traversing the tileset for
 /// rendering.  This property is required for the root tile of a tileset; it is optional for
 /// all other tiles.  The default is to inherit from the parent tile.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Refine {
     #[serde(rename = "ADD")]
     Add,
@@ -172,4 +438,705 @@ impl Tileset {
         let tileset: Tileset = serde_json::from_reader(reader).map_err(Error::Json)?;
         Ok(tileset)
     }
+
+    /// Write tileset JSON.
+    ///
+    /// Object keys are written in sorted order, independent of the options. Modeled numbers
+    /// without fractional part, like geometric errors and volumes, are written as integers.
+    /// Unknown properties, extensions and extras are written unchanged.
+    pub fn to_writer<W: Write>(&self, writer: W, options: &WriteOptions) -> Result<(), Error> {
+        let mut json = serde_json::to_value(self).map_err(Error::Json)?;
+        let mut write_number = |number: &mut serde_json::Value, geometry: bool| {
+            if let (true, Some(digits)) = (geometry, options.significant_digits) {
+                round_number(number, digits);
+            }
+            integral_number(number);
+        };
+        if let Some(geometric_error) = json.get_mut("geometricError") {
+            write_number(geometric_error, false);
+        }
+        if let Some(serde_json::Value::Object(properties)) = json.get_mut("properties") {
+            for property in properties.values_mut() {
+                for key in &["maximum", "minimum"] {
+                    if let Some(number) = property.get_mut(*key) {
+                        write_number(number, false);
+                    }
+                }
+            }
+        }
+        if let Some(root) = json.get_mut("root") {
+            tile_numbers(root, &mut write_number);
+        }
+        if options.pretty {
+            serde_json::to_writer_pretty(writer, &json).map_err(Error::Json)
+        } else {
+            serde_json::to_writer(writer, &json).map_err(Error::Json)
+        }
+    }
+
+    /// Write tileset JSON file.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P, options: &WriteOptions) -> Result<(), Error> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| Error::Io(e).with_path(path))?;
+        let mut writer = BufWriter::new(file);
+        self.to_writer(&mut writer, options)
+            .and_then(|_| writer.flush().map_err(Error::Io))
+            .map_err(|e| e.with_path(path))
+    }
+}
+
+/// Replace a float without fractional part by an integer, e.g. a geometric error of `0.0`
+/// by `0`.
+fn integral_number(value: &mut serde_json::Value) {
+    if let serde_json::Value::Number(number) = value {
+        if let Some(v) = number.as_f64().filter(|v| v.fract() == 0.0) {
+            if number.is_f64() && v.abs() < (1u64 << 53) as f64 {
+                *number = serde_json::Number::from(v as i64);
+            }
+        }
+    }
+}
+
+/// Round a number to `digits` significant digits.
+fn round_number(value: &mut serde_json::Value, digits: usize) {
+    if let Some(rounded) = value
+        .as_f64()
+        .and_then(|v| format!("{:.*e}", digits.saturating_sub(1), v).parse().ok())
+        .and_then(serde_json::Number::from_f64)
+    {
+        *value = serde_json::Value::Number(rounded);
+    }
+}
+
+/// Options for writing tileset JSON.
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Indent the output.
+    pub pretty: bool,
+    /// Round transforms and bounding volumes to this number of significant digits.
+    ///
+    /// Significant digits keep the same relative precision for ECEF coordinates in meters and
+    /// region coordinates in radians, e.g. 10 digits are about a millimeter on the globe.
+    pub significant_digits: Option<usize>,
+}
+
+/// Call `f` for the modeled numbers of a serialized tile and its children.
+///
+/// The numbers of transforms and bounding volumes are passed with `true`, geometric errors
+/// and expire durations with `false`.
+fn tile_numbers<F: FnMut(&mut serde_json::Value, bool)>(tile: &mut serde_json::Value, f: &mut F) {
+    let array = |value: Option<&mut serde_json::Value>, f: &mut F| {
+        if let Some(serde_json::Value::Array(numbers)) = value {
+            numbers.iter_mut().for_each(|number| f(number, true));
+        }
+    };
+    let volume = |value: Option<&mut serde_json::Value>, f: &mut F| {
+        if let Some(value) = value {
+            for key in &["box", "region", "sphere"] {
+                array(value.get_mut(*key), f);
+            }
+        }
+    };
+    if let Some(geometric_error) = tile.get_mut("geometricError") {
+        f(geometric_error, false);
+    }
+    if let Some(duration) = tile.get_mut("expire").and_then(|e| e.get_mut("duration")) {
+        f(duration, false);
+    }
+    array(tile.get_mut("transform"), f);
+    volume(tile.get_mut("boundingVolume"), f);
+    volume(tile.get_mut("viewerRequestVolume"), f);
+    if let Some(content) = tile.get_mut("content") {
+        volume(content.get_mut("boundingVolume"), f);
+    }
+    if let Some(serde_json::Value::Array(contents)) = tile.get_mut("contents") {
+        for content in contents.iter_mut() {
+            volume(content.get_mut("boundingVolume"), f);
+        }
+    }
+    if let Some(serde_json::Value::Array(children)) = tile.get_mut("children") {
+        for child in children.iter_mut() {
+            tile_numbers(child, f);
+        }
+    }
+}
+
+impl Tile {
+    /// Local transform, identity if undefined.
+    pub fn local_transform(&self) -> Matrix4 {
+        self.transform.unwrap_or_default()
+    }
+
+    /// The content and all entries of `contents`.
+    pub fn all_contents(&self) -> impl Iterator<Item = &TileContent> {
+        self.content.iter().chain(self.contents.iter().flatten())
+    }
+
+    /// Whether the tile content loaded at `loaded` has expired at `now`.
+    pub fn is_expired(&self, loaded: SystemTime, now: SystemTime) -> Result<bool, Error> {
+        match self.expire {
+            Some(ref expire) => expire.is_expired(loaded, now),
+            None => Ok(false),
+        }
+    }
+
+    /// Iterate over the tile and its descendants, depth-first in pre-order.
+    pub fn depth_first(&self) -> DepthFirst<'_> {
+        DepthFirst {
+            stack: vec![TileNode::root(self)],
+        }
+    }
+
+    /// Iterate over the tile and its descendants, level by level.
+    pub fn breadth_first(&self) -> BreadthFirst<'_> {
+        BreadthFirst {
+            queue: vec![TileNode::root(self)].into(),
+        }
+    }
+}
+
+/// A tile visited by a traversal.
+#[derive(Debug, Clone)]
+pub struct TileNode<'a> {
+    pub tile: &'a Tile,
+    /// Depth below the start tile, which has depth 0.
+    pub depth: usize,
+    /// Indexes into the children of each ancestor, leading from the start tile to this tile.
+    pub path: Vec<usize>,
+    /// Refinement of the tile, inherited from the nearest ancestor if not defined.
+    pub refine: Option<Refine>,
+    /// Transform from the tile's coordinate system to the coordinate system of the start
+    /// tile's parent, combining the transforms of all ancestors.
+    pub transform: Matrix4,
+}
+
+impl<'a> TileNode<'a> {
+    pub(crate) fn root(tile: &'a Tile) -> Self {
+        TileNode {
+            tile,
+            depth: 0,
+            path: Vec::new(),
+            refine: tile.refine,
+            transform: tile.local_transform(),
+        }
+    }
+
+    /// Nodes of the children of the tile.
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = TileNode<'a>> + '_ {
+        let children = self.tile.children.as_deref().unwrap_or(&[]);
+        children.iter().enumerate().map(move |(i, child)| {
+            let mut path = self.path.clone();
+            path.push(i);
+            TileNode {
+                tile: child,
+                depth: self.depth + 1,
+                path,
+                refine: child.refine.or(self.refine),
+                transform: self.transform * child.local_transform(),
+            }
+        })
+    }
+}
+
+/// Depth-first iterator over tiles, see [`Tile::depth_first`].
+pub struct DepthFirst<'a> {
+    stack: Vec<TileNode<'a>>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = TileNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().rev());
+        Some(node)
+    }
+}
+
+/// Breadth-first iterator over tiles, see [`Tile::breadth_first`].
+pub struct BreadthFirst<'a> {
+    queue: VecDeque<TileNode<'a>>,
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = TileNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+        self.queue.extend(node.children());
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding_volume::Sphere;
+
+    #[test]
+    fn bounding_volume_properties() {
+        let json = r#"{"sphere":[1,2,3,4],"extras":{"a":1},"unknown":true}"#;
+        let volume: BoundingVolume = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            volume.volume,
+            Volume::Sphere(Sphere {
+                center: [1.0, 2.0, 3.0],
+                radius: 4.0
+            })
+        );
+        assert_eq!(volume.extras, Some(serde_json::json!({"a": 1})));
+        assert_eq!(volume.other["unknown"], serde_json::json!(true));
+    }
+
+    #[test]
+    fn reject_multiple_volumes() {
+        let json = r#"{"box":[0,0,0,1,0,0,0,1,0,0,0,1],"sphere":[0,0,0,1]}"#;
+        let error = serde_json::from_str::<BoundingVolume>(json).unwrap_err();
+        assert!(error.to_string().contains("sphere"), "{}", error);
+        let json = r#"{"region":[0,0,1,1,0,1],"box":[0,0,0,1,0,0,0,1,0,0,0,1]}"#;
+        assert!(serde_json::from_str::<BoundingVolume>(json).is_err());
+        assert!(serde_json::from_str::<BoundingVolume>(r#"{"extras":{}}"#).is_err());
+    }
+
+    /// Tile with the given JSON.
+    fn tile(json: serde_json::Value) -> Tile {
+        serde_json::from_value(json).unwrap()
+    }
+
+    /// Tree of tiles `root` > (`a` > `a1`), `b` with content uris named after the tiles.
+    fn tree() -> Tile {
+        let leaf = |name: &str| {
+            serde_json::json!({
+                "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                "geometricError": 0,
+                "content": {"uri": name},
+            })
+        };
+        let transform = |m: Matrix4| serde_json::to_value(m).unwrap();
+        let mut a1 = leaf("a1");
+        a1["transform"] = transform(Matrix4::from_translation([0.0, 5.0, 0.0]));
+        let mut scaled = Matrix4::from_translation([1.0, 0.0, 0.0]);
+        for i in &[0, 5, 10] {
+            scaled.0[*i] = 2.0;
+        }
+        let mut a = leaf("a");
+        a["transform"] = transform(scaled);
+        a["refine"] = serde_json::json!("ADD");
+        a["children"] = serde_json::json!([a1]);
+        let mut root = leaf("root");
+        root["transform"] = transform(Matrix4::from_translation([0.0, 0.0, 100.0]));
+        root["geometricError"] = serde_json::json!(10);
+        root["refine"] = serde_json::json!("REPLACE");
+        root["children"] = serde_json::json!([a, leaf("b")]);
+        tile(root)
+    }
+
+    fn uri(node: &TileNode) -> String {
+        node.tile.content.as_ref().unwrap().uri.clone()
+    }
+
+    #[test]
+    fn depth_first() {
+        let tree = tree();
+        let nodes: Vec<_> = tree.depth_first().collect();
+        let uris: Vec<_> = nodes.iter().map(uri).collect();
+        assert_eq!(uris, ["root", "a", "a1", "b"]);
+        let depths: Vec<_> = nodes.iter().map(|node| node.depth).collect();
+        assert_eq!(depths, [0, 1, 2, 1]);
+        assert_eq!(nodes[2].path, [0, 0]);
+        assert_eq!(nodes[3].path, [1]);
+    }
+
+    #[test]
+    fn breadth_first() {
+        let tree = tree();
+        let uris: Vec<_> = tree.breadth_first().map(|node| uri(&node)).collect();
+        assert_eq!(uris, ["root", "a", "b", "a1"]);
+    }
+
+    #[test]
+    fn inherited_refine_and_transform() {
+        let tree = tree();
+        let nodes: Vec<_> = tree.depth_first().collect();
+        let refines: Vec<_> = nodes.iter().map(|node| node.refine).collect();
+        assert_eq!(
+            refines,
+            [
+                Some(Refine::Replace),
+                Some(Refine::Add),
+                Some(Refine::Add),
+                Some(Refine::Replace)
+            ]
+        );
+        // a1 is translated by 5 in y, scaled by 2 and translated to x 1 by a, then moved up
+        // by the root transform
+        assert_eq!(
+            nodes[2].transform.transform_point([0.0; 3]),
+            [1.0, 10.0, 100.0]
+        );
+        assert_eq!(
+            nodes[2].transform.transform_point([1.0, 0.0, 0.0]),
+            [3.0, 10.0, 100.0]
+        );
+        assert_eq!(
+            nodes[3].transform,
+            Matrix4::from_translation([0.0, 0.0, 100.0])
+        );
+    }
+
+    #[test]
+    fn traverse_sample() {
+        let data = crate::sample("TilesetWithDiscreteLOD/tileset.json");
+        let tileset = Tileset::from_reader(&data[..]).unwrap();
+        let nodes: Vec<_> = tileset.root.depth_first().collect();
+        let uris: Vec<_> = nodes.iter().map(uri).collect();
+        assert_eq!(
+            uris,
+            ["dragon_low.b3dm", "dragon_medium.b3dm", "dragon_high.b3dm"]
+        );
+        for node in &nodes {
+            assert_eq!(node.refine, Some(Refine::Replace));
+            assert_eq!(Some(node.transform), tileset.root.transform);
+        }
+    }
+
+    #[test]
+    fn expire_duration() {
+        let loaded = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let expire = TileExpire {
+            duration: Some(5.0),
+            date: Some("2000-01-01T00:00:00Z".to_string()),
+        };
+        assert_eq!(
+            expire.expires_at(loaded).unwrap(),
+            Some(loaded + Duration::from_secs(5))
+        );
+        assert!(!expire
+            .is_expired(loaded, loaded + Duration::from_secs(4))
+            .unwrap());
+        assert!(expire
+            .is_expired(loaded, loaded + Duration::from_secs(5))
+            .unwrap());
+
+        let invalid = TileExpire {
+            duration: Some(-1.0),
+            date: None,
+        };
+        assert!(invalid.expires_at(loaded).is_err());
+        let none = TileExpire {
+            duration: None,
+            date: None,
+        };
+        assert_eq!(none.expires_at(loaded).unwrap(), None);
+        assert!(!none.is_expired(loaded, SystemTime::now()).unwrap());
+    }
+
+    #[test]
+    fn expire_date() {
+        let at = |date: &str| {
+            let expire = TileExpire {
+                duration: None,
+                date: Some(date.to_string()),
+            };
+            expire.expires_at(SystemTime::UNIX_EPOCH).map(|time| {
+                time.unwrap()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+            })
+        };
+        assert_eq!(at("1970-01-01").unwrap(), Duration::from_secs(0));
+        assert_eq!(
+            at("2021-05-21T08:30:00Z").unwrap(),
+            Duration::from_secs(1_621_585_800)
+        );
+        assert_eq!(
+            at("2021-05-21T10:30:00.5+02:00").unwrap(),
+            Duration::from_millis(1_621_585_800_500)
+        );
+        assert_eq!(
+            at("2000-02-29T00:00:00-01:30").unwrap(),
+            Duration::from_secs(951_787_800)
+        );
+        assert!(at("2024-02-29").is_ok());
+        // Leap second
+        assert_eq!(
+            at("2016-12-31T23:59:60Z").unwrap(),
+            Duration::from_secs(1_483_228_800)
+        );
+        for invalid in &[
+            "",
+            "2021-13-01",
+            "2021-05-32",
+            "2021-04-31",
+            "2021-02-29",
+            "1900-02-29",
+            "2021-02-31T25:99:00Z",
+            "2021-05-21T24:00:00Z",
+            "2021-05-21T08:60:00Z",
+            "2021-05-21T08:30:61Z",
+            "2021-05-21T08:-1:00Z",
+            "2021-05-21T08:30:00+24:00",
+            "2021-05",
+            "2021-05-21T08:xx",
+        ] {
+            assert!(at(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn sample_expire_round_trip() {
+        let data = crate::sample("TilesetWithExpiration/tileset.json");
+        let tileset = Tileset::from_reader(&data[..]).unwrap();
+        let expire = tileset.root.expire.as_ref().unwrap();
+        assert_eq!(expire.duration, Some(5.0));
+        assert!(expire.date.is_none());
+
+        let json = serde_json::to_value(&tileset).unwrap();
+        let expire = json["root"]["expire"].as_object().unwrap();
+        assert_eq!(expire.len(), 1);
+        assert_eq!(expire["duration"].as_f64(), Some(5.0));
+        let written: Tileset = serde_json::from_value(json).unwrap();
+        assert_eq!(written.root.expire.unwrap().duration, Some(5.0));
+    }
+
+    #[test]
+    fn tile_expired() {
+        let mut tile = tile(serde_json::json!({
+            "boundingVolume": {"sphere": [0, 0, 0, 1]},
+            "geometricError": 0,
+            "expire": {"date": "2021-05-21T08:30:00Z"},
+        }));
+        let loaded = SystemTime::UNIX_EPOCH;
+        let before = loaded + Duration::from_secs(1_621_585_799);
+        let after = loaded + Duration::from_secs(1_621_585_800);
+        assert!(!tile.is_expired(loaded, before).unwrap());
+        assert!(tile.is_expired(loaded, after).unwrap());
+        tile.expire = None;
+        assert!(!tile.is_expired(loaded, after).unwrap());
+    }
+
+    #[test]
+    fn preserve_unknown_properties() {
+        let json = serde_json::json!({
+            "asset": {"version": "1.0", "generator": "tool"},
+            "geometricError": 500,
+            "schema": {"classes": {"building": {}}},
+            "root": {
+                "boundingVolume": {
+                    "region": [-1.3, 0.69, -1.29, 0.7, 0, 20],
+                    "vendor": {"volume": 1.0}
+                },
+                "geometricError": 100,
+                "refine": "ADD",
+                "metadata": {"class": "building", "height": 12.0},
+                "extras": {"scale": 2.0},
+                "content": {"uri": "root.b3dm", "group": 0},
+                "children": [{
+                    "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                    "geometricError": 0,
+                    "contents": [{"uri": "a.glb"}, {"uri": "b.glb"}],
+                    "implicitTiling": {
+                        "subdivisionScheme": "QUADTREE",
+                        "subtreeLevels": 2,
+                        "availableLevels": 3,
+                        "subtrees": {"uri": "{level}/{x}/{y}.subtree"},
+                        "vendor": [1, 2]
+                    }
+                }]
+            }
+        });
+        let tileset: Tileset = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(tileset.asset.other["generator"], "tool");
+        assert_eq!(tileset.root.other["metadata"]["class"], "building");
+        assert_eq!(tileset.root.bounding_volume.other["vendor"]["volume"], 1.0);
+
+        let mut out = Vec::new();
+        tileset
+            .to_writer(&mut out, &WriteOptions::default())
+            .unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(written, json);
+        // Floats of unknown properties and extras keep their fractional part
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#""volume":1.0"#), "{}", out);
+        assert!(out.contains(r#""height":12.0"#), "{}", out);
+        assert!(out.contains(r#""scale":2.0"#), "{}", out);
+    }
+
+    /// Write `tileset` with `options` into a string.
+    fn write(tileset: &Tileset, options: &WriteOptions) -> String {
+        let mut out = Vec::new();
+        tileset.to_writer(&mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn sample_tileset() -> Tileset {
+        Tileset::from_reader(&crate::sample("TilesetWithDiscreteLOD/tileset.json")[..]).unwrap()
+    }
+
+    #[test]
+    fn write_compact_and_pretty() {
+        let tileset = sample_tileset();
+        let compact = write(&tileset, &WriteOptions::default());
+        let pretty = write(
+            &tileset,
+            &WriteOptions {
+                pretty: true,
+                ..Default::default()
+            },
+        );
+        assert!(!compact.contains('\n'));
+        assert!(pretty.contains("\n  \"asset\": {\n"));
+        let compact_json: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        let pretty_json: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(compact_json, pretty_json);
+
+        // Keys are sorted independent of the options
+        let keys = |json: &str| {
+            let mut keys = Vec::new();
+            let mut rest = json;
+            while let Some(pos) = rest.find("\":") {
+                let start = rest[..pos].rfind('"').unwrap();
+                keys.push(rest[start + 1..pos].to_string());
+                rest = &rest[pos + 2..];
+            }
+            keys
+        };
+        let pretty = pretty.replace("\": ", "\":");
+        assert_eq!(keys(&compact), keys(&pretty));
+        assert!(
+            compact.starts_with(r#"{"asset":{"version":"1.0"},"geometricError":500,"root":{"#),
+            "{}",
+            compact
+        );
+        assert_eq!(compact, write(&sample_tileset(), &WriteOptions::default()));
+    }
+
+    #[test]
+    fn write_sample_unchanged() {
+        for name in &[
+            "TilesetWithDiscreteLOD/tileset.json",
+            "TilesetWithExpiration/tileset.json",
+            "TilesetWithRequestVolume/tileset.json",
+        ] {
+            let data = crate::sample(name);
+            let tileset = Tileset::from_reader(&data[..]).unwrap();
+            let written: serde_json::Value =
+                serde_json::from_str(&write(&tileset, &WriteOptions::default())).unwrap();
+            let expected: serde_json::Value = serde_json::from_slice(&data).unwrap();
+            assert_eq!(written, expected, "{}", name);
+        }
+        let data = crate::sample("TilesetWithExpiration/tileset.json");
+        let tileset = Tileset::from_reader(&data[..]).unwrap();
+        assert!(write(&tileset, &WriteOptions::default()).contains(r#""expire":{"duration":5}"#));
+    }
+
+    #[test]
+    fn write_integers() {
+        let tileset: Tileset = serde_json::from_value(serde_json::json!({
+            "asset": {"version": "1.0"},
+            "geometricError": 70.5,
+            "root": {
+                "boundingVolume": {"sphere": [0, 0, 0, 1.5]},
+                "geometricError": 0,
+                "expire": {"duration": 5},
+            }
+        }))
+        .unwrap();
+        let json = write(&tileset, &WriteOptions::default());
+        assert_eq!(
+            json,
+            r#"{"asset":{"version":"1.0"},"geometricError":70.5,"root":{"boundingVolume":{"sphere":[0,0,0,1.5]},"expire":{"duration":5},"geometricError":0}}"#
+        );
+    }
+
+    #[test]
+    fn write_significant_digits() {
+        let tileset: Tileset = serde_json::from_value(serde_json::json!({
+            "asset": {"version": "1.0"},
+            "geometricError": 1.23456789,
+            "root": {
+                "boundingVolume": {
+                    "region": [
+                        -1.3197004795898053, 0.6988582109, -1.3196595204101946, 0.6988897891,
+                        0, 20.123456
+                    ]
+                },
+                "geometricError": 0,
+                "transform": [
+                    1.000001, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0,
+                    1215012.8828876738, -4736313.051199853, 4081605.22126042, 1
+                ],
+                "content": {
+                    "uri": "a.b3dm",
+                    "boundingVolume": {"sphere": [1.23456789, 0, 0, 0.000123456789]}
+                },
+                "extras": {"value": 1.23456789}
+            }
+        }))
+        .unwrap();
+        let options = WriteOptions {
+            significant_digits: Some(4),
+            ..Default::default()
+        };
+        let json: serde_json::Value = serde_json::from_str(&write(&tileset, &options)).unwrap();
+        let root = &json["root"];
+        assert_eq!(
+            root["boundingVolume"]["region"],
+            serde_json::json!([-1.32, 0.6989, -1.32, 0.6989, 0, 20.12])
+        );
+        assert_eq!(
+            root["transform"],
+            serde_json::json!([1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 1215000, -4736000, 4082000, 1])
+        );
+        assert_eq!(
+            root["content"]["boundingVolume"]["sphere"],
+            serde_json::json!([1.235, 0, 0, 0.0001235])
+        );
+        // Only transforms and bounding volumes are rounded
+        assert_eq!(json["geometricError"], 1.23456789);
+        assert_eq!(root["extras"]["value"], 1.23456789);
+    }
+
+    #[test]
+    fn write_omits_none() {
+        let tileset: Tileset = serde_json::from_value(serde_json::json!({
+            "asset": {"version": "1.0"},
+            "geometricError": 1,
+            "root": {
+                "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                "geometricError": 0,
+                "content": {"uri": "a.b3dm"},
+                "expire": {"date": "2021-05-21T08:30:00Z"},
+                "implicitTiling": {
+                    "subdivisionScheme": "OCTREE",
+                    "subtreeLevels": 2,
+                    "availableLevels": 2,
+                    "subtrees": {"uri": "{level}/{x}/{y}/{z}.subtree"}
+                }
+            }
+        }))
+        .unwrap();
+        let json = write(&tileset, &WriteOptions::default());
+        assert!(!json.contains("null"), "{}", json);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::json!({
+                "asset": {"version": "1.0"},
+                "geometricError": 1,
+                "root": {
+                    "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                    "geometricError": 0,
+                    "content": {"uri": "a.b3dm"},
+                    "expire": {"date": "2021-05-21T08:30:00Z"},
+                    "implicitTiling": {
+                        "subdivisionScheme": "OCTREE",
+                        "subtreeLevels": 2,
+                        "availableLevels": 2,
+                        "subtrees": {"uri": "{level}/{x}/{y}/{z}.subtree"}
+                    }
+                }
+            })
+        );
+    }
 }
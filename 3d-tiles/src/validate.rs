@@ -0,0 +1,820 @@
+//! Validation of tilesets and their tile contents.
+//!
+//! Issues are reported with the URI of the tileset or tile content and a JSON pointer into
+//! the tileset, e.g. `/root/children/0/geometricError`.
+
+use crate::b3dm::B3dm;
+use crate::bounding_volume::Volume;
+use crate::cmpt::Cmpt;
+use crate::content::ContentType;
+use crate::error::Error;
+use crate::i3dm::I3dm;
+use crate::implicit::{ImplicitTree, SubtreeCache};
+use crate::limits::Limits;
+use crate::loader::{is_tileset_uri, Source, TilesetLoader, SUPPORTED_EXTENSIONS};
+use crate::pnts::Pnts;
+use crate::tileset::{Tile, TileContent, TileNode, Tileset};
+use serde_derive::Serialize;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+/// Supported values of `asset.version`.
+const VERSIONS: &[&str] = &["0.0", "1.0", "1.1"];
+
+/// Relative tolerance for bounding volume containment.
+const TOLERANCE: f64 = 1e-6;
+
+/// Severity of an [`Issue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The tileset violates the specification.
+    Error,
+    /// The tileset is valid, but clients may not handle it as intended.
+    Warning,
+}
+
+/// A problem found in a tileset or tile content.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// URI of the tileset or tile content.
+    pub uri: String,
+    /// JSON pointer to the offending value in the tileset.
+    pub path: String,
+    pub message: String,
+}
+
+/// Result of a validation.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    /// Issues with severity [`Severity::Error`].
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    /// Issues with severity [`Severity::Warning`].
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    /// Whether no errors were found.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+}
+
+/// Options for [`validate`].
+#[derive(Debug, Clone)]
+pub struct ValidateOptions {
+    /// Read and validate tile contents.
+    pub contents: bool,
+    /// Limits for reading tile contents.
+    pub limits: Limits,
+}
+
+impl Default for ValidateOptions {
+    fn default() -> Self {
+        ValidateOptions {
+            contents: true,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Validate the tileset at `uri`, including external tilesets and, if enabled in `options`,
+/// the binary layout of tile contents.
+pub fn validate<S: Source>(
+    loader: &TilesetLoader<S>,
+    uri: &str,
+    options: &ValidateOptions,
+) -> Report {
+    let mut report = Report::default();
+    let mut chain = Vec::new();
+    validate_uri(loader, uri, "", options, &mut chain, &mut report.issues);
+    report
+}
+
+/// Validate a single tileset without reading tile contents or external tilesets. `uri`
+/// identifies the tileset in the issues.
+pub fn validate_tileset(tileset: &Tileset, uri: &str) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut issue = |severity, path: &str, message: String| {
+        issues.push(Issue {
+            severity,
+            uri: uri.to_string(),
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    let version = &tileset.asset.version;
+    if version.is_empty() {
+        issue(
+            Severity::Error,
+            "/asset/version",
+            "asset.version must be set".to_string(),
+        );
+    } else if !VERSIONS.contains(&version.as_str()) {
+        issue(
+            Severity::Warning,
+            "/asset/version",
+            format!("unknown 3D Tiles version `{}`", version),
+        );
+    }
+    if tileset.geometric_error < 0.0 {
+        issue(
+            Severity::Error,
+            "/geometricError",
+            "geometricError must not be negative".to_string(),
+        );
+    }
+    let used = tileset.extensions_used.as_deref().unwrap_or(&[]);
+    for (i, name) in tileset.extensions_required.iter().flatten().enumerate() {
+        if !used.contains(name) {
+            issue(
+                Severity::Error,
+                &format!("/extensionsRequired/{}", i),
+                format!(
+                    "extension `{}` is required, but not in extensionsUsed",
+                    name
+                ),
+            );
+        }
+    }
+    if tileset.root.refine.is_none() {
+        issue(
+            Severity::Error,
+            "/root/refine",
+            "refine must be defined on the root tile".to_string(),
+        );
+    }
+    if tileset.root.geometric_error > tileset.geometric_error {
+        issue(
+            Severity::Warning,
+            "/root/geometricError",
+            format!(
+                "geometricError {} exceeds the geometricError {} of the tileset",
+                tileset.root.geometric_error, tileset.geometric_error
+            ),
+        );
+    }
+    for node in tileset.root.depth_first() {
+        validate_tile(&node, uri, &mut issues);
+    }
+    issues
+}
+
+/// JSON pointer to the tile at `path`.
+fn tile_pointer(path: &[usize]) -> String {
+    let mut pointer = "/root".to_string();
+    for index in path {
+        pointer.push_str(&format!("/children/{}", index));
+    }
+    pointer
+}
+
+/// Issues of a volume itself, e.g. a region with latitudes out of range.
+fn volume_errors(volume: &Volume) -> Vec<String> {
+    let mut errors = Vec::new();
+    match volume {
+        Volume::Region(r) => {
+            for (name, value) in &[("west", r.west), ("east", r.east)] {
+                if !(-PI..=PI).contains(value) {
+                    errors.push(format!("{} {} is not within [-π, π]", name, value));
+                }
+            }
+            for (name, value) in &[("south", r.south), ("north", r.north)] {
+                if !(-FRAC_PI_2..=FRAC_PI_2).contains(value) {
+                    errors.push(format!("{} {} is not within [-π/2, π/2]", name, value));
+                }
+            }
+            if r.south > r.north {
+                errors.push(format!("south {} exceeds north {}", r.south, r.north));
+            }
+            if r.minimum_height > r.maximum_height {
+                errors.push(format!(
+                    "minimum height {} exceeds maximum height {}",
+                    r.minimum_height, r.maximum_height
+                ));
+            }
+        }
+        Volume::Sphere(s) if s.radius < 0.0 => {
+            errors.push(format!("radius {} is negative", s.radius));
+        }
+        _ => {}
+    }
+    errors
+}
+
+fn validate_tile(node: &TileNode, uri: &str, issues: &mut Vec<Issue>) {
+    let tile = node.tile;
+    let pointer = tile_pointer(&node.path);
+    let mut issue = |severity, path: String, message: String| {
+        issues.push(Issue {
+            severity,
+            uri: uri.to_string(),
+            path,
+            message,
+        })
+    };
+
+    if tile.geometric_error < 0.0 {
+        issue(
+            Severity::Error,
+            format!("{}/geometricError", pointer),
+            "geometricError must not be negative".to_string(),
+        );
+    }
+    if tile.content.is_some() && tile.contents.is_some() {
+        issue(
+            Severity::Error,
+            format!("{}/contents", pointer),
+            "a tile must not define both content and contents".to_string(),
+        );
+    }
+    let mut volumes = vec![("boundingVolume".to_string(), &tile.bounding_volume)];
+    volumes.extend(
+        tile.viewer_request_volume
+            .as_ref()
+            .map(|volume| ("viewerRequestVolume".to_string(), volume)),
+    );
+    let content_volumes: Vec<_> = content_pointers(tile)
+        .filter_map(|(name, content)| {
+            content
+                .bounding_volume
+                .as_ref()
+                .map(|volume| (format!("{}/boundingVolume", name), volume))
+        })
+        .collect();
+    volumes.extend(content_volumes.iter().cloned());
+    for (name, volume) in volumes {
+        for message in volume_errors(&volume.volume) {
+            issue(Severity::Error, format!("{}/{}", pointer, name), message);
+        }
+    }
+
+    if tile.implicit_tiling.is_some() {
+        if let Err(e) = ImplicitTree::new(tile) {
+            issue(
+                Severity::Error,
+                format!("{}/implicitTiling", pointer),
+                e.to_string(),
+            );
+        }
+        if tile
+            .children
+            .as_ref()
+            .map_or(false, |children| !children.is_empty())
+        {
+            issue(
+                Severity::Error,
+                format!("{}/children", pointer),
+                "a tile with implicit tiling must not have children".to_string(),
+            );
+        }
+    }
+
+    let volume = tile.bounding_volume.volume.transform(&node.transform);
+    let tolerance = TOLERANCE * volume.to_sphere().radius;
+    for (name, content_volume) in content_volumes {
+        let content_volume = content_volume.volume.transform(&node.transform);
+        if !volume.contains_volume(&content_volume, tolerance) {
+            issue(
+                Severity::Error,
+                format!("{}/{}", pointer, name),
+                "content bounding volume is not inside the tile bounding volume".to_string(),
+            );
+        }
+    }
+    for child in node.children() {
+        let child_pointer = tile_pointer(&child.path);
+        if child.tile.geometric_error > tile.geometric_error {
+            issue(
+                Severity::Error,
+                format!("{}/geometricError", child_pointer),
+                format!(
+                    "geometricError {} exceeds the geometricError {} of the parent",
+                    child.tile.geometric_error, tile.geometric_error
+                ),
+            );
+        }
+        let child_volume = child
+            .tile
+            .bounding_volume
+            .volume
+            .transform(&child.transform);
+        if !volume.contains_volume(&child_volume, tolerance) {
+            issue(
+                Severity::Error,
+                format!("{}/boundingVolume", child_pointer),
+                "bounding volume is not inside the bounding volume of the parent".to_string(),
+            );
+        }
+    }
+}
+
+/// Validate the tileset at `uri`, referenced from `pointer` of the last tileset in `chain`.
+fn validate_uri<S: Source>(
+    loader: &TilesetLoader<S>,
+    uri: &str,
+    pointer: &str,
+    options: &ValidateOptions,
+    chain: &mut Vec<String>,
+    issues: &mut Vec<Issue>,
+) {
+    // Issues with loading a tileset are reported at the referencing tile, if any.
+    let parent = chain.last().map_or(uri, String::as_str).to_string();
+    let error = |message: String| Issue {
+        severity: Severity::Error,
+        uri: parent.clone(),
+        path: pointer.to_string(),
+        message,
+    };
+    if chain.iter().any(|visited| visited == uri) {
+        issues.push(error(format!(
+            "external tileset cycle: {} -> {}",
+            chain.join(" -> "),
+            uri
+        )));
+        return;
+    }
+    let tileset = match loader.read_tileset_unchecked(uri) {
+        Ok(tileset) => tileset,
+        Err(e) => {
+            issues.push(error(e.to_string()));
+            return;
+        }
+    };
+    issues.extend(validate_tileset(&tileset, uri));
+    for (i, name) in tileset.extensions_required.iter().flatten().enumerate() {
+        if !SUPPORTED_EXTENSIONS.contains(&name.as_str()) {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                uri: uri.to_string(),
+                path: format!("/extensionsRequired/{}", i),
+                message: format!("extension `{}` is not supported", name),
+            });
+        }
+    }
+
+    chain.push(uri.to_string());
+    for node in tileset.root.depth_first() {
+        let pointer = tile_pointer(&node.path);
+        if node.tile.implicit_tiling.is_some() {
+            // Content URIs are templates, invalid implicit tiling is reported by
+            // `validate_tile`
+            if let Ok(tree) = ImplicitTree::new(node.tile) {
+                validate_implicit(loader, tree, &pointer, options, chain, issues);
+            }
+            continue;
+        }
+        for (name, content) in content_pointers(node.tile) {
+            let pointer = format!("{}/{}/uri", pointer, name);
+            validate_content_uri(loader, &content.uri, &pointer, options, chain, issues);
+        }
+    }
+    chain.pop();
+}
+
+/// Validate the subtrees and contents of the implicit tiles in `tree`, whose root tile is at
+/// `pointer` of the last tileset in `chain`.
+fn validate_implicit<S: Source>(
+    loader: &TilesetLoader<S>,
+    tree: ImplicitTree,
+    pointer: &str,
+    options: &ValidateOptions,
+    chain: &mut Vec<String>,
+    issues: &mut Vec<Issue>,
+) {
+    let mut subtrees = SubtreeCache::new(loader, tree);
+    let content_pointers: Vec<_> = content_pointers(tree.root_tile())
+        .map(|(name, _)| format!("{}/{}/uri", pointer, name))
+        .collect();
+    let mut stack = Vec::new();
+    let mut next = tree.root(&mut subtrees).map(|root| vec![root]);
+    loop {
+        match next {
+            Ok(tiles) => stack.extend(tiles),
+            Err(e) => {
+                // Errors of subtrees are reported once, the referenced tiles are skipped
+                issues.push(Issue {
+                    severity: Severity::Error,
+                    uri: e.path().map_or_else(
+                        || chain.last().cloned().unwrap_or_default(),
+                        |path| path.to_string_lossy().into_owned(),
+                    ),
+                    path: format!("{}/implicitTiling/subtrees/uri", pointer),
+                    message: e.to_string(),
+                });
+            }
+        }
+        let tile = match stack.pop() {
+            Some(tile) => tile,
+            None => break,
+        };
+        for (content_uri, content_pointer) in tile.content_uris.iter().zip(&content_pointers) {
+            if let Some(content_uri) = content_uri {
+                validate_content_uri(loader, content_uri, content_pointer, options, chain, issues);
+            }
+        }
+        next = tree.children(&tile, &mut subtrees);
+    }
+}
+
+/// Contents of `tile` with their JSON pointers relative to the tile.
+fn content_pointers(tile: &Tile) -> impl Iterator<Item = (String, &TileContent)> {
+    let content = tile
+        .content
+        .iter()
+        .map(|content| ("content".to_string(), content));
+    let contents = tile
+        .contents
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, content)| (format!("contents/{}", i), content));
+    content.chain(contents)
+}
+
+/// Validate the content at `uri`, referenced from `pointer` of the last tileset in `chain`.
+///
+/// External tilesets are recognized by their content if contents are validated, otherwise
+/// by the heuristic of [`is_tileset_uri`].
+fn validate_content_uri<S: Source>(
+    loader: &TilesetLoader<S>,
+    uri: &str,
+    pointer: &str,
+    options: &ValidateOptions,
+    chain: &mut Vec<String>,
+    issues: &mut Vec<Issue>,
+) {
+    if !options.contents {
+        if is_tileset_uri(uri) {
+            validate_uri(loader, uri, pointer, options, chain, issues);
+        }
+        return;
+    }
+    let content_issue = |severity, message| Issue {
+        severity,
+        uri: uri.to_string(),
+        path: pointer.to_string(),
+        message,
+    };
+    match loader.source().read(uri) {
+        Ok(data) => match ContentType::detect(&data) {
+            ContentType::Tileset => validate_uri(loader, uri, pointer, options, chain, issues),
+            // Report why a presumed tileset can't be parsed
+            ContentType::Unknown if is_tileset_uri(uri) => {
+                validate_uri(loader, uri, pointer, options, chain, issues)
+            }
+            _ => {
+                for (severity, message) in validate_content(&data, &options.limits) {
+                    issues.push(content_issue(severity, message));
+                }
+            }
+        },
+        Err(_) if is_tileset_uri(uri) => validate_uri(loader, uri, pointer, options, chain, issues),
+        Err(e) => issues.push(content_issue(Severity::Error, e.to_string())),
+    }
+}
+
+/// Read tile content and check its binary layout.
+///
+/// Sections which are not padded to 8 bytes are warnings, since older tools commonly wrote
+/// tiles with 4-byte alignment.
+fn validate_content(data: &[u8], limits: &Limits) -> Vec<(Severity, String)> {
+    let errors = match ContentType::detect(data) {
+        ContentType::B3dm => {
+            B3dm::from_reader_with_limits(data, limits).map(|tile| tile.validate())
+        }
+        ContentType::I3dm => {
+            I3dm::from_reader_with_limits(data, limits).map(|tile| tile.validate())
+        }
+        ContentType::Pnts => {
+            Pnts::from_reader_with_limits(data, limits).map(|tile| tile.validate())
+        }
+        ContentType::Cmpt => {
+            Cmpt::from_reader_with_limits(data, limits).map(|tile| tile.validate())
+        }
+        ContentType::Glb | ContentType::Gltf | ContentType::Tileset => Ok(Vec::new()),
+        content_type @ ContentType::Subtree | content_type @ ContentType::Unknown => {
+            let message = format!("unsupported content type {:?}", content_type);
+            return vec![(Severity::Warning, message)];
+        }
+    };
+    match errors {
+        Ok(errors) => errors
+            .into_iter()
+            .map(|e| (severity(&e), e.to_string()))
+            .collect(),
+        Err(e) => vec![(Severity::Error, e.to_string())],
+    }
+}
+
+fn severity(error: &Error) -> Severity {
+    match error {
+        Error::Padding { .. } => Severity::Warning,
+        Error::InnerTile { source, .. } => severity(source),
+        _ => Severity::Error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::FileSource;
+    use crate::sample_path;
+    use crate::test_util::{tileset, MemorySource};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Issues as `(severity, path)` pairs.
+    fn paths(issues: &[Issue]) -> Vec<(Severity, &str)> {
+        let mut paths: Vec<_> = issues
+            .iter()
+            .map(|issue| (issue.severity, issue.path.as_str()))
+            .collect();
+        paths.sort_by(|a, b| a.1.cmp(b.1));
+        paths
+    }
+
+    #[test]
+    fn valid_samples() {
+        // TilesetWithDiscreteLOD lacks dragon_high.b3dm
+        for name in &[
+            "TilesetWithExpiration",
+            "TilesetWithRequestVolume",
+            "TilesetWithTreeBillboards",
+        ] {
+            let uri = sample_path(&format!("{}/tileset.json", name));
+            let report = validate(
+                &TilesetLoader::new(FileSource),
+                &uri,
+                &ValidateOptions::default(),
+            );
+            assert!(report.is_valid(), "{}: {:?}", name, report.issues);
+        }
+    }
+
+    #[test]
+    fn tileset_errors() {
+        let tileset: Tileset = serde_json::from_value(json!({
+            "asset": {"version": ""},
+            "geometricError": -1,
+            "extensionsRequired": ["EXT_a"],
+            "root": {
+                "boundingVolume": {"region": [-4, 0, 1, 2, 10, 0]},
+                "geometricError": 5,
+                "content": {"uri": "a.b3dm"},
+                "contents": [{"uri": "b.b3dm", "boundingVolume": {"sphere": [0, 0, 0, -1]}}],
+                "children": [{
+                    "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                    "geometricError": 6,
+                }],
+            },
+        }))
+        .unwrap();
+        let issues = validate_tileset(&tileset, "tileset.json");
+        assert!(issues.iter().all(|issue| issue.uri == "tileset.json"));
+        assert_eq!(
+            paths(&issues),
+            vec![
+                (Severity::Error, "/asset/version"),
+                (Severity::Error, "/extensionsRequired/0"),
+                (Severity::Error, "/geometricError"),
+                // West, north and heights of the region
+                (Severity::Error, "/root/boundingVolume"),
+                (Severity::Error, "/root/boundingVolume"),
+                (Severity::Error, "/root/boundingVolume"),
+                (Severity::Error, "/root/children/0/boundingVolume"),
+                (Severity::Error, "/root/children/0/geometricError"),
+                (Severity::Error, "/root/contents"),
+                // Negative radius and not inside the tile
+                (Severity::Error, "/root/contents/0/boundingVolume"),
+                (Severity::Error, "/root/contents/0/boundingVolume"),
+                (Severity::Warning, "/root/geometricError"),
+                (Severity::Error, "/root/refine"),
+            ]
+        );
+    }
+
+    #[test]
+    fn content_volumes() {
+        let tileset: Tileset = serde_json::from_value(json!({
+            "asset": {"version": "2.0"},
+            "geometricError": 10,
+            "root": {
+                "boundingVolume": {"box": [0, 0, 0, 10, 0, 0, 0, 10, 0, 0, 0, 10]},
+                "geometricError": 5,
+                "refine": "REPLACE",
+                "contents": [
+                    {"uri": "a.b3dm", "boundingVolume": {"sphere": [5, 5, 5, 2]}},
+                    {"uri": "b.b3dm", "boundingVolume": {"sphere": [8, 8, 8, 5]}},
+                ],
+                "children": [{
+                    "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                    "geometricError": 0,
+                    "implicitTiling": {
+                        "subdivisionScheme": "QUADTREE",
+                        "subtreeLevels": 0,
+                        "availableLevels": 1,
+                        "subtrees": {"uri": "{level}.subtree"},
+                    },
+                    "children": [{
+                        "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                        "geometricError": 0,
+                    }],
+                }],
+            },
+        }))
+        .unwrap();
+        let issues = validate_tileset(&tileset, "tileset.json");
+        assert_eq!(
+            paths(&issues),
+            vec![
+                (Severity::Warning, "/asset/version"),
+                (Severity::Error, "/root/children/0/children"),
+                (Severity::Error, "/root/children/0/implicitTiling"),
+                (Severity::Error, "/root/contents/1/boundingVolume"),
+            ]
+        );
+    }
+
+    #[test]
+    fn external_tilesets_and_contents() {
+        let b3dm = crate::sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let child = |uri: &str| {
+            json!({
+                "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                "geometricError": 1,
+                "content": {"uri": uri},
+            })
+        };
+        let mut resources = HashMap::new();
+        resources.insert(
+            "tileset.json".to_string(),
+            tileset("a/external.json").to_string().into_bytes(),
+        );
+        resources.insert(
+            "a/external.json".to_string(),
+            json!({
+                "asset": {"version": "1.0"},
+                "geometricError": 10,
+                "extensionsUsed": ["EXT_unknown"],
+                "extensionsRequired": ["EXT_unknown"],
+                "root": {
+                    "boundingVolume": {"sphere": [0, 0, 0, 1]},
+                    "geometricError": 10,
+                    "refine": "ADD",
+                    "children": [
+                        child("ok.b3dm"),
+                        child("truncated.b3dm"),
+                        child("missing.b3dm"),
+                        child("unknown.bin"),
+                        child("../tileset.json"),
+                    ],
+                },
+            })
+            .to_string()
+            .into_bytes(),
+        );
+        resources.insert("a/ok.b3dm".to_string(), b3dm.clone());
+        resources.insert("a/truncated.b3dm".to_string(), b3dm[..100].to_vec());
+        resources.insert("a/unknown.bin".to_string(), b"data".to_vec());
+        let loader = TilesetLoader::new(MemorySource(resources));
+
+        let report = validate(&loader, "tileset.json", &ValidateOptions::default());
+        let mut issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.severity, issue.uri.as_str(), issue.path.as_str()))
+            .collect();
+        issues.sort_by(|a, b| a.2.cmp(b.2));
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    Severity::Warning,
+                    "a/external.json",
+                    "/extensionsRequired/0"
+                ),
+                (
+                    Severity::Error,
+                    "a/truncated.b3dm",
+                    "/root/children/1/content/uri"
+                ),
+                (
+                    Severity::Error,
+                    "a/missing.b3dm",
+                    "/root/children/2/content/uri"
+                ),
+                (
+                    Severity::Warning,
+                    "a/unknown.bin",
+                    "/root/children/3/content/uri"
+                ),
+                (
+                    Severity::Error,
+                    "a/external.json",
+                    "/root/children/4/content/uri"
+                ),
+            ]
+        );
+        let cycle = &report
+            .issues
+            .iter()
+            .find(|issue| issue.path.ends_with("4/content/uri"))
+            .unwrap();
+        assert_eq!(
+            cycle.message,
+            "external tileset cycle: tileset.json -> a/external.json -> tileset.json"
+        );
+        assert!(!report.is_valid());
+        assert_eq!(report.warnings().count(), 2);
+
+        // Without contents, only the tilesets are read
+        let options = ValidateOptions {
+            contents: false,
+            ..Default::default()
+        };
+        let report = validate(&loader, "tileset.json", &options);
+        assert_eq!(report.errors().count(), 1);
+        assert_eq!(report.warnings().count(), 1);
+    }
+
+    #[test]
+    fn content_limits() {
+        let b3dm = crate::sample("TilesetWithDiscreteLOD/dragon_low.b3dm");
+        let limits = Limits {
+            max_byte_length: 1024,
+            ..Default::default()
+        };
+        let issues = validate_content(&b3dm, &limits);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].0, Severity::Error);
+        assert!(validate_content(&b3dm, &Limits::default()).is_empty());
+    }
+
+    #[test]
+    fn detect_external_tilesets() {
+        let mut resources = HashMap::new();
+        resources.insert(
+            "tileset.json".to_string(),
+            tileset("model.json").to_string().into_bytes(),
+        );
+        resources.insert(
+            "model.json".to_string(),
+            br#"{"asset": {"version": "2.0"}}"#.to_vec(),
+        );
+        resources.insert(
+            "external".to_string(),
+            tileset("a.b3dm").to_string().into_bytes(),
+        );
+        let loader = TilesetLoader::new(MemorySource(resources.clone()));
+        // glTF JSON isn't mistaken for a tileset
+        let report = validate(&loader, "tileset.json", &ValidateOptions::default());
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+        // A tileset without extension is validated, including the missing content
+        resources.insert(
+            "tileset.json".to_string(),
+            tileset("external").to_string().into_bytes(),
+        );
+        let loader = TilesetLoader::new(MemorySource(resources));
+        let report = validate(&loader, "tileset.json", &ValidateOptions::default());
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.severity, issue.uri.as_str(), issue.path.as_str()))
+            .collect();
+        assert_eq!(issues, [(Severity::Error, "a.b3dm", "/root/content/uri")]);
+    }
+
+    #[test]
+    fn invalid_implicit_tiling() {
+        let mut json = tileset("content/{level}/{x}/{y}.b3dm");
+        json["root"]["implicitTiling"] = json!({
+            "subdivisionScheme": "QUADTREE",
+            "subtreeLevels": 0,
+            "availableLevels": 1,
+            "subtrees": {"uri": "{level}.subtree"},
+        });
+        let mut resources = HashMap::new();
+        resources.insert("tileset.json".to_string(), json.to_string().into_bytes());
+        let loader = TilesetLoader::new(MemorySource(resources));
+        // The content URI template isn't read as a file
+        let report = validate(&loader, "tileset.json", &ValidateOptions::default());
+        assert_eq!(
+            paths(&report.issues),
+            vec![(Severity::Error, "/root/implicitTiling")]
+        );
+    }
+}